@@ -6,13 +6,46 @@ use rsa::pkcs1::EncodeRsaPrivateKey;
 
 const RSA_KEY_SIZE: usize = 2048;
 
+const DEFAULT_EDITION_SIZE: usize = 10;
+
+// Enough for a dozen or so subjects at once, and then four works a second
+const DEFAULT_RATE_BURST: usize = 16;
+const DEFAULT_RATE_REFILL: usize = 1;
+// In milliseconds
+const DEFAULT_RATE_PERIOD: usize = 250;
+
 fn main() {
     let priv_key = rsa::RsaPrivateKey::new(&mut rsa::rand_core::OsRng, RSA_KEY_SIZE).unwrap();
     let priv_key_pem = priv_key.to_pkcs1_pem(rsa::pkcs1::LineEnding::LF).unwrap();
     let out_dir = env::var("OUT_DIR").unwrap();
     let out_path = PathBuf::from(&out_dir).join("priv.pem");
-    fs::write(&out_path, &priv_key_pem).unwrap();
+    fs::write(out_path, &priv_key_pem).unwrap();
+
+    let edition_size = positive_var("BANSCII_EDITION_SIZE", DEFAULT_EDITION_SIZE);
+    let out_path = PathBuf::from(&out_dir).join("edition_size.rs");
+    fs::write(out_path, edition_size.to_string()).unwrap();
+
+    let rate_limiter = format!(
+        "RateLimiter::new({}, {}, Duration::from_millis({}))",
+        positive_var("BANSCII_RATE_BURST", DEFAULT_RATE_BURST),
+        positive_var("BANSCII_RATE_REFILL", DEFAULT_RATE_REFILL),
+        positive_var("BANSCII_RATE_PERIOD", DEFAULT_RATE_PERIOD),
+    );
+    let out_path = PathBuf::from(&out_dir).join("rate_limiter.rs");
+    fs::write(out_path, rate_limiter).unwrap();
 
     // No external dependencies
     println!("cargo:rerun-if-changed=build.rs");
 }
+
+fn positive_var(name: &str, default: usize) -> usize {
+    println!("cargo:rerun-if-env-changed={}", name);
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<usize>()
+            .ok()
+            .filter(|value| *value > 0)
+            .unwrap_or_else(|| panic!("{} must be a positive number", name)),
+        Err(_) => default,
+    }
+}
@@ -1,10 +1,152 @@
 #![no_std]
 
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use sha2::{Digest, Sha256};
 use zerocopy::{AsBytes, FromBytes};
 
+/// How the artist signs its works, for those who would check them.
+pub const SIGNATURE_ALGORITHM: &str = "RSASSA-PKCS1-v1_5 SHA-256";
+
+/// Room the artist needs in its output region beyond the masterpiece itself.
+pub const MAX_SIGNATURE_SIZE: usize = 0x200;
+
+pub const CHAIN_HASH_SIZE: usize = 32;
+
+/// A SHA-256 digest, linking an entry of the artist's audit log to the one
+/// before it.
+pub type ChainHash = [u8; CHAIN_HASH_SIZE];
+
+pub const PROVENANCE_SIZE: usize = 24 + CHAIN_HASH_SIZE;
+
+/// What the artist appends to a masterpiece before signing it, so that the
+/// signature vouches for the work's serial number, edition, and timestamp
+/// too, each a big-endian `u64`, and for the head of the audit log before it.
+pub fn provenance(
+    serial: usize,
+    edition: usize,
+    timestamp: u64,
+    chain_head: &ChainHash,
+) -> [u8; PROVENANCE_SIZE] {
+    let mut out = [0; PROVENANCE_SIZE];
+    out[..8].copy_from_slice(&(serial as u64).to_be_bytes());
+    out[8..16].copy_from_slice(&(edition as u64).to_be_bytes());
+    out[16..24].copy_from_slice(&timestamp.to_be_bytes());
+    out[24..].copy_from_slice(chain_head);
+    out
+}
+
+pub const AUDIT_ENTRY_SIZE: usize = 8 + 2 * CHAIN_HASH_SIZE;
+
+/// An entry in the artist's audit log, one for each work it has signed. The
+/// head of the log is the hash of its last entry, or all zeros before the
+/// first work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub serial: usize,
+    /// SHA-256 of everything the artist signed: the masterpiece followed by
+    /// its provenance.
+    pub envelope_hash: ChainHash,
+    /// The head of the log before this entry.
+    pub previous: ChainHash,
+}
+
+impl AuditEntry {
+    pub fn new(serial: usize, envelope: &[u8], previous: ChainHash) -> Self {
+        Self {
+            serial,
+            envelope_hash: Sha256::digest(envelope).into(),
+            previous,
+        }
+    }
+
+    /// The serial number as a big-endian `u64`, then the two hashes.
+    pub fn to_bytes(&self) -> [u8; AUDIT_ENTRY_SIZE] {
+        let mut out = [0; AUDIT_ENTRY_SIZE];
+        out[..8].copy_from_slice(&(self.serial as u64).to_be_bytes());
+        out[8..8 + CHAIN_HASH_SIZE].copy_from_slice(&self.envelope_hash);
+        out[8 + CHAIN_HASH_SIZE..].copy_from_slice(&self.previous);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8; AUDIT_ENTRY_SIZE]) -> Self {
+        Self {
+            serial: u64::from_be_bytes(bytes[..8].try_into().unwrap()) as usize,
+            envelope_hash: bytes[8..8 + CHAIN_HASH_SIZE].try_into().unwrap(),
+            previous: bytes[8 + CHAIN_HASH_SIZE..].try_into().unwrap(),
+        }
+    }
+
+    /// The head of the log once this entry is added.
+    pub fn hash(&self) -> ChainHash {
+        Sha256::digest(self.to_bytes()).into()
+    }
+}
+
+/// Follows the audit log, or the works it was built from, one entry at a
+/// time, checking that each comes straight after the last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chain {
+    /// The serial number of the last entry followed.
+    pub serial: usize,
+    pub head: ChainHash,
+}
+
+impl Chain {
+    /// Before the artist's first work.
+    pub const START: Self = Self {
+        serial: 0,
+        head: [0; CHAIN_HASH_SIZE],
+    };
+
+    /// Just before an entry, for following the log from part way through.
+    pub fn before(entry: &AuditEntry) -> Option<Self> {
+        Some(Self {
+            serial: entry.serial.checked_sub(1)?,
+            head: entry.previous,
+        })
+    }
+
+    pub fn extend(&mut self, entry: &AuditEntry) -> Result<(), ChainError> {
+        if Some(entry.serial) != self.serial.checked_add(1) {
+            return Err(ChainError::Gap {
+                after: self.serial,
+                next: entry.serial,
+            });
+        }
+        if entry.previous != self.head {
+            return Err(ChainError::Broken {
+                serial: entry.serial,
+            });
+        }
+        self.serial = entry.serial;
+        self.head = entry.hash();
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainError {
+    /// Entries are missing between these two serial numbers, or they're out
+    /// of order.
+    Gap { after: usize, next: usize },
+    /// The entry doesn't follow on from the one before it.
+    Broken { serial: usize },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[cfg_attr(target_pointer_width = "32", repr(u32))]
+#[cfg_attr(target_pointer_width = "64", repr(u64))]
+pub enum RequestTag {
+    Complete,
+    GetPublicKey,
+    Verify,
+    GetStats,
+    GetAuditLog,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
 #[repr(C)]
-pub struct Request {
+pub struct CompleteRequest {
     pub height: usize,
     pub width: usize,
     pub draft_start: usize,
@@ -13,11 +155,134 @@ pub struct Request {
 
 #[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
 #[repr(C)]
-pub struct Response {
+pub struct CompleteResponse {
     pub height: usize,
     pub width: usize,
     pub masterpiece_start: usize,
     pub masterpiece_size: usize,
     pub signature_start: usize,
     pub signature_size: usize,
+    /// How many works the artist has completed, this one included.
+    pub serial: usize,
+    /// How many works the artist has completed from this draft, this one
+    /// included.
+    pub edition: usize,
+    /// How many works the artist will complete from any one draft.
+    pub edition_size: usize,
+    /// Seconds since the Unix epoch when the artist signed the work, by the
+    /// timer's real-time clock.
+    pub timestamp: u64,
+    /// The head of the audit log before this work, which is signed along with
+    /// it.
+    pub chain_head: ChainHash,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct GetPublicKeyResponse {
+    pub pem_start: usize,
+    pub pem_size: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct VerifyRequest {
+    pub masterpiece_start: usize,
+    pub masterpiece_size: usize,
+    pub signature_start: usize,
+    pub signature_size: usize,
+    pub serial: usize,
+    pub edition: usize,
+    pub timestamp: u64,
+    pub chain_head: ChainHash,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct VerifyResponse {
+    pub valid: u8,
+}
+
+/// Counts since the artist last started, unlike serial numbers and editions,
+/// which are kept in storage.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct GetStatsResponse {
+    pub completed: usize,
+    pub verified: usize,
+    /// Requests rejected for any reason, those rate limited included.
+    pub rejected: usize,
+    pub rate_limited: usize,
+    /// Tokens left in the rate limiter's bucket, out of `burst`.
+    pub tokens: usize,
+    pub burst: usize,
+}
+
+/// Asks for the entries of the audit log which follow the one with serial
+/// number `after`, or all of them from the start for zero.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct GetAuditLogRequest {
+    pub after: usize,
+}
+
+/// `count` entries, each as [`AuditEntry::to_bytes`], one after the other
+/// from `entries_start` in the artist's outgoing region. `more` is non-zero
+/// if there are entries after these. `first` is the serial number of the
+/// oldest entry the artist still keeps, which the entries start from if the
+/// one after `after` is older.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct GetAuditLogResponse {
+    pub entries_start: usize,
+    pub count: usize,
+    pub more: usize,
+    pub first: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum RejectReason {
+    /// The request was malformed, or pointed outside of the artist's regions.
+    BadRequest,
+    /// Every edition of the draft has been completed.
+    SoldOut,
+    /// The artist can't keep count of the editions of any more drafts.
+    TooManySubjects,
+    /// The artist couldn't record the work's serial number or edition, so
+    /// didn't complete it.
+    Unrecorded,
+    /// The artist's records in storage aren't as it left them, so it won't
+    /// sign anything until they are.
+    Tampered,
+    /// The artist's records in storage are older than the last it saw, so it
+    /// won't sign anything until the latest are put back.
+    RolledBack,
+    /// The request came too soon after too many others. It's worth trying
+    /// again after [`RejectResponse::retry_after`].
+    RateLimited,
+    /// The artist couldn't tell the time to sign the work with, so didn't
+    /// complete it.
+    NoTime,
+}
+
+/// Sent along with an error status.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct RejectResponse {
+    pub reason: u8,
+    _padding: [u8; 7],
+    /// For [`RejectReason::RateLimited`], how many milliseconds until the
+    /// artist will take this one, at most, and zero otherwise.
+    pub retry_after: usize,
+}
+
+impl RejectResponse {
+    pub fn new(reason: RejectReason, retry_after: usize) -> Self {
+        Self {
+            reason: reason.into(),
+            _padding: [0; 7],
+            retry_after,
+        }
+    }
 }
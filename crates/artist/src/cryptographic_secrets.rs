@@ -1,11 +1,21 @@
+use alloc::string::String;
+
+use hmac::{Hmac, Mac};
 use rsa::pkcs1::DecodeRsaPrivateKey;
-use rsa::pkcs1v15::{Signature, SigningKey};
+use rsa::pkcs1v15::{Signature, SigningKey, VerifyingKey};
+use rsa::pkcs8::{EncodePublicKey, LineEnding};
 use rsa::sha2::Sha256;
-use rsa::signature::Signer;
+use rsa::signature::{Signer, Verifier};
 use rsa::RsaPrivateKey;
 
 const PRIV_KEY_PEM: &str = include_str!(concat!(env!("OUT_DIR"), "/priv.pem"));
 
+const SEALING_KEY_LABEL: &[u8] = b"banscii artist state sealing key";
+
+pub(crate) const MAC_SIZE: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
 fn get_priv_key() -> RsaPrivateKey {
     RsaPrivateKey::from_pkcs1_pem(PRIV_KEY_PEM).unwrap()
 }
@@ -14,3 +24,47 @@ pub(crate) fn sign(data: &[u8]) -> Signature {
     let signing_key = SigningKey::<Sha256>::new_with_prefix(get_priv_key());
     signing_key.sign(data)
 }
+
+pub(crate) fn verify(data: &[u8], signature: &[u8]) -> bool {
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(get_priv_key().to_public_key());
+    match Signature::try_from(signature) {
+        Ok(signature) => verifying_key.verify(data, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+pub(crate) fn public_key_pem() -> String {
+    get_priv_key()
+        .to_public_key()
+        .to_public_key_pem(LineEnding::LF)
+        .unwrap()
+}
+
+/// Authenticates the artist's own state, under a key derived from its private
+/// key, so that nothing else can produce state the artist will accept.
+///
+/// The build script generates a new private key every time it runs, so state
+/// sealed by one build of the artist is refused by the next as if it had been
+/// tampered with.
+pub(crate) fn seal(data: &[u8]) -> [u8; MAC_SIZE] {
+    mac(&sealing_key(), data).finalize().into_bytes().into()
+}
+
+pub(crate) fn check_seal(data: &[u8], mac_bytes: &[u8]) -> bool {
+    // Compared in constant time, to give nothing away
+    mac(&sealing_key(), data).verify_slice(mac_bytes).is_ok()
+}
+
+fn sealing_key() -> [u8; MAC_SIZE] {
+    mac(PRIV_KEY_PEM.as_bytes(), SEALING_KEY_LABEL)
+        .finalize()
+        .into_bytes()
+        .into()
+}
+
+fn mac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    // HMAC takes a key of any length
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac
+}
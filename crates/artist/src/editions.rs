@@ -0,0 +1,85 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use rsa::sha2::{Digest, Sha256};
+
+use banscii_artist_interface_types::RejectReason;
+
+/// Enough of a SHA-256 digest to tell drafts apart.
+pub(crate) type DraftHash = [u8; 16];
+
+/// Bytes each draft takes up once encoded: its hash, then its count as a
+/// big-endian `u32`.
+pub(crate) const ENCODED_DRAFT_SIZE: usize = 20;
+
+/// How many works have been completed from each of a set of drafts, so that
+/// no more than an edition's worth are ever signed. A subject is known to the
+/// artist only by its draft, so the same subject rendered differently is a
+/// new one.
+pub(crate) struct Editions {
+    size: usize,
+    max_drafts: usize,
+    completed: BTreeMap<DraftHash, usize>,
+}
+
+impl Editions {
+    pub(crate) fn new(size: usize, max_drafts: usize) -> Self {
+        Self {
+            size,
+            max_drafts,
+            completed: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Counts another work completed from a draft, and returns its edition,
+    /// starting at 1.
+    pub(crate) fn take(&mut self, hash: DraftHash) -> Result<usize, RejectReason> {
+        if !self.completed.contains_key(&hash) && self.completed.len() >= self.max_drafts {
+            return Err(RejectReason::TooManySubjects);
+        }
+        let completed = self.completed.entry(hash).or_insert(0);
+        if *completed >= self.size {
+            return Err(RejectReason::SoldOut);
+        }
+        *completed += 1;
+        Ok(*completed)
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.completed.len() as u32).to_be_bytes());
+        for (hash, completed) in &self.completed {
+            out.extend_from_slice(hash);
+            out.extend_from_slice(&(*completed as u32).to_be_bytes());
+        }
+    }
+
+    /// The inverse of [`Self::encode`], which must take up all of `bytes`.
+    pub(crate) fn decode(&mut self, bytes: &[u8]) -> Option<()> {
+        let count = u32::from_be_bytes(bytes.get(..4)?.try_into().unwrap()) as usize;
+        let drafts = &bytes[4..];
+        if Some(drafts.len()) != count.checked_mul(ENCODED_DRAFT_SIZE) {
+            return None;
+        }
+        for draft in drafts.chunks(ENCODED_DRAFT_SIZE) {
+            let (hash, completed) = draft.split_at(16);
+            self.completed.insert(
+                hash.try_into().unwrap(),
+                u32::from_be_bytes(completed.try_into().unwrap()) as usize,
+            );
+        }
+        Some(())
+    }
+}
+
+pub(crate) fn hash(height: usize, width: usize, pixel_data: &[u8]) -> DraftHash {
+    let digest = Sha256::new()
+        .chain_update((height as u64).to_be_bytes())
+        .chain_update((width as u64).to_be_bytes())
+        .chain_update(pixel_data)
+        .finalize();
+    digest[..16].try_into().unwrap()
+}
@@ -6,21 +6,44 @@
 
 extern crate alloc;
 
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::time::Duration;
+
 use sel4cp::memory_region::{memory_region_symbol, ExternallySharedRef, ReadOnly, ReadWrite};
-use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use sel4cp::message::{MessageInfo, StatusMessageLabel};
 use sel4cp::{protection_domain, Channel, Handler};
 
 use banscii_artist_interface_types::*;
+use banscii_storage_client::Storage;
+use banscii_timer_client::Timer;
 
 mod artistic_secrets;
 mod cryptographic_secrets;
+mod editions;
+mod rate_limit;
+mod state;
 
 use artistic_secrets::Masterpiece;
+use rate_limit::RateLimiter;
+use state::StateStore;
 
 const ASSISTANT: Channel = Channel::new(0);
+const STORAGE: Channel = Channel::new(1);
+
+const TIMER_CHANNEL: Channel = Channel::new(2);
+
+const TIMER: Timer = Timer::new(TIMER_CHANNEL);
 
 const REGION_SIZE: usize = 0x4_000;
 
+// Set with BANSCII_EDITION_SIZE at build time
+const EDITION_SIZE: usize = include!(concat!(env!("OUT_DIR"), "/edition_size.rs"));
+
+// Set with BANSCII_RATE_BURST, BANSCII_RATE_REFILL, and BANSCII_RATE_PERIOD (in
+// milliseconds) at build time
+const RATE_LIMITER: RateLimiter = include!(concat!(env!("OUT_DIR"), "/rate_limiter.rs"));
+
 #[protection_domain(heap_size = 0x10000)]
 fn init() -> ThisHandler {
     let region_in = unsafe {
@@ -35,76 +58,328 @@ fn init() -> ThisHandler {
         )
     };
 
+    let storage_region = unsafe {
+        ExternallySharedRef::<'static, [u8]>::new(memory_region_symbol!(
+            storage_region_start: *mut [u8],
+            n = banscii_storage_interface_types::REGION_SIZE
+        ))
+    };
+
+    // Without the timer, tokens are never put back, and the artist would take
+    // no more than one burst of costly requests without saying why
+    let period = RATE_LIMITER.period();
+    TIMER
+        .set_periodic(period, period)
+        .expect("the timer won't refill the rate limiter");
+
     ThisHandler {
         region_in,
         region_out,
+        state: StateStore::new(Storage::new(STORAGE, storage_region), EDITION_SIZE),
+        rate_limiter: RATE_LIMITER,
+        stats: GetStatsResponse {
+            completed: 0,
+            verified: 0,
+            rejected: 0,
+            rate_limited: 0,
+            tokens: 0,
+            burst: 0,
+        },
     }
 }
 
 struct ThisHandler {
     region_in: ExternallySharedRef<'static, [u8], ReadOnly>,
     region_out: ExternallySharedRef<'static, [u8], ReadWrite>,
+    state: StateStore,
+    rate_limiter: RateLimiter,
+    // The rate limiter's fields are filled in as they're asked for
+    stats: GetStatsResponse,
 }
 
 impl Handler for ThisHandler {
     type Error = !;
 
+    fn notified(&mut self, channel: Channel) -> Result<(), Self::Error> {
+        match channel {
+            TIMER_CHANNEL => {
+                self.rate_limiter.tick();
+            }
+            _ => {
+                unreachable!()
+            }
+        }
+        Ok(())
+    }
+
     fn protected(
         &mut self,
         channel: Channel,
         msg_info: MessageInfo,
     ) -> Result<MessageInfo, Self::Error> {
         Ok(match channel {
-            ASSISTANT => match msg_info.recv::<Request>() {
-                Ok(msg) => {
-                    let draft_height = msg.height;
-                    let draft_width = msg.width;
-                    let draft = self
-                        .region_in
-                        .as_ptr()
-                        .index(msg.draft_start..msg.draft_start + msg.draft_size)
-                        .copy_to_vec();
-
-                    let masterpiece = Masterpiece::complete(draft_height, draft_width, &draft);
-
-                    let masterpiece_start = 0;
-                    let masterpiece_size = masterpiece.pixel_data.len();
-                    let masterpiece_end = masterpiece_start + masterpiece_size;
-
-                    self.region_out
-                        .as_mut_ptr()
-                        .index(masterpiece_start..masterpiece_end)
-                        .copy_from_slice(&masterpiece.pixel_data);
-
-                    let signature = cryptographic_secrets::sign(&masterpiece.pixel_data);
-                    let signature = signature.as_ref();
-
-                    let signature_start = masterpiece_end;
-                    let signature_size = signature.len();
-                    let signature_end = signature_start + signature_size;
-
-                    self.region_out
-                        .as_mut_ptr()
-                        .index(signature_start..signature_end)
-                        .copy_from_slice(&signature);
-
-                    MessageInfo::send(
-                        StatusMessageLabel::Ok,
-                        Response {
-                            height: masterpiece.height,
-                            width: masterpiece.width,
-                            masterpiece_start,
-                            masterpiece_size,
-                            signature_start,
-                            signature_size,
-                        },
-                    )
+            ASSISTANT => {
+                let tag = msg_info.label().try_into().ok();
+                if tag.map_or(false, is_costly) {
+                    if let Err(retry_after) = self.rate_limiter.take() {
+                        return Ok(self.rate_limited(retry_after));
+                    }
                 }
-                Err(_) => MessageInfo::send(StatusMessageLabel::Error, NoMessageValue),
-            },
+                self.dispatch(tag, &msg_info)
+            }
             _ => {
                 unreachable!()
             }
         })
     }
 }
+
+impl ThisHandler {
+    fn dispatch(&mut self, tag: Option<RequestTag>, msg_info: &MessageInfo) -> MessageInfo {
+        match tag {
+            Some(RequestTag::Complete) => match msg_info.recv() {
+                Ok(req) => self
+                    .complete(&req)
+                    .unwrap_or_else(|reason| self.reject(reason)),
+                Err(_) => self.reject(RejectReason::BadRequest),
+            },
+            Some(RequestTag::GetPublicKey) => self.get_public_key(),
+            Some(RequestTag::Verify) => match msg_info.recv() {
+                Ok(req) => self
+                    .verify(&req)
+                    .unwrap_or_else(|| self.reject(RejectReason::BadRequest)),
+                Err(_) => self.reject(RejectReason::BadRequest),
+            },
+            Some(RequestTag::GetStats) => self.get_stats(),
+            Some(RequestTag::GetAuditLog) => match msg_info.recv() {
+                Ok(req) => self
+                    .get_audit_log(&req)
+                    .unwrap_or_else(|reason| self.reject(reason)),
+                Err(_) => self.reject(RejectReason::BadRequest),
+            },
+            None => self.reject(RejectReason::BadRequest),
+        }
+    }
+
+    fn complete(&mut self, req: &CompleteRequest) -> Result<MessageInfo, RejectReason> {
+        let draft_height = req.height;
+        let draft_width = req.width;
+        let draft_range =
+            region_range(req.draft_start, req.draft_size).ok_or(RejectReason::BadRequest)?;
+        // The masterpiece is the same size as the draft, and is followed by
+        // its signature in our outgoing region
+        if draft_height.checked_mul(draft_width) != Some(req.draft_size)
+            || req.draft_size > REGION_SIZE - MAX_SIGNATURE_SIZE
+        {
+            return Err(RejectReason::BadRequest);
+        }
+        let draft = self.region_in.as_ptr().index(draft_range).copy_to_vec();
+
+        let timestamp = TIMER.now().map_err(|_| RejectReason::NoTime)?.unix_seconds;
+
+        let mut state = self.state.load()?;
+        let draft_hash = editions::hash(draft_height, draft_width, &draft);
+        let mut shard = self.state.shard(&state, &draft_hash)?;
+        let edition = shard.editions.take(draft_hash)?;
+        state.serial += 1;
+        let serial = state.serial;
+
+        let masterpiece = Masterpiece::complete(draft_height, draft_width, &draft);
+
+        // Nothing is signed until the work is on record, along with its entry
+        // in the audit log
+        let chain_head = state.chain_head;
+        let envelope = signed_data(
+            &masterpiece.pixel_data,
+            serial,
+            edition,
+            timestamp,
+            &chain_head,
+        );
+        let entry = AuditEntry::new(serial, &envelope, chain_head);
+        state.chain_head = entry.hash();
+        self.state.record(&entry)?;
+        self.state.save(&mut state, &shard)?;
+
+        let masterpiece_start = 0;
+        let masterpiece_size = masterpiece.pixel_data.len();
+        let masterpiece_end = masterpiece_start + masterpiece_size;
+
+        self.region_out
+            .as_mut_ptr()
+            .index(masterpiece_start..masterpiece_end)
+            .copy_from_slice(&masterpiece.pixel_data);
+
+        let signature = cryptographic_secrets::sign(&envelope);
+        let signature = signature.as_ref();
+
+        let signature_start = masterpiece_end;
+        let signature_size = signature.len();
+        let signature_end = signature_start + signature_size;
+
+        self.region_out
+            .as_mut_ptr()
+            .index(signature_start..signature_end)
+            .copy_from_slice(signature);
+
+        self.stats.completed += 1;
+
+        Ok(MessageInfo::send(
+            StatusMessageLabel::Ok,
+            CompleteResponse {
+                height: masterpiece.height,
+                width: masterpiece.width,
+                masterpiece_start,
+                masterpiece_size,
+                signature_start,
+                signature_size,
+                serial,
+                edition,
+                edition_size: shard.editions.size(),
+                timestamp,
+                chain_head,
+            },
+        ))
+    }
+
+    fn get_public_key(&mut self) -> MessageInfo {
+        let pem = cryptographic_secrets::public_key_pem();
+
+        let pem_start = 0;
+        let pem_size = pem.len();
+        let pem_end = pem_start + pem_size;
+
+        self.region_out
+            .as_mut_ptr()
+            .index(pem_start..pem_end)
+            .copy_from_slice(pem.as_bytes());
+
+        MessageInfo::send(
+            StatusMessageLabel::Ok,
+            GetPublicKeyResponse {
+                pem_start,
+                pem_size,
+            },
+        )
+    }
+
+    fn verify(&mut self, req: &VerifyRequest) -> Option<MessageInfo> {
+        let masterpiece_range = region_range(req.masterpiece_start, req.masterpiece_size)?;
+        let signature_range = region_range(req.signature_start, req.signature_size)?;
+
+        let masterpiece = self
+            .region_in
+            .as_ptr()
+            .index(masterpiece_range)
+            .copy_to_vec();
+        let signature = self.region_in.as_ptr().index(signature_range).copy_to_vec();
+
+        let valid = cryptographic_secrets::verify(
+            &signed_data(
+                &masterpiece,
+                req.serial,
+                req.edition,
+                req.timestamp,
+                &req.chain_head,
+            ),
+            &signature,
+        );
+
+        self.stats.verified += 1;
+
+        Some(MessageInfo::send(
+            StatusMessageLabel::Ok,
+            VerifyResponse {
+                valid: valid.into(),
+            },
+        ))
+    }
+
+    // As many entries as fit in our outgoing region, from the oldest still
+    // kept, up to the last work in the sealed state, so that an entry left
+    // over from a work which couldn't be recorded is never shown
+    fn get_audit_log(&mut self, req: &GetAuditLogRequest) -> Result<MessageInfo, RejectReason> {
+        let state = self.state.load()?;
+        let first = req.after.saturating_add(1).max(state.first_entry());
+        let last = state
+            .serial
+            .min(first.saturating_add(REGION_SIZE / AUDIT_ENTRY_SIZE - 1));
+
+        let entries_start = 0;
+        let mut count = 0;
+        for serial in first..=last {
+            let at = entries_start + count * AUDIT_ENTRY_SIZE;
+            self.region_out
+                .as_mut_ptr()
+                .index(at..at + AUDIT_ENTRY_SIZE)
+                .copy_from_slice(&self.state.entry(serial)?.to_bytes());
+            count += 1;
+        }
+
+        Ok(MessageInfo::send(
+            StatusMessageLabel::Ok,
+            GetAuditLogResponse {
+                entries_start,
+                count,
+                more: (last < state.serial).into(),
+                first: state.first_entry(),
+            },
+        ))
+    }
+
+    fn get_stats(&mut self) -> MessageInfo {
+        MessageInfo::send(
+            StatusMessageLabel::Ok,
+            GetStatsResponse {
+                tokens: self.rate_limiter.tokens(),
+                burst: self.rate_limiter.burst(),
+                ..self.stats
+            },
+        )
+    }
+
+    fn reject(&mut self, reason: RejectReason) -> MessageInfo {
+        self.stats.rejected += 1;
+        MessageInfo::send(StatusMessageLabel::Error, RejectResponse::new(reason, 0))
+    }
+
+    fn rate_limited(&mut self, retry_after: Duration) -> MessageInfo {
+        self.stats.rejected += 1;
+        self.stats.rate_limited += 1;
+        // Rounded up, so that it's never too soon
+        let retry_after = retry_after.as_nanos().div_ceil(1_000_000) as usize;
+        MessageInfo::send(
+            StatusMessageLabel::Error,
+            RejectResponse::new(RejectReason::RateLimited, retry_after),
+        )
+    }
+}
+
+/// Requests which sign, verify, or go to storage, and so are rate limited.
+fn is_costly(tag: RequestTag) -> bool {
+    match tag {
+        RequestTag::Complete | RequestTag::Verify | RequestTag::GetAuditLog => true,
+        RequestTag::GetPublicKey | RequestTag::GetStats => false,
+    }
+}
+
+/// The assistant is untrusted, so every span it points us at is checked
+/// against our regions before use.
+fn region_range(start: usize, size: usize) -> Option<Range<usize>> {
+    let end = start.checked_add(size)?;
+    (end <= REGION_SIZE).then_some(start..end)
+}
+
+fn signed_data(
+    pixel_data: &[u8],
+    serial: usize,
+    edition: usize,
+    timestamp: u64,
+    chain_head: &ChainHash,
+) -> Vec<u8> {
+    [
+        pixel_data,
+        &provenance(serial, edition, timestamp, chain_head),
+    ]
+    .concat()
+}
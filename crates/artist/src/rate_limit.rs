@@ -0,0 +1,53 @@
+use core::time::Duration;
+
+/// A token bucket. Each costly request takes a token, and `refill` tokens are
+/// put back every `period`, up to `burst`, on a periodic timeout from the
+/// timer. Only time puts tokens back, so however fast the assistant calls,
+/// the artist takes no more than a burst and then `refill` every `period`.
+pub(crate) struct RateLimiter {
+    burst: usize,
+    refill: usize,
+    period: Duration,
+    tokens: usize,
+}
+
+impl RateLimiter {
+    /// Starts with a full bucket.
+    pub(crate) const fn new(burst: usize, refill: usize, period: Duration) -> Self {
+        Self {
+            burst,
+            refill,
+            period,
+            tokens: burst,
+        }
+    }
+
+    pub(crate) fn burst(&self) -> usize {
+        self.burst
+    }
+
+    pub(crate) fn tokens(&self) -> usize {
+        self.tokens
+    }
+
+    pub(crate) fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Puts back tokens, once a period.
+    pub(crate) fn tick(&mut self) {
+        self.tokens = self.tokens.saturating_add(self.refill).min(self.burst);
+    }
+
+    /// Takes a token, or returns how long until there's one to take, at
+    /// most.
+    pub(crate) fn take(&mut self) -> Result<(), Duration> {
+        match self.tokens.checked_sub(1) {
+            Some(tokens) => {
+                self.tokens = tokens;
+                Ok(())
+            }
+            None => Err(self.period),
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! The artist's serial number, audit log head, and edition counts are kept in
+//! storage as records sealed with a MAC under a key only the artist has, so
+//! that they can't be forged or altered there. Each MAC covers the record's
+//! key as well, so that one record can't be passed off as another.
+//!
+//! The edition counts are split by draft hash into a few shards, each a
+//! record of its own, so that they aren't bounded by what fits in one value,
+//! and completing a work only rewrites the shard its draft falls in. The main
+//! record carries a version, one more than the last, and the version each
+//! shard was last saved at, so that an older record of either put back in
+//! place of the latest is caught too.
+//!
+//! The artist only knows which version is latest once it has loaded or saved
+//! one since starting, so an older copy of storage put back while it's not
+//! running goes unnoticed. Catching that would take a counter which survives
+//! a reboot beyond the reach of everything else, which QEMU's virt machine
+//! doesn't offer.
+//!
+//! The main record is laid out as:
+//!
+//! ```text
+//! version (u64) | serial (u64) | chain head | shard versions (u64 each) | MAC
+//! ```
+//!
+//! and a shard as:
+//!
+//! ```text
+//! version (u64) | editions | MAC
+//! ```
+//!
+//! with integers big-endian, and each MAC of the key followed by all before
+//! it.
+//!
+//! The audit log is kept alongside, an entry to a key, as
+//! [`AuditEntry::to_bytes`]. The entries aren't sealed, since the chain head
+//! vouches for them, but a missing one counts as tampering. Only the latest
+//! 64 are kept, so that the log doesn't fill storage; the oldest of them holds
+//! the head as it was before it, which is as far back as the log can then be
+//! followed.
+
+use alloc::vec::Vec;
+
+use banscii_artist_interface_types::{
+    AuditEntry, Chain, ChainHash, RejectReason, AUDIT_ENTRY_SIZE, CHAIN_HASH_SIZE,
+};
+use banscii_storage_client::Storage;
+use banscii_storage_interface_types::MAX_VALUE_SIZE;
+
+use super::cryptographic_secrets::{self, MAC_SIZE};
+use super::editions::{DraftHash, Editions, ENCODED_DRAFT_SIZE};
+
+const STATE_KEY: &[u8] = b"state";
+
+// Followed by the shard's index as a byte
+const SHARD_KEY_PREFIX: &[u8] = b"editions/";
+
+// Followed by an entry's serial number as a big-endian `u64`
+const AUDIT_KEY_PREFIX: &[u8] = b"audit/";
+
+// How many of the latest entries in the audit log are kept
+const AUDIT_LOG_SIZE: usize = 64;
+
+const SHARDS: usize = 16;
+
+const STATE_SIZE: usize = 8 + 8 + CHAIN_HASH_SIZE + SHARDS * 8;
+
+// As many as fit in one value
+const MAX_DRAFTS_PER_SHARD: usize = (MAX_VALUE_SIZE - 8 - 4 - MAC_SIZE) / ENCODED_DRAFT_SIZE;
+
+pub(crate) struct State {
+    /// The serial number of the last work completed.
+    pub(crate) serial: usize,
+    /// The head of the audit log, after the last work completed.
+    pub(crate) chain_head: ChainHash,
+    // The version each shard was last saved at, or 0 if never
+    shard_versions: [u64; SHARDS],
+}
+
+impl State {
+    /// The serial number of the oldest entry kept in the audit log.
+    pub(crate) fn first_entry(&self) -> usize {
+        self.serial.saturating_sub(AUDIT_LOG_SIZE) + 1
+    }
+}
+
+/// The edition counts for the drafts whose hashes fall in one shard.
+pub(crate) struct Shard {
+    index: usize,
+    pub(crate) editions: Editions,
+}
+
+pub(crate) struct StateStore {
+    storage: Storage,
+    edition_size: usize,
+    // The latest version loaded or saved since the artist started
+    version: Option<u64>,
+}
+
+impl StateStore {
+    pub(crate) fn new(storage: Storage, edition_size: usize) -> Self {
+        Self {
+            storage,
+            edition_size,
+            version: None,
+        }
+    }
+
+    /// Reads the state back from storage, checking that it's the artist's
+    /// own, and no older than the last it saw. The state is read afresh each
+    /// time, so that signing resumes once the latest is put back.
+    pub(crate) fn load(&mut self) -> Result<State, RejectReason> {
+        let sealed = self
+            .storage
+            .get(STATE_KEY)
+            .map_err(|_| RejectReason::Unrecorded)?;
+        let Some(sealed) = sealed else {
+            // Nothing has been saved yet, unless we know otherwise
+            return match self.version {
+                None => Ok(State {
+                    serial: Chain::START.serial,
+                    chain_head: Chain::START.head,
+                    shard_versions: [0; SHARDS],
+                }),
+                Some(_) => Err(RejectReason::RolledBack),
+            };
+        };
+        let data = unseal(STATE_KEY, &sealed)
+            .filter(|data| data.len() == STATE_SIZE)
+            .ok_or(RejectReason::Tampered)?;
+        let version = u64::from_be_bytes(data[..8].try_into().unwrap());
+        if self.version.map_or(false, |latest| version < latest) {
+            return Err(RejectReason::RolledBack);
+        }
+        self.version = Some(version);
+        let serial = u64::from_be_bytes(data[8..16].try_into().unwrap()) as usize;
+        let (chain_head, shard_versions) = data[16..].split_at(CHAIN_HASH_SIZE);
+        let mut state = State {
+            serial,
+            chain_head: chain_head.try_into().unwrap(),
+            shard_versions: [0; SHARDS],
+        };
+        for (version, bytes) in state
+            .shard_versions
+            .iter_mut()
+            .zip(shard_versions.chunks(8))
+        {
+            *version = u64::from_be_bytes(bytes.try_into().unwrap());
+        }
+        Ok(state)
+    }
+
+    /// Reads back the shard a draft falls in, checking it against the state.
+    pub(crate) fn shard(
+        &mut self,
+        state: &State,
+        draft: &DraftHash,
+    ) -> Result<Shard, RejectReason> {
+        let index = usize::from(draft[0]) % SHARDS;
+        let expected = state.shard_versions[index];
+        let mut editions = Editions::new(self.edition_size, MAX_DRAFTS_PER_SHARD);
+        let sealed = self
+            .storage
+            .get(&shard_key(index))
+            .map_err(|_| RejectReason::Unrecorded)?;
+        let Some(sealed) = sealed else {
+            return match expected {
+                0 => Ok(Shard { index, editions }),
+                _ => Err(RejectReason::RolledBack),
+            };
+        };
+        let data = unseal(&shard_key(index), &sealed)
+            .filter(|data| data.len() >= 8)
+            .ok_or(RejectReason::Tampered)?;
+        let version = u64::from_be_bytes(data[..8].try_into().unwrap());
+        // A shard newer than the state is left over from a work which
+        // couldn't be recorded, and at worst costs an edition
+        if version < expected {
+            return Err(RejectReason::RolledBack);
+        }
+        editions.decode(&data[8..]).ok_or(RejectReason::Tampered)?;
+        Ok(Shard { index, editions })
+    }
+
+    /// Saves a shard and then the state as the next version, and lets go of
+    /// the entry in the audit log which that leaves too old to keep.
+    pub(crate) fn save(&mut self, state: &mut State, shard: &Shard) -> Result<(), RejectReason> {
+        let version = self.version.map_or(1, |latest| latest + 1);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&version.to_be_bytes());
+        shard.editions.encode(&mut data);
+        let key = shard_key(shard.index);
+        self.storage
+            .put(&key, &seal(&key, data))
+            .map_err(|_| RejectReason::Unrecorded)?;
+        state.shard_versions[shard.index] = version;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(state.serial as u64).to_be_bytes());
+        data.extend_from_slice(&state.chain_head);
+        for shard_version in state.shard_versions {
+            data.extend_from_slice(&shard_version.to_be_bytes());
+        }
+        self.storage
+            .put(STATE_KEY, &seal(STATE_KEY, data))
+            .map_err(|_| RejectReason::Unrecorded)?;
+        self.version = Some(version);
+
+        // Failing to delete it only leaves it taking up space
+        if let Some(serial) = state
+            .first_entry()
+            .checked_sub(1)
+            .filter(|serial| *serial > 0)
+        {
+            let _ = self.storage.delete(&audit_key(serial));
+        }
+        Ok(())
+    }
+
+    /// Adds an entry to the audit log. It's only part of the log once the
+    /// state with it as the chain head is saved.
+    pub(crate) fn record(&mut self, entry: &AuditEntry) -> Result<(), RejectReason> {
+        self.storage
+            .put(&audit_key(entry.serial), &entry.to_bytes())
+            .map_err(|_| RejectReason::Unrecorded)
+    }
+
+    /// Reads back the entry for a work from `state.first_entry()` up to
+    /// `state.serial`.
+    pub(crate) fn entry(&mut self, serial: usize) -> Result<AuditEntry, RejectReason> {
+        let bytes = self
+            .storage
+            .get(&audit_key(serial))
+            .map_err(|_| RejectReason::Unrecorded)?
+            .ok_or(RejectReason::Tampered)?;
+        let bytes: &[u8; AUDIT_ENTRY_SIZE] =
+            bytes[..].try_into().map_err(|_| RejectReason::Tampered)?;
+        Ok(AuditEntry::from_bytes(bytes))
+    }
+}
+
+fn seal(key: &[u8], mut data: Vec<u8>) -> Vec<u8> {
+    let mac = cryptographic_secrets::seal(&[key, &data].concat());
+    data.extend_from_slice(&mac);
+    data
+}
+
+// The data a record was sealed with, if the MAC checks out
+fn unseal<'a>(key: &[u8], sealed: &'a [u8]) -> Option<&'a [u8]> {
+    let (data, mac) = sealed.split_at(sealed.len().checked_sub(MAC_SIZE)?);
+    cryptographic_secrets::check_seal(&[key, data].concat(), mac).then_some(data)
+}
+
+fn shard_key(index: usize) -> Vec<u8> {
+    [SHARD_KEY_PREFIX, &[index as u8]].concat()
+}
+
+fn audit_key(serial: usize) -> Vec<u8> {
+    [AUDIT_KEY_PREFIX, &(serial as u64).to_be_bytes()].concat()
+}
@@ -0,0 +1,37 @@
+//! Rendering with and without the glyph cache. Run with
+//!
+//!     cargo bench -p banscii-assistant-core
+
+#![feature(test)]
+
+extern crate test;
+
+use test::Bencher;
+
+use banscii_assistant_core::{Draft, GlyphCache, RenderOptions};
+
+const SUBJECTS: &[&str] = &["Hello, World!", "The quick brown fox", "0123456789"];
+
+// As much as the assistant sets aside
+const CACHE_SIZE: usize = 0x2_000;
+
+#[bench]
+fn draft_uncached(b: &mut Bencher) {
+    let options = RenderOptions::default();
+    b.iter(|| {
+        for subject in SUBJECTS {
+            test::black_box(Draft::new(subject, &options));
+        }
+    });
+}
+
+#[bench]
+fn draft_cached(b: &mut Bencher) {
+    let options = RenderOptions::default();
+    let mut cache = GlyphCache::new(CACHE_SIZE);
+    b.iter(|| {
+        for subject in SUBJECTS {
+            test::black_box(Draft::with_cache(subject, &options, &mut cache));
+        }
+    });
+}
@@ -0,0 +1,28 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+extern crate alloc;
+
+// The typeface is shared with the crate so that the bound is worked out just
+// as it would be at run time
+#[allow(dead_code)]
+#[path = "src/typeface.rs"]
+mod typeface;
+
+use typeface::Typeface;
+
+const BUNDLED_FONT: &str = "assets/fonts/rock-salt/RockSalt-Regular.ttf";
+
+fn main() {
+    let font_data = fs::read(BUNDLED_FONT).unwrap();
+    let typeface = Typeface::try_from_bytes(&font_data).unwrap();
+    let max_kerning = typeface.unscaled_max_kerning();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_path = PathBuf::from(&out_dir).join("max_kerning.rs");
+    fs::write(out_path, format!("{:?}", max_kerning)).unwrap();
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/typeface.rs");
+    println!("cargo:rerun-if-changed={}", BUNDLED_FONT);
+}
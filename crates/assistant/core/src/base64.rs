@@ -0,0 +1,126 @@
+//! Standard base64, as produced by `base64` and `openssl base64`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Padded, and all on one line.
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for group in data.chunks(3) {
+        let acc = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, b)| acc | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(char::from(ALPHABET[(acc >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Whitespace is skipped, and padding is optional. Returns `None` for anything
+/// else outside of the alphabet, or for padding anywhere but the end.
+pub fn decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut len = 0;
+    let mut padding = 0;
+    for c in s.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            padding += 1;
+            continue;
+        }
+        if padding > 0 {
+            return None;
+        }
+        let value = ALPHABET.iter().position(|a| *a == c)?;
+        // Bits beyond those still to be output are shifted out the top
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        len += 1;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    // Padding only ever makes up the rest of a group of four, and a lone
+    // character left over can't hold a whole byte
+    match (len % 4, padding) {
+        (0, 0) | (2, 0 | 2) | (3, 0 | 1) => Some(out),
+        _ => None,
+    }
+}
+
+/// The contents of a PEM block, such as a public key, ignoring its label.
+pub fn decode_pem(pem: &str) -> Option<Vec<u8>> {
+    let mut lines = pem.lines().map(str::trim);
+    lines.find(|line| line.starts_with("-----BEGIN "))?;
+    let mut body = String::new();
+    for line in lines {
+        if line.starts_with("-----END ") {
+            return decode(&body);
+        }
+        body.push_str(line);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_4648_vectors() {
+        for (decoded, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(decoded.as_bytes()), encoded);
+            assert_eq!(decode(encoded).unwrap(), decoded.as_bytes());
+            assert_eq!(
+                decode(encoded.trim_end_matches('=')).unwrap(),
+                decoded.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn whitespace_and_every_byte() {
+        let encoded = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4v\n\
+                       MDEyMzQ1Njc4OTo7PD0+P0BBQkNERUZHSElKS0xNTk9QUVJTVFVWV1hZWltcXV5f\n\
+                       YGFiY2RlZmdoaWprbG1ub3BxcnN0dXZ3eHl6e3x9fn+AgYKDhIWGh4iJiouMjY6P\n\
+                       kJGSk5SVlpeYmZqbnJ2en6ChoqOkpaanqKmqq6ytrq+wsbKztLW2t7i5uru8vb6/\n\
+                       wMHCw8TFxsfIycrLzM3Oz9DR0tPU1dbX2Nna29zd3t/g4eLj5OXm5+jp6uvs7e7v\n\
+                       8PHy8/T19vf4+fr7/P3+/w==\n";
+        let decoded = decode(encoded).unwrap();
+        assert_eq!(decoded, (0..=255).collect::<Vec<u8>>());
+        assert_eq!(encode(&decoded), encoded.replace('\n', ""));
+    }
+
+    #[test]
+    fn pem() {
+        let pem = "-----BEGIN PUBLIC KEY-----\nZm9v\nYmFy\n-----END PUBLIC KEY-----\n";
+        assert_eq!(decode_pem(pem).unwrap(), b"foobar");
+        assert_eq!(decode_pem("Zm9vYmFy"), None);
+        assert_eq!(decode_pem(&pem[..pem.len() - 25]), None);
+    }
+
+    #[test]
+    fn rejects() {
+        for encoded in ["Zg=a", "Z", "Zm9vY", "Zg===", "Zm9v=", "Zm9*", "Zm9v\0"] {
+            assert_eq!(decode(encoded), None, "{:?}", encoded);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Running a list of subjects past the artist in one go, for a script at the
+//! other end of the console rather than a person.
+//!
+//! Subjects are entered one per line after `:batch`, up to [`END_MARKER`].
+//! Each is then reported as a line of JSON, followed by a line summing up the
+//! whole run:
+//!
+//! ```text
+//! {"index":0,"subject":"Hi","width":23,"height":13,"serial":1,"edition":1,"timestamp":1700000000,"chain_head":"0000...","missing_glyphs":[],"art":"QEBA...","signature":"NNiO..."}
+//! {"index":1,"subject":"中","error":"subject is too large to render at this size"}
+//! {"completed":1,"failed":1}
+//! ```
+//!
+//! The art is the masterpiece's rows run together, and it and the signature
+//! are base64. The timestamp is when the artist signed the work, in seconds
+//! since the Unix epoch. The head of the artist's audit log before the work, which is
+//! signed along with it, is hex.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+use core::mem;
+
+use crate::base64;
+use crate::json::{write_string, Escaped};
+
+pub const END_MARKER: &str = ":end";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchTooLarge;
+
+impl fmt::Display for BatchTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "too many subjects for one batch")
+    }
+}
+
+/// Subjects gathered for a batch, bounded by the number of bytes they occupy
+/// on the heap.
+pub struct Batch {
+    subjects: Vec<String>,
+    size: usize,
+    max_size: usize,
+    too_large: bool,
+}
+
+impl Batch {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            subjects: Vec::new(),
+            size: 0,
+            max_size,
+            too_large: false,
+        }
+    }
+
+    /// Once the batch grows too large, the rest of it is dropped as it
+    /// arrives, and [`Self::into_subjects`] fails, so that none of it is run.
+    pub fn push(&mut self, subject: &str) {
+        let cost = mem::size_of::<String>() + subject.len();
+        if self.too_large || self.size + cost > self.max_size {
+            self.too_large = true;
+            self.subjects = Vec::new();
+            return;
+        }
+        self.size += cost;
+        self.subjects.push(subject.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.subjects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subjects.is_empty()
+    }
+
+    pub fn into_subjects(self) -> Result<Vec<String>, BatchTooLarge> {
+        if self.too_large {
+            return Err(BatchTooLarge);
+        }
+        Ok(self.subjects)
+    }
+}
+
+/// The result for one subject, as a line of JSON without the newline.
+pub struct Record<'a> {
+    pub index: usize,
+    pub subject: &'a str,
+    pub outcome: Outcome<'a>,
+}
+
+pub enum Outcome<'a> {
+    Completed(Completed<'a>),
+    Failed(&'a dyn fmt::Display),
+}
+
+pub struct Completed<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub serial: usize,
+    pub edition: usize,
+    pub timestamp: u64,
+    pub chain_head: &'a [u8],
+    pub missing_glyphs: &'a [char],
+    pub pixel_data: &'a [u8],
+    pub signature: &'a [u8],
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{\"index\":{},\"subject\":", self.index)?;
+        write_string(f, self.subject)?;
+        match &self.outcome {
+            Outcome::Completed(work) => {
+                write!(
+                    f,
+                    ",\"width\":{},\"height\":{},\"serial\":{},\"edition\":{},\
+                     \"timestamp\":{},\"chain_head\":\"",
+                    work.width, work.height, work.serial, work.edition, work.timestamp,
+                )?;
+                for b in work.chain_head {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, "\",\"missing_glyphs\":[")?;
+                for (i, c) in work.missing_glyphs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, c.encode_utf8(&mut [0; 4]))?;
+                }
+                write!(
+                    f,
+                    "],\"art\":\"{}\",\"signature\":\"{}\"",
+                    base64::encode(work.pixel_data),
+                    base64::encode(work.signature),
+                )?;
+            }
+            Outcome::Failed(err) => {
+                write!(f, ",\"error\":\"")?;
+                write!(Escaped(f), "{}", err)?;
+                write!(f, "\"")?;
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+/// The last line of a batch's output.
+pub struct Summary {
+    pub completed: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{{\"completed\":{},\"failed\":{}}}",
+            self.completed, self.failed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::format;
+
+    #[test]
+    fn bounded() {
+        let cost = mem::size_of::<String>() + 5;
+        let mut batch = Batch::new(2 * cost);
+        batch.push("Hello");
+        batch.push("World");
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.into_subjects().unwrap(), ["Hello", "World"]);
+
+        let mut batch = Batch::new(2 * cost);
+        batch.push("Hello");
+        batch.push("World");
+        batch.push("!");
+        batch.push("?");
+        assert_eq!(batch.into_subjects(), Err(BatchTooLarge));
+    }
+
+    #[test]
+    fn completed_record() {
+        let record = Record {
+            index: 3,
+            subject: "中 \"quoted\" \\ \u{1}",
+            outcome: Outcome::Completed(Completed {
+                width: 2,
+                height: 1,
+                serial: 7,
+                edition: 1,
+                timestamp: 1_700_000_000,
+                chain_head: &[0x0a],
+                missing_glyphs: &['中'],
+                pixel_data: b"@ ",
+                signature: &[0xff, 0x00],
+            }),
+        };
+        assert_eq!(
+            format!("{}", record),
+            "{\"index\":3,\"subject\":\"中 \\\"quoted\\\" \\\\ \\u0001\",\
+             \"width\":2,\"height\":1,\"serial\":7,\"edition\":1,\"timestamp\":1700000000,\
+             \"chain_head\":\"0a\",\
+             \"missing_glyphs\":[\"中\"],\
+             \"art\":\"QCA=\",\"signature\":\"/wA=\"}",
+        );
+    }
+
+    #[test]
+    fn failed_record() {
+        let record = Record {
+            index: 0,
+            subject: "x",
+            outcome: Outcome::Failed(&BatchTooLarge),
+        };
+        assert_eq!(
+            format!("{}", record),
+            "{\"index\":0,\"subject\":\"x\",\"error\":\"too many subjects for one batch\"}",
+        );
+        let summary = Summary {
+            completed: 1,
+            failed: 2,
+        };
+        assert_eq!(format!("{}", summary), "{\"completed\":1,\"failed\":2}");
+    }
+}
@@ -0,0 +1,191 @@
+use core::fmt;
+
+const PREFIXES: &[char] = &[':', '/'];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input<'a> {
+    Subject(&'a str),
+    Command(Invocation<'a>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation<'a> {
+    pub name: &'a str,
+    pub args: Args<'a>,
+}
+
+/// Splits a line into either a subject or a command invocation.
+///
+/// Lines starting with `:` or `/` are commands. Doubling the prefix escapes
+/// it, so that `//etc` is the subject `/etc`.
+pub fn parse(line: &str) -> Input {
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(c) if PREFIXES.contains(&c) => {
+            let rest = chars.as_str();
+            if rest.starts_with(c) {
+                return Input::Subject(rest);
+            }
+            let mut args = Args::new(rest);
+            let name = args.next().unwrap_or("");
+            Input::Command(Invocation { name, args })
+        }
+        _ => Input::Subject(line),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Args<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(s: &'a str) -> Self {
+        Self {
+            rest: s.trim_start(),
+        }
+    }
+
+    /// Everything after the arguments consumed so far, with surrounding
+    /// whitespace removed.
+    pub fn rest(&self) -> &'a str {
+        self.rest.trim_end()
+    }
+
+    pub fn required(&mut self, name: &'static str) -> Result<&'a str, ArgsError<'a>> {
+        self.next().ok_or(ArgsError::Missing(name))
+    }
+
+    pub fn finish(mut self) -> Result<(), ArgsError<'a>> {
+        match self.next() {
+            None => Ok(()),
+            Some(arg) => Err(ArgsError::Unexpected(arg)),
+        }
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (arg, rest) = self
+            .rest
+            .split_once(char::is_whitespace)
+            .unwrap_or((self.rest, ""));
+        self.rest = rest.trim_start();
+        Some(arg)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgsError<'a> {
+    Missing(&'static str),
+    Unexpected(&'a str),
+}
+
+impl fmt::Display for ArgsError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Missing(name) => write!(f, "missing argument <{}>", name),
+            Self::Unexpected(arg) => write!(f, "unexpected argument '{}'", arg),
+        }
+    }
+}
+
+/// An entry in a command table. `T` is whatever state the command operates on,
+/// and `E` is what it can fail with.
+pub struct Command<T, E> {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub summary: &'static str,
+    pub run: fn(&mut T, Args) -> Result<(), E>,
+}
+
+pub fn lookup<'a, T, E>(table: &'a [Command<T, E>], name: &str) -> Option<&'a Command<T, E>> {
+    table.iter().find(|command| command.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    fn command(line: &str) -> (&str, Vec<&str>) {
+        match parse(line) {
+            Input::Command(Invocation { name, args }) => (name, args.collect()),
+            Input::Subject(subject) => panic!("parsed as subject: {:?}", subject),
+        }
+    }
+
+    #[test]
+    fn subjects() {
+        assert_eq!(parse("Hello, World!"), Input::Subject("Hello, World!"));
+        assert_eq!(parse(" :help"), Input::Subject(" :help"));
+        assert_eq!(parse("a:b/c"), Input::Subject("a:b/c"));
+        assert_eq!(parse("::help"), Input::Subject(":help"));
+        assert_eq!(parse("//etc"), Input::Subject("/etc"));
+        assert_eq!(parse(""), Input::Subject(""));
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(command(":help"), ("help", [].into()));
+        assert_eq!(command("/help"), ("help", [].into()));
+        assert_eq!(
+            command(":  show   settings "),
+            ("show", ["settings"].into())
+        );
+        assert_eq!(command("/set size 10.5"), ("set", ["size", "10.5"].into()));
+        assert_eq!(command(":/help"), ("/help", [].into()));
+        assert_eq!(command(":"), ("", [].into()));
+        assert_eq!(command("/ "), ("", [].into()));
+    }
+
+    #[test]
+    fn args() {
+        let mut args = Args::new("  key  some value  ");
+        assert_eq!(args.required("key"), Ok("key"));
+        assert_eq!(args.rest(), "some value");
+        assert_eq!(args.clone().finish(), Err(ArgsError::Unexpected("some")));
+        assert_eq!(args.next(), Some("some"));
+        assert_eq!(args.next(), Some("value"));
+        assert_eq!(args.required("other"), Err(ArgsError::Missing("other")));
+        assert_eq!(args.finish(), Ok(()));
+    }
+
+    #[test]
+    fn table() {
+        fn incr(n: &mut usize, _: Args) -> Result<(), ()> {
+            *n += 1;
+            Ok(())
+        }
+
+        let table = [
+            Command {
+                name: "one",
+                usage: "",
+                summary: "",
+                run: incr,
+            },
+            Command {
+                name: "two",
+                usage: "",
+                summary: "",
+                run: incr,
+            },
+        ];
+
+        let mut n = 0;
+        assert_eq!(
+            (lookup(&table, "two").unwrap().run)(&mut n, Args::new("")),
+            Ok(())
+        );
+        assert_eq!(n, 1);
+        assert!(lookup(&table, "three").is_none());
+        assert!(lookup(&table, "").is_none());
+    }
+}
@@ -0,0 +1,184 @@
+//! Encodings of a [`Draft`] as an image, for looking at the rasterisation
+//! directly rather than through the artist's palette.
+//!
+//! Pixel values are written as they are, so ink is light on a dark
+//! background. Each pixel stands for one character cell of the masterpiece.
+//! The raster formats draw it as a square, which stretches the subject to
+//! twice its width. The SVG draws it twice as tall as it is wide, like a cell
+//! of a terminal.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::Draft;
+
+pub(crate) mod deflate;
+
+/// Binary greyscale Netpbm.
+pub fn pgm(draft: &Draft) -> Vec<u8> {
+    let mut out = format!("P5\n{} {}\n255\n", draft.width, draft.height).into_bytes();
+    out.extend_from_slice(&draft.pixel_data);
+    out
+}
+
+/// 8-bit greyscale PNG, or `None` if the draft is empty, since a PNG must have
+/// at least one pixel.
+pub fn png(draft: &Draft) -> Option<Vec<u8>> {
+    if draft.width == 0 || draft.height == 0 {
+        return None;
+    }
+    let width = u32::try_from(draft.width).ok()?;
+    let height = u32::try_from(draft.height).ok()?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // Bit depth, greyscale, deflate, adaptive filtering, no interlacing
+    ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    // Each scanline is unfiltered, which is marked by a leading zero
+    let mut scanlines = Vec::with_capacity((draft.width + 1) * draft.height);
+    for row in draft.pixel_data.chunks(draft.width) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"IDAT", &deflate::zlib(&scanlines));
+    chunk(&mut out, b"IEND", &[]);
+    Some(out)
+}
+
+/// One `<rect>` per character cell.
+pub fn svg(draft: &Draft) -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+        draft.width,
+        draft.height * 2,
+    );
+    for (y, row) in draft.pixel_data.chunks(draft.width.max(1)).enumerate() {
+        for (x, grey) in row.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                r##"<rect x="{}" y="{}" width="1" height="2" fill="#{:02x}{:02x}{:02x}"/>"##,
+                x,
+                y * 2,
+                grey,
+                grey,
+                grey,
+            );
+        }
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, ty: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(ty);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// CRC-32 as used by PNG, computed bitwise since images are small
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc ^= u32::from(*b);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec;
+
+    use crate::RenderOptions;
+
+    fn draft() -> Draft {
+        Draft::new("Hi", &RenderOptions::default())
+    }
+
+    #[test]
+    fn pgm_header() {
+        let draft = draft();
+        let pgm = pgm(&draft);
+        let header = format!("P5\n{} {}\n255\n", draft.width, draft.height);
+        assert!(pgm.starts_with(header.as_bytes()));
+        assert_eq!(&pgm[header.len()..], &draft.pixel_data[..]);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        // The IEND chunk, as it appears in every PNG
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let draft = draft();
+        let png = png(&draft).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        // Walk the chunks, checking each one's CRC
+        let mut chunks = vec![];
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (body, crc) = rest[4..].split_at(4 + len);
+            assert_eq!(
+                crc32(body),
+                u32::from_be_bytes(crc[..4].try_into().unwrap())
+            );
+            chunks.push((&body[..4], &body[4..]));
+            rest = &crc[4..];
+        }
+        let types = chunks.iter().map(|(ty, _)| *ty).collect::<Vec<_>>();
+        assert_eq!(types, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(&chunks[0].1[..4], &(draft.width as u32).to_be_bytes());
+        assert_eq!(&chunks[0].1[4..8], &(draft.height as u32).to_be_bytes());
+
+        let scanlines = miniz_oxide::inflate::decompress_to_vec_zlib(chunks[1].1).unwrap();
+        let pixel_data = scanlines
+            .chunks(draft.width + 1)
+            .flat_map(|line| {
+                assert_eq!(line[0], 0);
+                &line[1..]
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(pixel_data, draft.pixel_data);
+    }
+
+    #[test]
+    fn png_of_empty_draft() {
+        assert!(png(&Draft::new("", &RenderOptions::default())).is_none());
+    }
+
+    #[test]
+    fn svg_has_a_rect_per_cell() {
+        let draft = draft();
+        let svg = svg(&draft);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), draft.pixel_data.len());
+    }
+}
@@ -0,0 +1,255 @@
+//! A small zlib encoder. Data is compressed with the fixed Huffman codes and
+//! a greedy match search, which does well on drafts since they're mostly
+//! blank. Whatever doesn't compress is sent in stored blocks instead.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_STORED_BLOCK: usize = u16::MAX as usize;
+
+const HASH_BITS: u32 = 12;
+
+pub(crate) const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(crate) const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(crate) const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(crate) const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// A zlib stream holding `data`, in whichever of a compressed block or stored
+/// blocks is smaller.
+pub(crate) fn zlib(data: &[u8]) -> Vec<u8> {
+    let fixed = fixed(data);
+    let mut out = vec![0x78, 0x01];
+    if fixed.len() < stored_size(data.len()) {
+        out.extend(fixed);
+    } else {
+        out.extend(stored(data));
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn stored_size(len: usize) -> usize {
+    let blocks = ((len + MAX_STORED_BLOCK - 1) / MAX_STORED_BLOCK).max(1);
+    len + blocks * 5
+}
+
+fn stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(stored_size(data.len()));
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        // BFINAL and BTYPE, padded out to a byte
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+fn fixed(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter::default();
+    // BFINAL, then BTYPE for fixed Huffman codes
+    out.bits(1, 1);
+    out.bits(1, 2);
+
+    let mut heads = vec![usize::MAX; 1 << HASH_BITS];
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(&data[i..i + MIN_MATCH]);
+            let candidate = heads[h];
+            heads[h] = i;
+            if candidate != usize::MAX && i - candidate <= WINDOW_SIZE {
+                let len = data[i..]
+                    .iter()
+                    .zip(&data[candidate..])
+                    .take(MAX_MATCH)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len >= MIN_MATCH {
+                    best = (len, i - candidate);
+                }
+            }
+        }
+        match best {
+            (0, _) => {
+                out.literal(u16::from(data[i]));
+                i += 1;
+            }
+            (len, distance) => {
+                out.copy(len, distance);
+                // Keep the table up to date through the match, so that later
+                // matches can start inside it
+                for j in i + 1..(i + len).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+                    heads[hash(&data[j..j + MIN_MATCH])] = j;
+                }
+                i += len;
+            }
+        }
+    }
+    out.literal(256);
+    out.finish()
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let n = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+    (n.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // The largest run which can't overflow before reducing
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    len: u32,
+}
+
+impl BitWriter {
+    /// Least significant bit first, as for everything but Huffman codes.
+    fn bits(&mut self, value: u32, n: u32) {
+        self.acc |= value << self.len;
+        self.len += n;
+        while self.len >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    /// Huffman codes go most significant bit first.
+    fn code(&mut self, code: u32, n: u32) {
+        self.bits(code.reverse_bits() >> (32 - n), n)
+    }
+
+    fn literal(&mut self, value: u16) {
+        let value = u32::from(value);
+        match value {
+            0..=143 => self.code(0x30 + value, 8),
+            144..=255 => self.code(0x190 + value - 144, 9),
+            256..=279 => self.code(value - 256, 7),
+            _ => self.code(0xc0 + value - 280, 8),
+        }
+    }
+
+    fn copy(&mut self, len: usize, distance: usize) {
+        let i = LENGTH_BASES.partition_point(|base| usize::from(*base) <= len) - 1;
+        self.literal(257 + i as u16);
+        let extra = LENGTH_EXTRA_BITS[i];
+        self.bits((len - usize::from(LENGTH_BASES[i])) as u32, extra.into());
+
+        let i = DISTANCE_BASES.partition_point(|base| usize::from(*base) <= distance) - 1;
+        self.code(i as u32, 5);
+        let extra = DISTANCE_EXTRA_BITS[i];
+        self.bits(
+            (distance - usize::from(DISTANCE_BASES[i])) as u32,
+            extra.into(),
+        );
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+    // Deterministic, and incompressible enough to need stored blocks
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn round_trip(data: &[u8]) {
+        assert_eq!(decompress_to_vec_zlib(&zlib(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn compressible() {
+        let mut data = vec![0; 5000];
+        data.extend(b"abcabcabcabd".repeat(100));
+        data.extend((0..=255).cycle().take(1000));
+        let compressed = zlib(&data);
+        assert!(compressed.len() < data.len() / 10);
+        round_trip(&data);
+    }
+
+    #[test]
+    fn incompressible() {
+        for len in [1000, MAX_STORED_BLOCK, MAX_STORED_BLOCK + 1, 100_000] {
+            let data = noise(len);
+            assert_eq!(zlib(&data).len(), 2 + stored_size(len) + 4);
+            round_trip(&data);
+        }
+    }
+
+    #[test]
+    fn both_block_types() {
+        for data in [&b""[..], b"a", b"aaaa", &noise(1000), &[7; 300]] {
+            let mut out = vec![0x78, 0x01];
+            out.extend(fixed(data));
+            out.extend_from_slice(&adler32(data).to_be_bytes());
+            assert_eq!(decompress_to_vec_zlib(&out).unwrap(), data);
+
+            let mut out = vec![0x78, 0x01];
+            out.extend(stored(data));
+            out.extend_from_slice(&adler32(data).to_be_bytes());
+            assert_eq!(decompress_to_vec_zlib(&out).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn long_distances_and_lengths() {
+        let block = noise(20_000);
+        let mut data = block.clone();
+        data.extend(&block);
+        data.extend(vec![1; 1000]);
+        round_trip(&data);
+    }
+}
@@ -0,0 +1,316 @@
+//! Completed works kept in a buffer of their own, oldest making way for
+//! newest, so that the art and signatures don't take up the heap.
+//!
+//! Each work is laid out in the buffer as:
+//!
+//! ```text
+//! width (u32) | height (u32) | serial (u64) | edition (u64) | edition size (u64)
+//!     | timestamp (u64) | chain head | subject length (u16) | signature length (u16)
+//!     | subject | art | signature
+//! ```
+//!
+//! with integers big-endian, and a subject length of `0xffff` for a work made
+//! from an image. Only where each work starts is kept on the heap.
+
+use alloc::collections::VecDeque;
+use core::fmt;
+use core::str;
+
+pub const CHAIN_HEAD_SIZE: usize = 32;
+
+const HEADER_SIZE: usize = 4 + 4 + 8 + 8 + 8 + 8 + CHAIN_HEAD_SIZE + 2 + 2;
+
+const NO_SUBJECT: u16 = 0xffff;
+
+/// A work as kept in a [`Gallery`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Piece<'a> {
+    /// `None` for a work made from an image.
+    pub subject: Option<&'a str>,
+    pub width: usize,
+    pub height: usize,
+    pub serial: usize,
+    pub edition: usize,
+    pub edition_size: usize,
+    /// Seconds since the Unix epoch when the artist signed the work.
+    pub timestamp: u64,
+    /// The head of the artist's audit log before this work.
+    pub chain_head: [u8; CHAIN_HEAD_SIZE],
+    /// The masterpiece's rows run together.
+    pub pixel_data: &'a [u8],
+    pub signature: &'a [u8],
+}
+
+impl Piece<'_> {
+    fn encoded_size(&self) -> usize {
+        HEADER_SIZE
+            + self.subject.map_or(0, str::len)
+            + self.pixel_data.len()
+            + self.signature.len()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TooLarge;
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "work is too large for the gallery")
+    }
+}
+
+// Where a work lies in the buffer
+struct Slot {
+    number: usize,
+    start: usize,
+    end: usize,
+}
+
+/// Works numbered in the order they were added, from 1, and kept until the
+/// space they take up is needed for newer ones or they're removed.
+pub struct Gallery<T> {
+    buf: T,
+    slots: VecDeque<Slot>,
+    next_number: usize,
+    // Where the next work goes, unless it has to wrap around to the start
+    head: usize,
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Gallery<T> {
+    pub fn new(buf: T) -> Self {
+        Self {
+            buf,
+            slots: VecDeque::new(),
+            next_number: 1,
+            head: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Adds a work, making room for it by letting go of the oldest, and
+    /// returns its number.
+    pub fn push(&mut self, piece: &Piece) -> Result<usize, TooLarge> {
+        let size = piece.encoded_size();
+        let capacity = self.buf.as_ref().len();
+        if size > capacity
+            || piece
+                .subject
+                .map_or(false, |subject| subject.len() >= usize::from(NO_SUBJECT))
+            || piece.signature.len() > usize::from(u16::MAX)
+        {
+            return Err(TooLarge);
+        }
+
+        let mut start = self.head;
+        if start + size > capacity {
+            // Whatever lies between here and the end goes too, since it's
+            // older than what we're about to overwrite at the start
+            while self.slots.front().map_or(false, |slot| slot.start >= start) {
+                self.slots.pop_front();
+            }
+            start = 0;
+        }
+        let end = start + size;
+        while self
+            .slots
+            .front()
+            .map_or(false, |slot| slot.start < end && start < slot.end)
+        {
+            self.slots.pop_front();
+        }
+
+        encode(piece, &mut self.buf.as_mut()[start..end]);
+        let number = self.next_number;
+        self.next_number += 1;
+        self.head = end;
+        self.slots.push_back(Slot { number, start, end });
+        Ok(number)
+    }
+
+    pub fn get(&self, number: usize) -> Option<Piece> {
+        let i = self.find(number)?;
+        let slot = &self.slots[i];
+        Some(decode(&self.buf.as_ref()[slot.start..slot.end]))
+    }
+
+    /// Oldest first, along with their numbers.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Piece)> {
+        self.slots.iter().map(|slot| {
+            (
+                slot.number,
+                decode(&self.buf.as_ref()[slot.start..slot.end]),
+            )
+        })
+    }
+
+    /// Lets go of a work, returning whether there was one by that number. Its
+    /// space is taken up again when newer works reach it.
+    pub fn remove(&mut self, number: usize) -> bool {
+        match self.find(number) {
+            Some(i) => {
+                self.slots.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn find(&self, number: usize) -> Option<usize> {
+        self.slots
+            .binary_search_by_key(&number, |slot| slot.number)
+            .ok()
+    }
+}
+
+fn encode(piece: &Piece, out: &mut [u8]) {
+    let subject = piece.subject.unwrap_or("");
+    let subject_len = match piece.subject {
+        Some(subject) => subject.len() as u16,
+        None => NO_SUBJECT,
+    };
+    let fields = [
+        &(piece.width as u32).to_be_bytes()[..],
+        &(piece.height as u32).to_be_bytes(),
+        &(piece.serial as u64).to_be_bytes(),
+        &(piece.edition as u64).to_be_bytes(),
+        &(piece.edition_size as u64).to_be_bytes(),
+        &piece.timestamp.to_be_bytes(),
+        &piece.chain_head,
+        &subject_len.to_be_bytes(),
+        &(piece.signature.len() as u16).to_be_bytes(),
+        subject.as_bytes(),
+        piece.pixel_data,
+        piece.signature,
+    ];
+    let mut at = 0;
+    for field in fields {
+        out[at..at + field.len()].copy_from_slice(field);
+        at += field.len();
+    }
+}
+
+// Only ever given what `encode` wrote
+fn decode(bytes: &[u8]) -> Piece {
+    let u32_at = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+    let u64_at = |at: usize| u64::from_be_bytes(bytes[at..at + 8].try_into().unwrap());
+    let u16_at = |at: usize| u16::from_be_bytes(bytes[at..at + 2].try_into().unwrap());
+
+    let subject_len = u16_at(40 + CHAIN_HEAD_SIZE);
+    let signature_len = usize::from(u16_at(42 + CHAIN_HEAD_SIZE));
+    let (subject, rest) = match subject_len {
+        NO_SUBJECT => (None, &bytes[HEADER_SIZE..]),
+        len => {
+            let (subject, rest) = bytes[HEADER_SIZE..].split_at(len.into());
+            (str::from_utf8(subject).ok(), rest)
+        }
+    };
+    let (pixel_data, signature) = rest.split_at(rest.len() - signature_len);
+    Piece {
+        subject,
+        width: u32_at(0),
+        height: u32_at(4),
+        serial: u64_at(8) as usize,
+        edition: u64_at(16) as usize,
+        edition_size: u64_at(24) as usize,
+        timestamp: u64_at(32),
+        chain_head: bytes[40..40 + CHAIN_HEAD_SIZE].try_into().unwrap(),
+        pixel_data,
+        signature,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn piece<'a>(subject: Option<&'a str>, serial: usize, pixel_data: &'a [u8]) -> Piece<'a> {
+        Piece {
+            subject,
+            width: pixel_data.len(),
+            height: 1,
+            serial,
+            edition: 1,
+            edition_size: 10,
+            timestamp: 1_700_000_000 + serial as u64,
+            chain_head: [serial as u8; CHAIN_HEAD_SIZE],
+            pixel_data,
+            signature: b"signature",
+        }
+    }
+
+    fn serials(gallery: &Gallery<Vec<u8>>) -> Vec<(usize, usize)> {
+        gallery
+            .iter()
+            .map(|(number, piece)| (number, piece.serial))
+            .collect()
+    }
+
+    #[test]
+    fn push_then_get() {
+        let mut gallery = Gallery::new(vec![0; 0x400]);
+        assert_eq!(gallery.push(&piece(Some("Hi"), 7, b"@%x")), Ok(1));
+        assert_eq!(gallery.push(&piece(None, 8, b"")), Ok(2));
+        assert_eq!(gallery.get(1), Some(piece(Some("Hi"), 7, b"@%x")));
+        assert_eq!(gallery.get(2), Some(piece(None, 8, b"")));
+        assert_eq!(gallery.get(3), None);
+        assert_eq!(serials(&gallery), [(1, 7), (2, 8)]);
+    }
+
+    #[test]
+    fn evicts_oldest_when_full() {
+        let art = [b'x'; 50];
+        let size = piece(Some("ab"), 0, &art).encoded_size();
+        // Room for three, with some left over at the end
+        let mut gallery = Gallery::new(vec![0; size * 3 + size / 2]);
+        for serial in 1..=3 {
+            gallery.push(&piece(Some("ab"), serial, &art)).unwrap();
+        }
+        assert_eq!(serials(&gallery), [(1, 1), (2, 2), (3, 3)]);
+
+        // The fourth wraps around to the start, over the first
+        gallery.push(&piece(Some("ab"), 4, &art)).unwrap();
+        assert_eq!(serials(&gallery), [(2, 2), (3, 3), (4, 4)]);
+        for serial in 5..=7 {
+            gallery.push(&piece(Some("ab"), serial, &art)).unwrap();
+        }
+        assert_eq!(serials(&gallery), [(5, 5), (6, 6), (7, 7)]);
+        assert_eq!(gallery.get(6), Some(piece(Some("ab"), 6, &art)));
+
+        // A larger one takes the place of more than one
+        let large = [b'y'; 100];
+        gallery.push(&piece(None, 8, &large)).unwrap();
+        assert_eq!(serials(&gallery), [(7, 7), (8, 8)]);
+        assert_eq!(gallery.get(8), Some(piece(None, 8, &large)));
+    }
+
+    #[test]
+    fn remove() {
+        let mut gallery = Gallery::new(vec![0; 0x400]);
+        for serial in 1..=3 {
+            gallery.push(&piece(Some("ab"), serial, b"@")).unwrap();
+        }
+        assert!(gallery.remove(2));
+        assert!(!gallery.remove(2));
+        assert!(!gallery.remove(4));
+        assert_eq!(serials(&gallery), [(1, 1), (3, 3)]);
+        assert_eq!(gallery.push(&piece(Some("ab"), 4, b"@")), Ok(4));
+        assert_eq!(gallery.len(), 3);
+    }
+
+    #[test]
+    fn too_large() {
+        let mut gallery = Gallery::new(vec![0; 0x40]);
+        assert_eq!(gallery.push(&piece(None, 1, &[0; 0x40])), Err(TooLarge));
+        assert!(gallery.is_empty());
+    }
+}
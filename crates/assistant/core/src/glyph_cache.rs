@@ -0,0 +1,247 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use rusttype::{point, Point, ScaledGlyph};
+
+use crate::nostd_float::FloatExt;
+
+// Glyphs are positioned to within this fraction of a pixel, so that one
+// rasterisation serves a glyph at nearby positions
+const SUBPIXEL_STEPS: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    glyph: u16,
+    // The bits of each dimension of the scale
+    scale: (u32, u32),
+    // In steps of 1/SUBPIXEL_STEPS of a pixel
+    offset: (u8, u8),
+}
+
+/// A glyph's coverage, relative to the whole pixel that it is positioned
+/// within.
+pub(crate) struct Rasterised {
+    pub(crate) min: Point<i32>,
+    pub(crate) width: usize,
+    pub(crate) coverage: Vec<u8>,
+}
+
+struct Entry {
+    glyph: Rasterised,
+    last_used: u64,
+}
+
+impl Entry {
+    fn cost(&self) -> usize {
+        mem::size_of::<Key>() + mem::size_of::<Self>() + self.glyph.coverage.capacity()
+    }
+}
+
+/// Rasterised glyphs, kept between drafts since rasterising dominates the cost
+/// of rendering. Bounded by the number of bytes it occupies on the heap, and
+/// least recently used glyphs are evicted first.
+pub struct GlyphCache {
+    entries: BTreeMap<Key, Entry>,
+    size: usize,
+    max_size: usize,
+    clock: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl GlyphCache {
+    /// A `max_size` of zero disables caching.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            size: 0,
+            max_size,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Passes `f` the rasterisation of `glyph` at `position`, along with the
+    /// whole pixel which that rasterisation is relative to.
+    pub(crate) fn draw(
+        &mut self,
+        glyph: &ScaledGlyph<'_>,
+        position: Point<f32>,
+        f: impl FnOnce(Point<i32>, &Rasterised),
+    ) {
+        let (x, offset_x) = quantise(position.x);
+        let (y, offset_y) = quantise(position.y);
+        let origin = point(x, y);
+        let scale = glyph.scale();
+        let key = Key {
+            glyph: glyph.id().0,
+            scale: (scale.x.to_bits(), scale.y.to_bits()),
+            offset: (offset_x, offset_y),
+        };
+
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.hits += 1;
+            entry.last_used = self.clock;
+            f(origin, &entry.glyph);
+            return;
+        }
+        self.misses += 1;
+
+        let entry = Entry {
+            glyph: rasterise(glyph, offset_x, offset_y),
+            last_used: self.clock,
+        };
+        f(origin, &entry.glyph);
+
+        let cost = entry.cost();
+        if cost > self.max_size {
+            return;
+        }
+        while self.size + cost > self.max_size {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            match oldest.and_then(|key| self.entries.remove(&key)) {
+                Some(evicted) => self.size -= evicted.cost(),
+                None => break,
+            }
+        }
+        self.size += cost;
+        self.entries.insert(key, entry);
+    }
+}
+
+fn quantise(x: f32) -> (i32, u8) {
+    let whole = FloatExt::floor(x);
+    let steps = ((x - whole) * f32::from(SUBPIXEL_STEPS) + 0.5) as u8;
+    if steps == SUBPIXEL_STEPS {
+        (whole as i32 + 1, 0)
+    } else {
+        (whole as i32, steps)
+    }
+}
+
+fn rasterise(glyph: &ScaledGlyph<'_>, offset_x: u8, offset_y: u8) -> Rasterised {
+    let steps = f32::from(SUBPIXEL_STEPS);
+    let glyph = glyph.clone().positioned(point(
+        f32::from(offset_x) / steps,
+        f32::from(offset_y) / steps,
+    ));
+    let Some(bb) = glyph.pixel_bounding_box() else {
+        return Rasterised {
+            min: point(0, 0),
+            width: 0,
+            coverage: vec![],
+        };
+    };
+    let width = bb.width() as usize;
+    let mut coverage = vec![0; width * bb.height() as usize];
+    glyph.draw(|x, y, v| {
+        coverage[x as usize + y as usize * width] = (v * 255.0 + 0.5) as u8;
+    });
+    Rasterised {
+        min: bb.min,
+        width,
+        coverage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusttype::Scale;
+
+    use crate::typeface;
+
+    fn glyph(c: char) -> ScaledGlyph<'static> {
+        typeface().font().glyph(c).scaled(Scale::uniform(12.0))
+    }
+
+    fn draw(cache: &mut GlyphCache, c: char, x: f32) -> (Point<i32>, Vec<u8>) {
+        let mut out = None;
+        cache.draw(&glyph(c), point(x, 10.0), |origin, rasterised| {
+            out = Some((origin, rasterised.coverage.clone()))
+        });
+        out.unwrap()
+    }
+
+    #[test]
+    fn quantisation() {
+        assert_eq!(quantise(3.0), (3, 0));
+        assert_eq!(quantise(3.3), (3, 1));
+        assert_eq!(quantise(3.9), (4, 0));
+        assert_eq!(quantise(-0.1), (0, 0));
+        assert_eq!(quantise(-0.3), (-1, 3));
+    }
+
+    #[test]
+    fn hits_at_nearby_positions() {
+        let mut cache = GlyphCache::new(0x1_000);
+        let (origin, a) = draw(&mut cache, 'a', 5.26);
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+        let (other_origin, b) = draw(&mut cache, 'a', 7.24);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(a, b);
+        assert_eq!(other_origin.x - origin.x, 2);
+        draw(&mut cache, 'a', 5.5);
+        draw(&mut cache, 'b', 5.26);
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+    }
+
+    #[test]
+    fn same_coverage_with_caching_disabled() {
+        let mut cache = GlyphCache::new(0);
+        assert_eq!(draw(&mut cache, 'x', 1.0), draw(&mut cache, 'x', 1.0));
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut one = GlyphCache::new(0x1_000);
+        draw(&mut one, 'W', 0.0);
+        let max_size = one.size() * 2 + one.size() / 2;
+
+        let mut cache = GlyphCache::new(max_size);
+        draw(&mut cache, 'W', 0.0);
+        draw(&mut cache, 'W', 0.5);
+        draw(&mut cache, 'W', 0.0);
+        draw(&mut cache, 'W', 0.25);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.size() <= max_size);
+
+        let misses = cache.misses();
+        draw(&mut cache, 'W', 0.0);
+        draw(&mut cache, 'W', 0.25);
+        assert_eq!(cache.misses(), misses);
+        draw(&mut cache, 'W', 0.5);
+        assert_eq!(cache.misses(), misses + 1);
+    }
+}
@@ -0,0 +1,223 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use core::fmt;
+use core::mem;
+
+pub const FINGERPRINT_SIZE: usize = 8;
+
+pub type Fingerprint = [u8; FINGERPRINT_SIZE];
+
+pub struct Entry {
+    pub number: usize,
+    pub subject: String,
+    pub fingerprint: Fingerprint,
+}
+
+impl Entry {
+    fn cost(&self) -> usize {
+        mem::size_of::<Self>() + self.subject.capacity()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventNotFound<'a>(pub &'a str);
+
+impl fmt::Display for EventNotFound<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: event not found", self.0)
+    }
+}
+
+/// A ring of past subjects, bounded by the number of bytes it occupies on the
+/// heap rather than by the number of entries.
+pub struct History {
+    entries: VecDeque<Entry>,
+    next_number: usize,
+    size: usize,
+    max_size: usize,
+    browsing: Option<usize>,
+    stash: String,
+}
+
+impl History {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            next_number: 1,
+            size: 0,
+            max_size,
+            browsing: None,
+            stash: String::new(),
+        }
+    }
+
+    pub fn push(&mut self, subject: &str, fingerprint: Fingerprint) -> usize {
+        let number = self.next_number;
+        self.restore(number, subject, fingerprint);
+        number
+    }
+
+    /// Adds an entry kept from before a restart, under the number it had
+    /// then. Entries are restored oldest first, and before any are pushed, so
+    /// that numbers only ever go up. There may be gaps between them.
+    pub fn restore(&mut self, number: usize, subject: &str, fingerprint: Fingerprint) {
+        self.end_browsing();
+        self.next_number = number + 1;
+        let entry = Entry {
+            number,
+            subject: subject.into(),
+            fingerprint,
+        };
+        self.size += entry.cost();
+        self.entries.push_back(entry);
+        while self.size > self.max_size {
+            match self.entries.pop_front() {
+                Some(evicted) => self.size -= evicted.cost(),
+                None => break,
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, number: usize) -> Option<&Entry> {
+        let i = self
+            .entries
+            .binary_search_by_key(&number, |entry| entry.number)
+            .ok()?;
+        self.entries.get(i)
+    }
+
+    pub fn first(&self) -> Option<&Entry> {
+        self.entries.front()
+    }
+
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    /// Resolves `!!` and `!n` references to past entries. Lines which are not
+    /// history references yield `Ok(None)`.
+    pub fn expand<'a>(&'a self, line: &'a str) -> Result<Option<&'a Entry>, EventNotFound<'a>> {
+        let entry = match line.strip_prefix('!') {
+            Some("!") => self.last(),
+            Some(number) if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => {
+                number.parse().ok().and_then(|number| self.get(number))
+            }
+            _ => return Ok(None),
+        };
+        entry.map(Some).ok_or(EventNotFound(line))
+    }
+
+    /// Steps back through history. `current` is the line being edited, which
+    /// is restored once the user steps forward past the most recent entry.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let i = match self.browsing {
+            None => {
+                let i = self.entries.len().checked_sub(1)?;
+                self.stash.clear();
+                self.stash.push_str(current);
+                i
+            }
+            Some(i) => i.checked_sub(1)?,
+        };
+        self.browsing = Some(i);
+        Some(&self.entries[i].subject)
+    }
+
+    pub fn newer(&mut self) -> Option<&str> {
+        let i = self.browsing? + 1;
+        if i < self.entries.len() {
+            self.browsing = Some(i);
+            Some(&self.entries[i].subject)
+        } else {
+            self.browsing = None;
+            Some(&self.stash)
+        }
+    }
+
+    pub fn end_browsing(&mut self) {
+        self.browsing = None;
+        self.stash.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    fn subjects(history: &History) -> Vec<(usize, &str)> {
+        history
+            .iter()
+            .map(|entry| (entry.number, entry.subject.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn evicts_oldest_when_over_budget() {
+        let entry_size = mem::size_of::<Entry>() + 4;
+        let mut history = History::new(entry_size * 3);
+        for subject in ["aaaa", "bbbb", "cccc", "dddd"] {
+            history.push(subject, [0; FINGERPRINT_SIZE]);
+        }
+        assert_eq!(subjects(&history), [(2, "bbbb"), (3, "cccc"), (4, "dddd")]);
+        assert!(history.get(1).is_none());
+        assert_eq!(history.get(3).unwrap().subject, "cccc");
+        assert!(history.get(5).is_none());
+    }
+
+    #[test]
+    fn restore_then_push() {
+        let mut history = History::new(4096);
+        history.restore(3, "three", [3; FINGERPRINT_SIZE]);
+        history.restore(7, "seven", [7; FINGERPRINT_SIZE]);
+        assert_eq!(history.push("eight", [8; FINGERPRINT_SIZE]), 8);
+        assert_eq!(
+            subjects(&history),
+            [(3, "three"), (7, "seven"), (8, "eight")]
+        );
+        assert_eq!(history.get(7).unwrap().subject, "seven");
+        assert!(history.get(5).is_none());
+        assert_eq!(history.expand("!3").unwrap().unwrap().subject, "three");
+    }
+
+    #[test]
+    fn expand() {
+        let mut history = History::new(4096);
+        assert_eq!(history.expand("!!").err(), Some(EventNotFound("!!")));
+        history.push("one", [1; FINGERPRINT_SIZE]);
+        history.push("two", [2; FINGERPRINT_SIZE]);
+        assert_eq!(history.expand("!!").unwrap().unwrap().subject, "two");
+        assert_eq!(history.expand("!1").unwrap().unwrap().subject, "one");
+        assert_eq!(history.expand("!3").err(), Some(EventNotFound("!3")));
+        assert_eq!(
+            history.expand("!99999999999999999999999").err(),
+            Some(EventNotFound("!99999999999999999999999"))
+        );
+        assert!(history.expand("!").unwrap().is_none());
+        assert!(history.expand("!x").unwrap().is_none());
+        assert!(history.expand("hi!!").unwrap().is_none());
+    }
+
+    #[test]
+    fn browse() {
+        let mut history = History::new(4096);
+        assert_eq!(history.older("draft"), None);
+        history.push("one", [0; FINGERPRINT_SIZE]);
+        history.push("two", [0; FINGERPRINT_SIZE]);
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older("draft"), Some("two"));
+        assert_eq!(history.older("two"), Some("one"));
+        assert_eq!(history.older("one"), None);
+        assert_eq!(history.newer(), Some("two"));
+        assert_eq!(history.newer(), Some("draft"));
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older("again"), Some("two"));
+        history.end_browsing();
+        assert_eq!(history.newer(), None);
+    }
+}
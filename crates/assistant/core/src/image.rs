@@ -0,0 +1,317 @@
+//! Greyscale images as an alternative to text for the subject of a draft,
+//! pasted over the console as base64 between [`BEGIN_MARKER`] and
+//! [`END_MARKER`] lines.
+//!
+//! Pixel values are luminance, as for a [`Draft`](crate::Draft). Anything
+//! transparent is composited over black, which is the background of a draft.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::base64;
+
+use crate::nostd_float::FloatExt;
+
+mod inflate;
+mod png;
+
+pub const BEGIN_MARKER: &str = "-----BEGIN IMAGE-----";
+pub const END_MARKER: &str = "-----END IMAGE-----";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixel_data: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// More was pasted than there's room to keep.
+    PasteTooLong,
+    /// The paste isn't base64.
+    Base64,
+    /// Neither a PGM nor a PNG.
+    UnknownFormat,
+    Malformed(&'static str),
+    Unsupported(&'static str),
+    /// Too many pixels, or too much data for them.
+    TooLarge,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PasteTooLong => write!(f, "image is too long to paste"),
+            Self::Base64 => write!(f, "image is not valid base64"),
+            Self::UnknownFormat => write!(f, "image is neither a PGM nor a PNG"),
+            Self::Malformed(what) => write!(f, "malformed image: {}", what),
+            Self::Unsupported(what) => write!(f, "unsupported image: {}", what),
+            Self::TooLarge => write!(f, "image has too many pixels"),
+        }
+    }
+}
+
+/// Decodes a PGM (binary or plain) or a PNG of at most `max_pixels` pixels.
+pub fn decode(data: &[u8], max_pixels: usize) -> Result<Image, ImageError> {
+    if data.starts_with(png::SIGNATURE) {
+        png::decode(data, max_pixels)
+    } else if data.starts_with(b"P5") || data.starts_with(b"P2") {
+        pgm(data, max_pixels)
+    } else {
+        Err(ImageError::UnknownFormat)
+    }
+}
+
+// Netpbm: a magic number, then width, height, and maximum value in ASCII,
+// separated by whitespace and comments
+fn pgm(data: &[u8], max_pixels: usize) -> Result<Image, ImageError> {
+    let plain = data.starts_with(b"P2");
+    let mut rest = &data[2..];
+    let mut fields = [0; 3];
+    for field in &mut fields {
+        *field = pgm_number(&mut rest).ok_or(ImageError::Malformed("bad header"))?;
+    }
+    let [width, height, max_value] = fields;
+    if !(1..=0xffff).contains(&max_value) {
+        return Err(ImageError::Malformed("bad maximum value"));
+    }
+    let len = checked_area(width, height, max_pixels)?;
+
+    let scale = |value: usize| -> Result<u8, ImageError> {
+        if value > max_value {
+            return Err(ImageError::Malformed("value out of range"));
+        }
+        Ok(((value * 255 + max_value / 2) / max_value) as u8)
+    };
+    let mut pixel_data = Vec::with_capacity(len);
+    if plain {
+        for _ in 0..len {
+            let value = pgm_number(&mut rest).ok_or(ImageError::Malformed("truncated"))?;
+            pixel_data.push(scale(value)?);
+        }
+    } else {
+        // A single whitespace character separates the header from the raster
+        rest = rest.get(1..).ok_or(ImageError::Malformed("truncated"))?;
+        let sample_size = if max_value > 0xff { 2 } else { 1 };
+        let raster = rest
+            .get(..len * sample_size)
+            .ok_or(ImageError::Malformed("truncated"))?;
+        for sample in raster.chunks(sample_size) {
+            let value = sample.iter().fold(0, |acc, b| acc << 8 | usize::from(*b));
+            pixel_data.push(scale(value)?);
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        pixel_data,
+    })
+}
+
+fn pgm_number(rest: &mut &[u8]) -> Option<usize> {
+    loop {
+        match rest.first()? {
+            b'#' => {
+                let end = rest.iter().position(|b| *b == b'\n')?;
+                *rest = &rest[end..];
+            }
+            b if b.is_ascii_whitespace() => *rest = &rest[1..],
+            _ => break,
+        }
+    }
+    let len = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let (digits, after) = rest.split_at(len);
+    *rest = after;
+    digits
+        .iter()
+        .try_fold(0usize, |acc, d| {
+            acc.checked_mul(10)?.checked_add(usize::from(d - b'0'))
+        })
+        .filter(|_| len > 0)
+}
+
+fn checked_area(width: usize, height: usize, max_pixels: usize) -> Result<usize, ImageError> {
+    if width == 0 || height == 0 {
+        return Err(ImageError::Malformed("no pixels"));
+    }
+    match width.checked_mul(height) {
+        Some(len) if len <= max_pixels => Ok(len),
+        _ => Err(ImageError::TooLarge),
+    }
+}
+
+impl Image {
+    /// Scales to exactly `width` by `height`, each new pixel averaging the
+    /// area of the image that it covers.
+    ///
+    /// Each pixel is worked out in full on its own, so that nothing besides
+    /// the result has to be kept while scaling.
+    pub fn resample(&self, width: usize, height: usize) -> Image {
+        let pixel = |x: usize, y: usize| -> f32 {
+            weights(self.height, height, y)
+                .map(|(j, wy)| {
+                    weights(self.width, width, x)
+                        .map(|(i, wx)| f32::from(self.pixel_data[i + j * self.width]) * wx)
+                        .sum::<f32>()
+                        * wy
+                })
+                .sum()
+        };
+        let mut pixel_data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixel_data.push((pixel(x, y) + 0.5).clamp(0.0, 255.0) as u8);
+            }
+        }
+        Image {
+            width,
+            height,
+            pixel_data,
+        }
+    }
+}
+
+// For the `i`th of `to` pixels along an axis, the pixels among `from` that it
+// covers and by how much, normalised to sum to one
+fn weights(from: usize, to: usize, i: usize) -> impl Iterator<Item = (usize, f32)> {
+    let step = from as f32 / to as f32;
+    let start = i as f32 * step;
+    let end = start + step;
+    let first = start as usize;
+    let last = ((FloatExt::ceil(end) as usize).min(from)).max(first + 1);
+    (first..last).map(move |j| {
+        let covered = (end.min(j as f32 + 1.0) - start.max(j as f32)).max(0.0);
+        (j, covered / step)
+    })
+}
+
+/// Base64 lines gathered between the markers, bounded so that a paste can't
+/// take all of the heap.
+pub struct Paste {
+    base64: String,
+    max_len: usize,
+    too_long: bool,
+}
+
+impl Paste {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            base64: String::new(),
+            max_len,
+            too_long: false,
+        }
+    }
+
+    /// Once the paste grows too long, the rest of it is dropped as it arrives,
+    /// and [`Self::finish`] fails.
+    pub fn push_line(&mut self, line: &str) {
+        let line = line.trim();
+        if self.too_long || self.base64.len() + line.len() > self.max_len {
+            self.too_long = true;
+            self.base64 = String::new();
+            return;
+        }
+        self.base64.push_str(line);
+    }
+
+    pub fn finish(self, max_pixels: usize) -> Result<Image, ImageError> {
+        if self.too_long {
+            return Err(ImageError::PasteTooLong);
+        }
+        let data = base64::decode(&self.base64).ok_or(ImageError::Base64)?;
+        // Decoding needs room of its own
+        drop(self.base64);
+        decode(&data, max_pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::format;
+    use alloc::vec;
+
+    #[test]
+    fn binary_pgm() {
+        let mut data = b"P5\n# a comment\n3 2\n255\n".to_vec();
+        data.extend([0, 128, 255, 10, 20, 30]);
+        let image = decode(&data, 6).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.pixel_data, [0, 128, 255, 10, 20, 30]);
+        assert_eq!(decode(&data, 5), Err(ImageError::TooLarge));
+        assert_eq!(
+            decode(&data[..data.len() - 1], 6),
+            Err(ImageError::Malformed("truncated"))
+        );
+    }
+
+    #[test]
+    fn plain_and_deep_pgm() {
+        let image = decode(b"P2 2 2 15  0 15\n 5 # comment\n 10", 4).unwrap();
+        assert_eq!(image.pixel_data, [0, 255, 85, 170]);
+
+        let mut data = b"P5 2 1 65535\n".to_vec();
+        data.extend([0xff, 0xff, 0x80, 0x00]);
+        assert_eq!(decode(&data, 2).unwrap().pixel_data, [255, 128]);
+
+        assert_eq!(
+            decode(b"P2 1 1 15 16", 1),
+            Err(ImageError::Malformed("value out of range"))
+        );
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(decode(b"GIF89a", 100), Err(ImageError::UnknownFormat));
+        assert_eq!(decode(b"", 100), Err(ImageError::UnknownFormat));
+    }
+
+    #[test]
+    fn resample_averages() {
+        let image = Image {
+            width: 4,
+            height: 2,
+            pixel_data: vec![0, 100, 200, 40, 0, 100, 200, 40],
+        };
+        assert_eq!(image.resample(2, 1).pixel_data, [50, 120]);
+        assert_eq!(image.resample(4, 2), image);
+        // Upscaling repeats pixels
+        assert_eq!(
+            image.resample(8, 1).pixel_data,
+            [0, 0, 100, 100, 200, 200, 40, 40]
+        );
+        // Uneven steps split pixels between neighbours
+        assert_eq!(image.resample(3, 1).pixel_data, [25, 150, 80]);
+        // Scaling down and back up again
+        let tall = image.resample(1, 6);
+        assert_eq!(tall.pixel_data, [85; 6]);
+        assert_eq!(tall.resample(2, 3).pixel_data, [85; 6]);
+    }
+
+    #[test]
+    fn paste() {
+        let mut data = b"P5 2 1 255\n".to_vec();
+        data.extend([7, 9]);
+        // Produced with `base64 -w 8`
+        let encoded = ["UDUgMiAx", "IDI1NQoH", "CQ=="];
+        let mut paste = Paste::new(100);
+        for line in encoded {
+            paste.push_line(&format!("  {}\r", line));
+        }
+        assert_eq!(paste.finish(2).unwrap().pixel_data, [7, 9]);
+
+        // The rest of a paste that's too long is dropped
+        let mut paste = Paste::new(10);
+        paste.push_line("UDUgMiAx");
+        paste.push_line("IDI1");
+        paste.push_line("Cg==");
+        assert_eq!(paste.finish(2), Err(ImageError::PasteTooLong));
+
+        let mut paste = Paste::new(10);
+        paste.push_line("!!!!");
+        assert_eq!(paste.finish(2), Err(ImageError::Base64));
+    }
+}
@@ -0,0 +1,358 @@
+//! A zlib decoder, for the image data of PNGs. Output is bounded, since it's
+//! kept on a small heap and the input comes from whoever is at the console.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::export::deflate::{adler32, DISTANCE_BASES, DISTANCE_EXTRA_BITS};
+use crate::export::deflate::{LENGTH_BASES, LENGTH_EXTRA_BITS};
+
+const MAX_BITS: usize = 15;
+
+// The order in which code length code lengths are sent
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum InflateError {
+    Malformed,
+    TooLarge,
+}
+
+/// The data in a zlib stream, so long as it's at most `max_len` bytes.
+pub(super) fn zlib(data: &[u8], max_len: usize) -> Result<Vec<u8>, InflateError> {
+    let [cmf, flg, ref rest @ ..] = *data else {
+        return Err(InflateError::Malformed);
+    };
+    // Deflate with a window of at most 32K, no preset dictionary
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 || flg & 0x20 != 0 {
+        return Err(InflateError::Malformed);
+    }
+    if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(InflateError::Malformed);
+    }
+
+    let mut input = BitReader::new(rest);
+    let mut out = Vec::new();
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => stored(&mut input, &mut out, max_len)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(&mut input, &mut out, max_len, &literals, &distances)?
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut input)?;
+                codes(&mut input, &mut out, max_len, &literals, &distances)?
+            }
+            _ => return Err(InflateError::Malformed),
+        }
+        if last {
+            break;
+        }
+    }
+
+    let checksum = input.trailer()?;
+    if checksum != adler32(&out) {
+        return Err(InflateError::Malformed);
+    }
+    Ok(out)
+}
+
+fn stored(input: &mut BitReader, out: &mut Vec<u8>, max_len: usize) -> Result<(), InflateError> {
+    let block = input.stored_block()?;
+    if out.len() + block.len() > max_len {
+        return Err(InflateError::TooLarge);
+    }
+    out.extend_from_slice(block);
+    Ok(())
+}
+
+fn codes(
+    input: &mut BitReader,
+    out: &mut Vec<u8>,
+    max_len: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = literals.decode(input)?;
+        match symbol {
+            0..=255 => {
+                if out.len() >= max_len {
+                    return Err(InflateError::TooLarge);
+                }
+                out.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let i = usize::from(symbol - 257);
+                let len = usize::from(LENGTH_BASES[i])
+                    + input.bits(LENGTH_EXTRA_BITS[i].into())? as usize;
+                let i = usize::from(distances.decode(input)?);
+                if i >= DISTANCE_BASES.len() {
+                    return Err(InflateError::Malformed);
+                }
+                let distance = usize::from(DISTANCE_BASES[i])
+                    + input.bits(DISTANCE_EXTRA_BITS[i].into())? as usize;
+                if distance > out.len() {
+                    return Err(InflateError::Malformed);
+                }
+                if out.len() + len > max_len {
+                    return Err(InflateError::TooLarge);
+                }
+                // Byte by byte, since the copy may overlap what it produces
+                let start = out.len() - distance;
+                for j in 0..len {
+                    out.push(out[start + j]);
+                }
+            }
+            _ => return Err(InflateError::Malformed),
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    // Both tables are complete, so building them can't fail
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&[5; 30]).unwrap(),
+    )
+}
+
+fn dynamic_codes(input: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let literal_count = input.bits(5)? as usize + 257;
+    let distance_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0; 19];
+    for i in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_length_lengths[*i] = input.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    // Literal/length and distance code lengths run together, and repeats may
+    // cross from one to the other
+    let mut lengths = [0; 286 + 30];
+    let total = literal_count + distance_count;
+    let mut i = 0;
+    while i < total {
+        let (value, repeat) = match code_lengths.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => match i.checked_sub(1) {
+                Some(previous) => (lengths[previous], 3 + input.bits(2)? as usize),
+                None => return Err(InflateError::Malformed),
+            },
+            17 => (0, 3 + input.bits(3)? as usize),
+            18 => (0, 11 + input.bits(7)? as usize),
+            _ => return Err(InflateError::Malformed),
+        };
+        if i + repeat > total {
+            return Err(InflateError::Malformed);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(InflateError::Malformed);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..total])?,
+    ))
+}
+
+// A canonical Huffman code, decoded a bit at a time
+struct Huffman {
+    // The number of codes of each length
+    counts: [u16; MAX_BITS + 1],
+    // Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0; MAX_BITS + 1];
+        for len in lengths {
+            counts[usize::from(*len)] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject codes which assign more codes of some length than there's
+        // room for. Incomplete codes are allowed, as for a lone distance code.
+        let mut left = 1i32;
+        for count in &counts[1..] {
+            left = left * 2 - i32::from(*count);
+            if left < 0 {
+                return Err(InflateError::Malformed);
+            }
+        }
+
+        let mut offsets = [0; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_BITS + 1])];
+        for (symbol, len) in lengths.iter().enumerate() {
+            if *len != 0 {
+                let offset = &mut offsets[usize::from(*len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<u16, InflateError> {
+        // The first code of each length, and the index of its symbol
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for count in &self.counts[1..] {
+            code |= input.bits(1)? as i32;
+            let count = i32::from(*count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Malformed)
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u32,
+    len: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            acc: 0,
+            len: 0,
+        }
+    }
+
+    /// Least significant bit first.
+    fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        while self.len < n {
+            let byte = *self.data.get(self.pos).ok_or(InflateError::Malformed)?;
+            self.pos += 1;
+            self.acc |= u32::from(byte) << self.len;
+            self.len += 8;
+        }
+        let value = self.acc & ((1 << n) - 1);
+        self.acc >>= n;
+        self.len -= n;
+        Ok(value)
+    }
+
+    // Whatever is left of the current byte is padding
+    fn align(&mut self) {
+        self.acc = 0;
+        self.len = 0;
+    }
+
+    fn stored_block(&mut self) -> Result<&'a [u8], InflateError> {
+        self.align();
+        let header = self
+            .data
+            .get(self.pos..self.pos + 4)
+            .ok_or(InflateError::Malformed)?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        let nlen = u16::from_le_bytes([header[2], header[3]]);
+        if len != !nlen {
+            return Err(InflateError::Malformed);
+        }
+        let start = self.pos + 4;
+        let end = start + usize::from(len);
+        let block = self.data.get(start..end).ok_or(InflateError::Malformed)?;
+        self.pos = end;
+        Ok(block)
+    }
+
+    fn trailer(&mut self) -> Result<u32, InflateError> {
+        self.align();
+        let trailer = self
+            .data
+            .get(self.pos..self.pos + 4)
+            .ok_or(InflateError::Malformed)?;
+        Ok(u32::from_be_bytes(trailer.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    use crate::export::deflate;
+
+    fn sample() -> Vec<u8> {
+        let mut data = vec![0; 3000];
+        data.extend(b"the quick brown fox jumps over the lazy dog ".repeat(50));
+        data.extend((0..=255).cycle().take(2000));
+        data
+    }
+
+    #[test]
+    fn our_own_encoding() {
+        for data in [&b""[..], b"a", &sample()] {
+            assert_eq!(zlib(&deflate::zlib(data), usize::MAX).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn dynamic_codes_from_miniz() {
+        let data = sample();
+        for level in [1, 6, 10] {
+            let compressed = compress_to_vec_zlib(&data, level);
+            assert_eq!(zlib(&compressed, usize::MAX).unwrap(), data);
+        }
+        // Level 0 only uses stored blocks
+        let compressed = compress_to_vec_zlib(&data, 0);
+        assert_eq!(zlib(&compressed, usize::MAX).unwrap(), data);
+    }
+
+    #[test]
+    fn bounded_output() {
+        let data = sample();
+        let compressed = compress_to_vec_zlib(&data, 6);
+        assert_eq!(zlib(&compressed, data.len()).unwrap(), data);
+        assert_eq!(
+            zlib(&compressed, data.len() - 1),
+            Err(InflateError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn corruption() {
+        let data = sample();
+        let compressed = compress_to_vec_zlib(&data, 6);
+        // Every truncation fails cleanly
+        for len in 0..compressed.len() {
+            assert!(zlib(&compressed[..len], usize::MAX).is_err());
+        }
+        let mut checksum = compressed.clone();
+        *checksum.last_mut().unwrap() ^= 1;
+        assert_eq!(zlib(&checksum, usize::MAX), Err(InflateError::Malformed));
+        let mut header = compressed;
+        header[0] = 0x79;
+        assert_eq!(zlib(&header, usize::MAX), Err(InflateError::Malformed));
+    }
+}
@@ -0,0 +1,452 @@
+//! PNG decoding, for every colour type and bit depth but without interlacing.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::inflate::{self, InflateError};
+use super::{checked_area, Image, ImageError};
+use crate::export::crc32;
+
+pub(super) const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Clone, Copy)]
+enum ColourType {
+    Grey,
+    Rgb,
+    Palette,
+    GreyAlpha,
+    Rgba,
+}
+
+impl ColourType {
+    fn channels(self) -> usize {
+        match self {
+            Self::Grey | Self::Palette => 1,
+            Self::GreyAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    colour_type: ColourType,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, ImageError> {
+        let &[w0, w1, w2, w3, h0, h1, h2, h3, bit_depth, colour_type, compression, filter, interlace] =
+            data
+        else {
+            return Err(ImageError::Malformed("bad header"));
+        };
+        let colour_type = match (colour_type, bit_depth) {
+            (0, 1 | 2 | 4 | 8 | 16) => ColourType::Grey,
+            (2, 8 | 16) => ColourType::Rgb,
+            (3, 1 | 2 | 4 | 8) => ColourType::Palette,
+            (4, 8 | 16) => ColourType::GreyAlpha,
+            (6, 8 | 16) => ColourType::Rgba,
+            _ => return Err(ImageError::Malformed("bad colour type or bit depth")),
+        };
+        if compression != 0 || filter != 0 {
+            return Err(ImageError::Malformed("bad compression or filter method"));
+        }
+        if interlace != 0 {
+            return Err(ImageError::Unsupported("interlacing"));
+        }
+        Ok(Self {
+            width: u32::from_be_bytes([w0, w1, w2, w3]) as usize,
+            height: u32::from_be_bytes([h0, h1, h2, h3]) as usize,
+            bit_depth,
+            colour_type,
+        })
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.colour_type.channels() * usize::from(self.bit_depth)
+    }
+
+    // Not counting the filter type byte
+    fn scanline_len(&self) -> usize {
+        (self.width * self.bits_per_pixel() + 7) / 8
+    }
+}
+
+pub(super) fn decode(data: &[u8], max_pixels: usize) -> Result<Image, ImageError> {
+    let mut rest = &data[SIGNATURE.len()..];
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+    loop {
+        let (Chunk { ty, body }, after) = Chunk::parse(rest)?;
+        rest = after;
+        match (&ty, &header) {
+            (b"IHDR", None) => header = Some(Header::parse(body)?),
+            (b"IHDR", Some(_)) | (_, None) => {
+                return Err(ImageError::Malformed("IHDR must come first"))
+            }
+            (b"PLTE", _) => palette = body,
+            (b"tRNS", _) => transparency = body,
+            (b"IDAT", _) => compressed.extend_from_slice(body),
+            (b"IEND", _) => break,
+            // Ancillary chunks can be ignored, but not critical ones
+            _ if ty[0].is_ascii_lowercase() => {}
+            _ => return Err(ImageError::Unsupported("critical chunk")),
+        }
+    }
+    // Present, since only IHDR could have come first
+    let header = header.unwrap();
+    let len = checked_area(header.width, header.height, max_pixels)?;
+
+    let scanline_len = header.scanline_len();
+    let raw_len = (scanline_len + 1) * header.height;
+    // Enough for one 8-bit RGBA image at the largest size
+    if raw_len > max_pixels.saturating_mul(4).saturating_add(header.height) {
+        return Err(ImageError::TooLarge);
+    }
+    let mut raw = inflate::zlib(&compressed, raw_len).map_err(|err| match err {
+        InflateError::Malformed => ImageError::Malformed("bad image data"),
+        InflateError::TooLarge => ImageError::Malformed("too much image data"),
+    })?;
+    drop(compressed);
+    if raw.len() != raw_len {
+        return Err(ImageError::Malformed("too little image data"));
+    }
+
+    unfilter(&mut raw, scanline_len, header.bits_per_pixel())?;
+
+    let mut pixel_data = Vec::with_capacity(len);
+    for scanline in raw.chunks(scanline_len + 1) {
+        let samples = Samples::new(&scanline[1..], header.bit_depth);
+        match header.colour_type {
+            ColourType::Grey => pixel_data.extend(samples.take(header.width)),
+            ColourType::Rgb => {
+                let mut samples = samples;
+                for _ in 0..header.width {
+                    let mut next = || samples.next().unwrap_or(0);
+                    pixel_data.push(luminance(next(), next(), next()));
+                }
+            }
+            ColourType::Palette => {
+                for index in Samples::indices(&scanline[1..], header.bit_depth).take(header.width) {
+                    let i = usize::from(index);
+                    let rgb = palette
+                        .get(3 * i..3 * i + 3)
+                        .ok_or(ImageError::Malformed("index beyond palette"))?;
+                    let alpha = transparency.get(i).copied().unwrap_or(0xff);
+                    pixel_data.push(over_black(luminance(rgb[0], rgb[1], rgb[2]), alpha));
+                }
+            }
+            ColourType::GreyAlpha => {
+                let mut samples = samples;
+                for _ in 0..header.width {
+                    let mut next = || samples.next().unwrap_or(0);
+                    let (grey, alpha) = (next(), next());
+                    pixel_data.push(over_black(grey, alpha));
+                }
+            }
+            ColourType::Rgba => {
+                let mut samples = samples;
+                for _ in 0..header.width {
+                    let mut next = || samples.next().unwrap_or(0);
+                    let grey = luminance(next(), next(), next());
+                    pixel_data.push(over_black(grey, next()));
+                }
+            }
+        }
+    }
+
+    Ok(Image {
+        width: header.width,
+        height: header.height,
+        pixel_data,
+    })
+}
+
+struct Chunk<'a> {
+    ty: [u8; 4],
+    body: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    // Along with whatever follows the chunk
+    fn parse(data: &'a [u8]) -> Result<(Self, &'a [u8]), ImageError> {
+        let truncated = ImageError::Malformed("truncated");
+        let len = data.get(..4).ok_or(truncated)?;
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        let end = len.checked_add(8).ok_or(truncated)?;
+        let typed = data.get(4..end).ok_or(truncated)?;
+        let crc = data.get(end..end + 4).ok_or(truncated)?;
+        if crc32(typed) != u32::from_be_bytes(crc.try_into().unwrap()) {
+            return Err(ImageError::Malformed("bad checksum"));
+        }
+        let chunk = Self {
+            ty: typed[..4].try_into().unwrap(),
+            body: &typed[4..],
+        };
+        Ok((chunk, &data[end + 4..]))
+    }
+}
+
+// Reverses each scanline's filter in place, leaving the filter type bytes
+fn unfilter(raw: &mut [u8], scanline_len: usize, bits_per_pixel: usize) -> Result<(), ImageError> {
+    // Filters work on bytes, comparing each with the corresponding byte of the
+    // previous whole pixel
+    let bpp = (bits_per_pixel + 7) / 8;
+    let stride = scanline_len + 1;
+    let mut previous = vec![0; scanline_len];
+    for scanline in raw.chunks_mut(stride) {
+        let (ty, line) = scanline.split_first_mut().unwrap();
+        for i in 0..line.len() {
+            let a = if i >= bpp { line[i - bpp] } else { 0 };
+            let b = previous[i];
+            let c = if i >= bpp { previous[i - bpp] } else { 0 };
+            let predicted = match ty {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(ImageError::Malformed("bad filter type")),
+            };
+            line[i] = line[i].wrapping_add(predicted);
+        }
+        previous.copy_from_slice(line);
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Rec. 601 weights
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b) + 500) / 1000) as u8
+}
+
+fn over_black(value: u8, alpha: u8) -> u8 {
+    ((u16::from(value) * u16::from(alpha) + 127) / 255) as u8
+}
+
+// The samples of a scanline, scaled to eight bits
+struct Samples<'a> {
+    data: &'a [u8],
+    bit_depth: u8,
+    scale: bool,
+    pos: usize,
+}
+
+impl<'a> Samples<'a> {
+    fn new(data: &'a [u8], bit_depth: u8) -> Self {
+        Self {
+            data,
+            bit_depth,
+            scale: true,
+            pos: 0,
+        }
+    }
+
+    // Palette indices, which mustn't be scaled
+    fn indices(data: &'a [u8], bit_depth: u8) -> Self {
+        Self {
+            scale: false,
+            ..Self::new(data, bit_depth)
+        }
+    }
+}
+
+impl Iterator for Samples<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let value = match self.bit_depth {
+            // Keep the most significant byte
+            16 => *self.data.get(self.pos / 8)?,
+            8 => *self.data.get(self.pos / 8)?,
+            depth => {
+                let byte = *self.data.get(self.pos / 8)?;
+                let shift = 8 - depth as usize - self.pos % 8;
+                let value = (byte >> shift) & ((1 << depth) - 1);
+                if self.scale {
+                    (u16::from(value) * 255 / ((1 << depth) - 1)) as u8
+                } else {
+                    value
+                }
+            }
+        };
+        self.pos += usize::from(self.bit_depth);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec;
+
+    use crate::export;
+    use crate::{Draft, RenderOptions};
+
+    // An unfiltered PNG of raw scanlines, compressed by miniz
+    fn png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        colour_type: u8,
+        extra: &[(&[u8; 4], &[u8])],
+        scanlines: &[u8],
+    ) -> Vec<u8> {
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[bit_depth, colour_type, 0, 0, 0]);
+        let mut out = SIGNATURE.to_vec();
+        push_chunk(&mut out, b"IHDR", &ihdr);
+        for (ty, body) in extra {
+            push_chunk(&mut out, ty, body);
+        }
+        let idat = miniz_oxide::deflate::compress_to_vec_zlib(scanlines, 6);
+        push_chunk(&mut out, b"IDAT", &idat);
+        push_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    fn push_chunk(out: &mut Vec<u8>, ty: &[u8; 4], body: &[u8]) {
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(ty);
+        out.extend_from_slice(body);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    #[test]
+    fn round_trip_through_export() {
+        let draft = Draft::new("Hi", &RenderOptions::default());
+        let image = decode(&export::png(&draft).unwrap(), usize::MAX).unwrap();
+        assert_eq!((image.width, image.height), (draft.width, draft.height));
+        assert_eq!(image.pixel_data, draft.pixel_data);
+    }
+
+    #[test]
+    fn colour_types_and_depths() {
+        let grey_2bit = png(3, 1, 2, 0, &[], &[0, 0b00_01_11_00]);
+        assert_eq!(decode(&grey_2bit, 3).unwrap().pixel_data, [0, 85, 255]);
+
+        let grey_16bit = png(2, 1, 16, 0, &[], &[0, 0x12, 0x34, 0xff, 0xff]);
+        assert_eq!(decode(&grey_16bit, 2).unwrap().pixel_data, [0x12, 0xff]);
+
+        let rgb = png(2, 1, 8, 2, &[], &[0, 255, 0, 0, 0, 0, 255]);
+        assert_eq!(decode(&rgb, 2).unwrap().pixel_data, [76, 29]);
+
+        let rgba = png(1, 1, 8, 6, &[], &[0, 255, 255, 255, 128]);
+        assert_eq!(decode(&rgba, 1).unwrap().pixel_data, [128]);
+
+        let grey_alpha = png(1, 1, 16, 4, &[], &[0, 200, 0, 0, 0]);
+        assert_eq!(decode(&grey_alpha, 1).unwrap().pixel_data, [0]);
+
+        let palette = [0, 0, 0, 255, 255, 255];
+        let indexed = png(
+            3,
+            1,
+            1,
+            3,
+            &[(b"PLTE", &palette), (b"tRNS", &[255, 0])],
+            &[0, 0b0100_0000],
+        );
+        assert_eq!(decode(&indexed, 3).unwrap().pixel_data, [0, 0, 0]);
+        let opaque = png(2, 1, 4, 3, &[(b"PLTE", &palette)], &[0, 0x10]);
+        assert_eq!(decode(&opaque, 2).unwrap().pixel_data, [255, 0]);
+        let beyond = png(1, 1, 8, 3, &[(b"PLTE", &palette)], &[0, 2]);
+        assert_eq!(
+            decode(&beyond, 1),
+            Err(ImageError::Malformed("index beyond palette"))
+        );
+    }
+
+    #[test]
+    fn filters() {
+        // Two rows of 8-bit RGB, the second predicted from the first by each
+        // filter type in turn
+        let first = [10, 20, 30, 40, 50, 60];
+        let second = [15, 25, 35, 45, 55, 65];
+        let expected = decode(
+            &png(2, 2, 8, 2, &[], &[&[0][..], &first, &[0], &second].concat()),
+            4,
+        )
+        .unwrap();
+        for ty in 1..=4 {
+            let mut filtered = vec![ty];
+            for i in 0..second.len() {
+                let a = if i >= 3 { second[i - 3] } else { 0 };
+                let b = first[i];
+                let c = if i >= 3 { first[i - 3] } else { 0 };
+                let predicted = match ty {
+                    1 => a,
+                    2 => b,
+                    3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                filtered.push(second[i].wrapping_sub(predicted));
+            }
+            let scanlines = [&[0][..], &first, &filtered].concat();
+            assert_eq!(
+                decode(&png(2, 2, 8, 2, &[], &scanlines), 4).unwrap(),
+                expected,
+                "filter {}",
+                ty
+            );
+        }
+    }
+
+    #[test]
+    fn rejects() {
+        let grey = png(2, 2, 8, 0, &[], &[0, 1, 2, 0, 3, 4]);
+        assert_eq!(decode(&grey, 3), Err(ImageError::TooLarge));
+
+        let mut corrupt = grey.clone();
+        corrupt[SIGNATURE.len() + 10] ^= 1;
+        assert_eq!(
+            decode(&corrupt, 4),
+            Err(ImageError::Malformed("bad checksum"))
+        );
+
+        for len in SIGNATURE.len()..grey.len() {
+            assert!(decode(&grey[..len], 4).is_err());
+        }
+
+        let short = png(2, 2, 8, 0, &[], &[0, 1, 2]);
+        assert_eq!(
+            decode(&short, 4),
+            Err(ImageError::Malformed("too little image data"))
+        );
+
+        let mut interlaced = grey;
+        interlaced[SIGNATURE.len() + 8 + 12] = 1;
+        let crc = crc32(&interlaced[SIGNATURE.len() + 4..SIGNATURE.len() + 8 + 13]);
+        interlaced[SIGNATURE.len() + 8 + 13..SIGNATURE.len() + 8 + 17]
+            .copy_from_slice(&crc.to_be_bytes());
+        assert_eq!(
+            decode(&interlaced, 4),
+            Err(ImageError::Unsupported("interlacing"))
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Just enough JSON for the flat objects that works are reported in.
+
+use core::fmt;
+use core::fmt::Write;
+
+pub(crate) fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    Escaped(f).write_str(s)?;
+    write!(f, "\"")
+}
+
+/// The inside of a JSON string. Anything outside of ASCII is left as UTF-8,
+/// which JSON allows.
+pub(crate) struct Escaped<'a, 'b>(pub(crate) &'a mut fmt::Formatter<'b>);
+
+impl fmt::Write for Escaped<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' => write!(self.0, "\\\"")?,
+                '\\' => write!(self.0, "\\\\")?,
+                '\n' => write!(self.0, "\\n")?,
+                '\r' => write!(self.0, "\\r")?,
+                '\t' => write!(self.0, "\\t")?,
+                c if u32::from(c) < 0x20 => write!(self.0, "\\u{:04x}", u32::from(c))?,
+                c => write!(self.0, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
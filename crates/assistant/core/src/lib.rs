@@ -2,37 +2,233 @@
 
 extern crate alloc;
 
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
 
-use rusttype::{point, Font, Scale};
+use once_cell::race::OnceBox;
+use rusttype::{point, GlyphId, Scale};
 
+mod glyph_cache;
+mod json;
 mod nostd_float;
+mod typeface;
+
+pub mod base64;
+pub mod batch;
+pub mod command;
+pub mod export;
+pub mod gallery;
+pub mod history;
+pub mod image;
+pub mod line_editor;
+pub mod output;
+pub mod qr;
+pub mod signature;
+pub mod time;
+pub mod utf8;
+
+pub use glyph_cache::GlyphCache;
+pub use typeface::Typeface;
 
 use nostd_float::FloatExt;
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// Desired font pixel height
+    pub size: f32,
+    /// Whether to apply the font's pair kerning
+    pub kerning: bool,
+    /// Extra pixels between each pair of characters
+    pub letter_spacing: f32,
+    /// Fixed width of the draft in pixels, within which the subject is placed
+    /// according to `align`. A subject too wide for it widens the draft instead
+    /// of being clipped.
+    pub width: Option<usize>,
+    pub align: Align,
+}
+
+impl RenderOptions {
+    pub const MIN_SIZE: f32 = 6.0;
+    pub const MAX_SIZE: f32 = 16.0;
+    pub const MAX_LETTER_SPACING: f32 = 8.0;
+
+    fn pixel_height(&self) -> usize {
+        FloatExt::ceil(self.size) as usize
+    }
+
+    fn scale(&self) -> Scale {
+        // 2x scale in x direction to counter the aspect ratio of monospace characters.
+        Scale {
+            x: self.size * 2.0,
+            y: self.size,
+        }
+    }
+
+    /// The number of characters that are guaranteed to render to a draft of
+    /// at most `max_pixels` pixels, whichever characters they are.
+    pub fn max_subject_len(&self, max_pixels: usize) -> usize {
+        match self.width.unwrap_or(0).checked_mul(self.pixel_height()) {
+            Some(size) if size <= max_pixels => {}
+            _ => return 0,
+        }
+        let typeface = typeface();
+        let font = typeface.font();
+        let scale = self.scale();
+        let mut widest = (0..font.glyph_count())
+            .map(|id| {
+                font.glyph(GlyphId(id as u16))
+                    .scaled(scale)
+                    .h_metrics()
+                    .advance_width
+            })
+            .fold(0.0, f32::max);
+        widest += self.letter_spacing;
+        if self.kerning {
+            widest += typeface.max_kerning(scale);
+        }
+        // Leave a pixel for rounding up the total width
+        let max_width = (max_pixels / self.pixel_height()).saturating_sub(1);
+        (max_width as f32 / widest) as usize
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            size: 12.4, // to get 80 chars across (fits most terminals); adjust as desired
+            kerning: true,
+            letter_spacing: 0.0,
+            width: None,
+            align: Align::Left,
+        }
+    }
+}
+
+/// Where a subject sits within a draft of fixed width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Centre,
+    Right,
+}
+
+impl Align {
+    // The fraction of the spare width that goes before the subject
+    fn fraction(self) -> f32 {
+        match self {
+            Self::Left => 0.0,
+            Self::Centre => 0.5,
+            Self::Right => 1.0,
+        }
+    }
+}
+
+impl fmt::Display for Align {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Left => "left",
+            Self::Centre => "centre",
+            Self::Right => "right",
+        })
+    }
+}
+
+impl FromStr for Align {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Self::Left),
+            "centre" | "center" => Ok(Self::Centre),
+            "right" => Ok(Self::Right),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Drawn in place of characters that the font has no glyph for.
+pub const PLACEHOLDER: char = '?';
+
+// Worked out by the build script, since it means trying every pair of glyphs
+const BUNDLED_MAX_KERNING: f32 = include!(concat!(env!("OUT_DIR"), "/max_kerning.rs"));
+
+/// Parsed on first use, and kept for the life of the program.
+fn typeface() -> &'static Typeface<'static> {
+    static TYPEFACE: OnceBox<Typeface<'static>> = OnceBox::new();
+    TYPEFACE.get_or_init(|| {
+        let font_data = include_bytes!("../assets/fonts/rock-salt/RockSalt-Regular.ttf");
+        Box::new(
+            Typeface::try_from_bytes(font_data)
+                .unwrap()
+                .with_max_kerning(BUNDLED_MAX_KERNING),
+        )
+    })
+}
+
 pub struct Draft {
     pub width: usize,
     pub height: usize,
     pub pixel_data: Vec<u8>,
+    /// Characters of the subject which were drawn as [`PLACEHOLDER`], in order
+    /// of first appearance.
+    pub missing_glyphs: Vec<char>,
 }
 
 impl Draft {
-    // Derived from:
-    // https://github.com/redox-os/rusttype/blob/master/dev/examples/ascii.rs
-    pub fn new(subject: &str) -> Self {
-        let font_data = include_bytes!("../assets/fonts/rock-salt/RockSalt-Regular.ttf");
-        let font = Font::try_from_bytes(font_data as &[u8]).unwrap();
+    pub fn new(subject: &str, options: &RenderOptions) -> Self {
+        Self::with_cache(subject, options, &mut GlyphCache::new(0))
+    }
 
-        // Desired font pixel height
-        let height: f32 = 12.4; // to get 80 chars across (fits most terminals); adjust as desired
-        let pixel_height = height.ceil() as usize;
+    /// Reuses glyphs rasterised for earlier drafts. `cache` must only ever be
+    /// used with the bundled font.
+    pub fn with_cache(subject: &str, options: &RenderOptions, cache: &mut GlyphCache) -> Self {
+        Self::render(subject, typeface(), options, cache)
+    }
 
-        // 2x scale in x direction to counter the aspect ratio of monospace characters.
-        let scale = Scale {
-            x: height * 2.0,
-            y: height,
-        };
+    /// Renders in some font other than the bundled one.
+    pub fn with_typeface(subject: &str, typeface: &Typeface, options: &RenderOptions) -> Self {
+        Self::render(subject, typeface, options, &mut GlyphCache::new(0))
+    }
+
+    /// Scales an image to `width` pixels across, keeping its aspect ratio
+    /// given that a character cell is twice as tall as it is wide.
+    ///
+    /// Panics if [`Self::image_height`] can't say how tall that would be.
+    pub fn from_image(image: &image::Image, width: usize) -> Self {
+        let height = Self::image_height(image, width).expect("image is too large to scale");
+        let image = image.resample(width, height);
+        Self {
+            width: image.width,
+            height: image.height,
+            pixel_data: image.pixel_data,
+            missing_glyphs: Vec::new(),
+        }
+    }
+
+    /// How many rows [`Self::from_image`] would scale an image to, so that
+    /// its size can be checked before going to the trouble, or `None` if
+    /// that's too many to count.
+    pub fn image_height(image: &image::Image, width: usize) -> Option<usize> {
+        let scaled = image.height.checked_mul(width)?.checked_add(image.width)?;
+        let height = scaled / image.width.checked_mul(2)?;
+        Some(height.max(1))
+    }
+
+    // Derived from:
+    // https://github.com/redox-os/rusttype/blob/master/dev/examples/ascii.rs
+    fn render(
+        subject: &str,
+        typeface: &Typeface,
+        options: &RenderOptions,
+        cache: &mut GlyphCache,
+    ) -> Self {
+        let font = typeface.font();
+        let pixel_height = options.pixel_height();
+        let scale = options.scale();
 
         // The origin of a line of text is at the baseline (roughly where
         // non-descending letters sit). We don't want to clip the text, so we shift
@@ -40,40 +236,155 @@ impl Draft {
         // distance between the baseline and the highest edge of any glyph in
         // the font. That's enough to guarantee that there's no clipping.
         let v_metrics = font.v_metrics(scale);
-        let offset = point(0.0, v_metrics.ascent);
 
-        let glyphs = font.layout(subject, scale, offset).collect::<Vec<_>>();
+        let mut missing_glyphs = Vec::new();
+        let subject = subject
+            .chars()
+            .map(|c| {
+                if font.glyph(c).id() != GlyphId(0) {
+                    return c;
+                }
+                if !missing_glyphs.contains(&c) {
+                    missing_glyphs.push(c);
+                }
+                PLACEHOLDER
+            })
+            .collect::<String>();
+
+        // Lay out along the baseline, leaving the caret where the subject ends
+        let mut caret = 0.0;
+        let mut last = None;
+        let mut glyphs = Vec::new();
+        for c in subject.chars() {
+            let g = font.glyph(c).scaled(scale);
+            if let Some(last) = last {
+                if options.kerning {
+                    caret += typeface.pair_kerning(scale, last, g.id());
+                }
+                caret += options.letter_spacing;
+            }
+            last = Some(g.id());
+            let advance_width = g.h_metrics().advance_width;
+            glyphs.push((caret, g));
+            caret += advance_width;
+        }
 
         // Find the most visually pleasing width to display
-        let width = glyphs
-            .iter()
-            .rev()
-            .map(|g| g.position().x as f32 + g.unpositioned().h_metrics().advance_width)
-            .next()
-            .unwrap_or(0.0)
-            .ceil() as usize;
+        let natural_width = FloatExt::ceil(caret) as usize;
+        let width = options.width.unwrap_or(0).max(natural_width);
+        // Only a fixed width leaves any room to align within
+        let spare = options
+            .width
+            .map_or(0.0, |fixed| (fixed as f32 - caret).max(0.0));
+        let offset = point(spare * options.align.fraction(), v_metrics.ascent);
 
         // Rasterise to greyscale
         let mut pixel_data = vec![0; width * pixel_height];
-        for g in glyphs {
-            if let Some(bb) = g.pixel_bounding_box() {
-                g.draw(|x, y, v| {
-                    let x = x as i32 + bb.min.x;
-                    let y = y as i32 + bb.min.y;
+        for (x, g) in glyphs {
+            let position = point(offset.x + x, offset.y);
+            cache.draw(&g, position, |origin, glyph| {
+                for (i, v) in glyph.coverage.iter().enumerate() {
+                    let x = origin.x + glyph.min.x + (i % glyph.width) as i32;
+                    let y = origin.y + glyph.min.y + (i / glyph.width) as i32;
                     // There's still a possibility that the glyph clips the boundaries of the bitmap
                     if x >= 0 && x < width as i32 && y >= 0 && y < pixel_height as i32 {
                         let x = x as usize;
                         let y = y as usize;
-                        pixel_data[x + y * width] = (v * 255.0 + 0.5) as u8;
+                        pixel_data[x + y * width] = *v;
                     }
-                })
-            }
+                }
+            });
         }
 
         Self {
             width,
             height: pixel_height,
             pixel_data,
+            missing_glyphs,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec;
+
+    #[test]
+    fn bundled_max_kerning() {
+        let font_data = include_bytes!("../assets/fonts/rock-salt/RockSalt-Regular.ttf");
+        let typeface = Typeface::try_from_bytes(font_data).unwrap();
+        assert_eq!(BUNDLED_MAX_KERNING, typeface.unscaled_max_kerning());
+    }
+
+    #[test]
+    fn missing_glyphs() {
+        let options = RenderOptions::default();
+        let draft = Draft::new("é中ж中", &options);
+        assert_eq!(draft.missing_glyphs, vec!['中', 'ж']);
+        let placeholders = Draft::new("é???", &options);
+        assert_eq!(draft.pixel_data, placeholders.pixel_data);
+        assert!(Draft::new("é", &options).missing_glyphs.is_empty());
+    }
+
+    #[test]
+    fn fixed_width() {
+        let options = RenderOptions {
+            width: Some(100),
+            align: Align::Right,
+            ..Default::default()
+        };
+        let draft = Draft::new("To", &options);
+        assert_eq!(draft.width, 100);
+        // The subject is pushed up against the right edge
+        let inked =
+            |x: usize| (0..draft.height).any(|y| draft.pixel_data[x + y * draft.width] != 0);
+        assert!(!inked(0));
+        assert!((90..100).any(inked));
+
+        let wide = "The quick brown fox";
+        assert_eq!(
+            Draft::new(wide, &options).pixel_data,
+            Draft::new(wide, &RenderOptions::default()).pixel_data,
+        );
+    }
+
+    #[test]
+    fn from_image_keeps_aspect() {
+        let image = image::Image {
+            width: 40,
+            height: 30,
+            pixel_data: vec![200; 40 * 30],
+        };
+        let draft = Draft::from_image(&image, 80);
+        // Cells are twice as tall as they are wide
+        assert_eq!((draft.width, draft.height), (80, 30));
+        assert!(draft.pixel_data.iter().all(|v| *v == 200));
+        assert_eq!(Draft::image_height(&image, 1), Some(1));
+        assert_eq!(Draft::image_height(&image, usize::MAX), None);
+    }
+
+    #[test]
+    fn max_subject_len_fits() {
+        let max_pixels = 0x4_000 - 0x200;
+        for size in [RenderOptions::MIN_SIZE, 12.4, RenderOptions::MAX_SIZE] {
+            let options = RenderOptions {
+                size,
+                ..Default::default()
+            };
+            let n = options.max_subject_len(max_pixels);
+            assert!(n > 0);
+            for c in (b' '..=b'~').map(char::from) {
+                let subject = String::from_iter((0..n).map(|_| c));
+                let draft = Draft::new(&subject, &options);
+                assert!(draft.pixel_data.len() <= max_pixels, "{:?} at {}", c, size);
+            }
+        }
+        let options = RenderOptions {
+            width: Some(usize::MAX),
+            ..Default::default()
+        };
+        assert_eq!(options.max_subject_len(max_pixels), 0);
+    }
+}
@@ -0,0 +1,610 @@
+use alloc::string::String;
+use core::cmp::Ordering;
+use core::fmt;
+use core::mem;
+
+use crate::utf8::{InvalidSequence, Utf8Decoder};
+
+const BEL: u8 = 0x07;
+const ESC: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+const fn ctrl(c: u8) -> u8 {
+    c & 0x1f
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Submit(String),
+    Interrupt,
+    Full,
+    HistoryPrev,
+    HistoryNext,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EscapeState {
+    Ground,
+    Escape,
+    Csi { param: u16 },
+    Ss3,
+}
+
+/// A single-line editor for a dumb serial console.
+///
+/// Input arrives one byte at a time as UTF-8, and everything that needs to be
+/// echoed back to the terminal is written to `out`, assuming a VT100-compatible
+/// terminal whose cursor sits just after the prompt and the current line, and
+/// on which each character occupies a single column.
+pub struct LineEditor {
+    line: String,
+    cursor: usize,
+    max_len: usize,
+    decoder: Utf8Decoder,
+    escape: EscapeState,
+    last_was_cr: bool,
+    discarding: bool,
+}
+
+impl LineEditor {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            line: String::new(),
+            cursor: 0,
+            max_len,
+            decoder: Utf8Decoder::new(),
+            escape: EscapeState::Ground,
+            last_was_cr: false,
+            discarding: false,
+        }
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Byte offset of the cursor into the line.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Maximum line length, in characters.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Lines already longer than `max_len` are left alone, but can't grow.
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.line.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.take();
+    }
+
+    /// Clears the line, and then swallows input up to and including the next
+    /// line ending, so that the rest of a line abandoned part way through
+    /// isn't taken for a line of its own.
+    pub fn discard_rest_of_line(&mut self) {
+        self.clear();
+        self.discarding = true;
+    }
+
+    /// Whether input is being swallowed up to the end of the line.
+    pub fn is_discarding(&self) -> bool {
+        self.discarding
+    }
+
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.decoder.reset();
+        self.escape = EscapeState::Ground;
+        mem::take(&mut self.line)
+    }
+
+    pub fn feed(&mut self, b: u8, out: &mut impl fmt::Write) -> Result<Option<Event>, fmt::Error> {
+        if self.discarding {
+            if b == b'\r' || b == b'\n' {
+                self.discarding = false;
+                self.last_was_cr = b == b'\r';
+            }
+            return Ok(None);
+        }
+        if self.escape != EscapeState::Ground {
+            if b.is_ascii() {
+                return self.escape(b, out);
+            }
+            self.escape = EscapeState::Ground;
+        }
+        let mut event = None;
+        for decoded in self.decoder.push(b) {
+            match decoded {
+                Ok(c) => event = self.key(c, out)?,
+                Err(InvalidSequence) => bell(out)?,
+            }
+        }
+        Ok(event)
+    }
+
+    fn escape(&mut self, b: u8, out: &mut impl fmt::Write) -> Result<Option<Event>, fmt::Error> {
+        match self.escape {
+            EscapeState::Ground => unreachable!(),
+            EscapeState::Escape => {
+                self.escape = match b {
+                    b'[' => EscapeState::Csi { param: 0 },
+                    b'O' => EscapeState::Ss3,
+                    _ => EscapeState::Ground,
+                };
+                Ok(None)
+            }
+            EscapeState::Csi { param } => match b {
+                b'0'..=b'9' => {
+                    let digit = u16::from(b - b'0');
+                    self.escape = EscapeState::Csi {
+                        param: param.saturating_mul(10).saturating_add(digit),
+                    };
+                    Ok(None)
+                }
+                // Parameter and intermediate bytes we don't interpret
+                0x20..=0x3f => Ok(None),
+                0x40..=0x7e => {
+                    self.escape = EscapeState::Ground;
+                    self.csi(param, b, out)
+                }
+                _ => {
+                    self.escape = EscapeState::Ground;
+                    Ok(None)
+                }
+            },
+            EscapeState::Ss3 => {
+                self.escape = EscapeState::Ground;
+                self.csi(0, b, out)
+            }
+        }
+    }
+
+    fn key(&mut self, c: char, out: &mut impl fmt::Write) -> Result<Option<Event>, fmt::Error> {
+        let last_was_cr = mem::replace(&mut self.last_was_cr, c == '\r');
+        match c {
+            '\n' if last_was_cr => {}
+            '\r' | '\n' => return Ok(Some(Event::Submit(self.take()))),
+            _ if c.is_ascii_control() => return self.control(c as u8, out),
+            _ if c.is_control() => {}
+            _ => {
+                if self.line.chars().count() >= self.max_len {
+                    bell(out)?;
+                    return Ok(Some(Event::Full));
+                }
+                self.insert(c, out)?;
+            }
+        }
+        Ok(None)
+    }
+
+    fn control(&mut self, b: u8, out: &mut impl fmt::Write) -> Result<Option<Event>, fmt::Error> {
+        match b {
+            ESC => self.escape = EscapeState::Escape,
+            DEL | 0x08 => self.backspace(out)?,
+            _ if b == ctrl(b'A') => self.move_to(0, out)?,
+            _ if b == ctrl(b'E') => self.move_to(self.line.len(), out)?,
+            _ if b == ctrl(b'B') => self.move_left(out)?,
+            _ if b == ctrl(b'F') => self.move_right(out)?,
+            _ if b == ctrl(b'D') => self.delete(out)?,
+            _ if b == ctrl(b'K') => self.kill(self.cursor, self.line.len(), out)?,
+            _ if b == ctrl(b'U') => self.kill(0, self.cursor, out)?,
+            _ if b == ctrl(b'W') => self.kill(self.word_start(), self.cursor, out)?,
+            _ if b == ctrl(b'P') => return Ok(Some(Event::HistoryPrev)),
+            _ if b == ctrl(b'N') => return Ok(Some(Event::HistoryNext)),
+            _ if b == ctrl(b'C') => {
+                self.clear();
+                return Ok(Some(Event::Interrupt));
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn csi(
+        &mut self,
+        param: u16,
+        b: u8,
+        out: &mut impl fmt::Write,
+    ) -> Result<Option<Event>, fmt::Error> {
+        match (b, param) {
+            (b'A', _) => return Ok(Some(Event::HistoryPrev)),
+            (b'B', _) => return Ok(Some(Event::HistoryNext)),
+            (b'C', _) => self.move_right(out)?,
+            (b'D', _) => self.move_left(out)?,
+            (b'H', _) | (b'~', 1 | 7) => self.move_to(0, out)?,
+            (b'F', _) | (b'~', 4 | 8) => self.move_to(self.line.len(), out)?,
+            (b'~', 3) => self.delete(out)?,
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Replaces the entire line, for example with an entry recalled from history.
+    pub fn replace(&mut self, line: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        self.move_to(0, out)?;
+        self.line.clear();
+        self.line.push_str(line);
+        self.cursor = self.line.len();
+        out.write_str(&self.line)?;
+        clear_to_end_of_line(out)
+    }
+
+    /// Writes out the whole line and restores the cursor, for use after
+    /// something else has been printed over it.
+    pub fn redraw(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        out.write_str(&self.line)?;
+        cursor_left(columns(&self.line[self.cursor..]), out)
+    }
+
+    fn insert(&mut self, c: char, out: &mut impl fmt::Write) -> fmt::Result {
+        self.line.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        out.write_char(c)?;
+        self.redraw_tail(out)
+    }
+
+    fn backspace(&mut self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self.prev_boundary() {
+            Some(start) => self.kill(start, self.cursor, out),
+            None => bell(out),
+        }
+    }
+
+    fn delete(&mut self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self.next_boundary() {
+            Some(end) => self.kill(self.cursor, end, out),
+            None => bell(out),
+        }
+    }
+
+    fn kill(&mut self, start: usize, end: usize, out: &mut impl fmt::Write) -> fmt::Result {
+        if start == end {
+            return Ok(());
+        }
+        self.move_to(start, out)?;
+        self.line.replace_range(start..end, "");
+        self.redraw_tail(out)
+    }
+
+    fn move_left(&mut self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self.prev_boundary() {
+            Some(cursor) => self.move_to(cursor, out),
+            None => bell(out),
+        }
+    }
+
+    fn move_right(&mut self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self.next_boundary() {
+            Some(cursor) => self.move_to(cursor, out),
+            None => bell(out),
+        }
+    }
+
+    fn move_to(&mut self, cursor: usize, out: &mut impl fmt::Write) -> fmt::Result {
+        match cursor.cmp(&self.cursor) {
+            Ordering::Less => cursor_left(columns(&self.line[cursor..self.cursor]), out)?,
+            Ordering::Greater => cursor_right(columns(&self.line[self.cursor..cursor]), out)?,
+            Ordering::Equal => {}
+        }
+        self.cursor = cursor;
+        Ok(())
+    }
+
+    // Rewrites everything from the cursor onwards, leaving the cursor where it was.
+    fn redraw_tail(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        let tail = &self.line[self.cursor..];
+        out.write_str(tail)?;
+        clear_to_end_of_line(out)?;
+        cursor_left(columns(tail), out)
+    }
+
+    fn prev_boundary(&self) -> Option<usize> {
+        let c = self.line[..self.cursor].chars().next_back()?;
+        Some(self.cursor - c.len_utf8())
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        let c = self.line[self.cursor..].chars().next()?;
+        Some(self.cursor + c.len_utf8())
+    }
+
+    fn word_start(&self) -> usize {
+        let before = self.line[..self.cursor].trim_end_matches(' ');
+        before.rfind(' ').map(|i| i + 1).unwrap_or(0)
+    }
+}
+
+fn columns(s: &str) -> usize {
+    s.chars().count()
+}
+
+pub fn bell(out: &mut impl fmt::Write) -> fmt::Result {
+    out.write_char(char::from(BEL))
+}
+
+fn cursor_left(n: usize, out: &mut impl fmt::Write) -> fmt::Result {
+    match n {
+        0 => Ok(()),
+        _ => write!(out, "\x1b[{}D", n),
+    }
+}
+
+fn cursor_right(n: usize, out: &mut impl fmt::Write) -> fmt::Result {
+    match n {
+        0 => Ok(()),
+        _ => write!(out, "\x1b[{}C", n),
+    }
+}
+
+fn clear_to_end_of_line(out: &mut impl fmt::Write) -> fmt::Result {
+    out.write_str("\x1b[K")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    fn run(editor: &mut LineEditor, input: &[u8]) -> (String, Vec<Event>) {
+        let mut out = String::new();
+        let mut events = Vec::new();
+        for b in input {
+            if let Some(event) = editor.feed(*b, &mut out).unwrap() {
+                events.push(event);
+            }
+        }
+        (out, events)
+    }
+
+    fn editor() -> LineEditor {
+        LineEditor::new(64)
+    }
+
+    #[test]
+    fn plain_typing() {
+        let mut ed = editor();
+        let (out, events) = run(&mut ed, b"abc");
+        assert_eq!(ed.line(), "abc");
+        assert_eq!(ed.cursor(), 3);
+        assert_eq!(out, "a\x1b[Kb\x1b[Kc\x1b[K");
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn submit_on_cr_or_lf() {
+        let mut ed = editor();
+        let (_, events) = run(&mut ed, b"hi\r\nyo\n\r");
+        assert_eq!(
+            events,
+            [
+                Event::Submit("hi".into()),
+                Event::Submit("yo".into()),
+                Event::Submit("".into()),
+            ]
+        );
+        assert!(ed.is_empty());
+    }
+
+    #[test]
+    fn ignores_other_control_bytes() {
+        let mut ed = editor();
+        let (out, events) = run(&mut ed, b"a\x00\x1a\tb");
+        assert_eq!(ed.line(), "ab");
+        assert_eq!(out, "a\x1b[Kb\x1b[K");
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn backspace() {
+        let mut ed = editor();
+        run(&mut ed, b"abc");
+        let (out, _) = run(&mut ed, b"\x7f");
+        assert_eq!(ed.line(), "ab");
+        assert_eq!(out, "\x1b[1D\x1b[K");
+        let (out, _) = run(&mut ed, b"\x08\x08\x08");
+        assert_eq!(ed.line(), "");
+        assert_eq!(out, "\x1b[1D\x1b[K\x1b[1D\x1b[K\x07");
+    }
+
+    #[test]
+    fn arrows_and_insertion() {
+        let mut ed = editor();
+        run(&mut ed, b"ac");
+        let (out, _) = run(&mut ed, b"\x1b[Db");
+        assert_eq!(ed.line(), "abc");
+        assert_eq!(ed.cursor(), 2);
+        assert_eq!(out, "\x1b[1Dbc\x1b[K\x1b[1D");
+        let (out, _) = run(&mut ed, b"\x1b[C\x1b[C");
+        assert_eq!(ed.cursor(), 3);
+        assert_eq!(out, "\x1b[1C\x07");
+    }
+
+    #[test]
+    fn home_end_and_delete() {
+        let mut ed = editor();
+        run(&mut ed, b"hello");
+        let (out, _) = run(&mut ed, b"\x1b[H");
+        assert_eq!(ed.cursor(), 0);
+        assert_eq!(out, "\x1b[5D");
+        let (out, _) = run(&mut ed, b"\x1b[3~");
+        assert_eq!(ed.line(), "ello");
+        assert_eq!(out, "ello\x1b[K\x1b[4D");
+        let (out, _) = run(&mut ed, b"\x1bOF");
+        assert_eq!(ed.cursor(), 4);
+        assert_eq!(out, "\x1b[4C");
+        run(&mut ed, b"\x1b[1~");
+        assert_eq!(ed.cursor(), 0);
+        run(&mut ed, b"\x1b[4~");
+        assert_eq!(ed.cursor(), 4);
+        run(&mut ed, b"\x01");
+        assert_eq!(ed.cursor(), 0);
+        run(&mut ed, b"\x05");
+        assert_eq!(ed.cursor(), 4);
+    }
+
+    #[test]
+    fn kill_to_start() {
+        let mut ed = editor();
+        run(&mut ed, b"hello world\x1b[D\x1b[D");
+        let (out, _) = run(&mut ed, b"\x15");
+        assert_eq!(ed.line(), "ld");
+        assert_eq!(ed.cursor(), 0);
+        assert_eq!(out, "\x1b[9Dld\x1b[K\x1b[2D");
+    }
+
+    #[test]
+    fn kill_to_end() {
+        let mut ed = editor();
+        run(&mut ed, b"hello world\x01\x1b[C");
+        run(&mut ed, b"\x0b");
+        assert_eq!(ed.line(), "h");
+    }
+
+    #[test]
+    fn kill_word() {
+        let mut ed = editor();
+        run(&mut ed, b"one two  three  ");
+        run(&mut ed, b"\x17");
+        assert_eq!(ed.line(), "one two  ");
+        run(&mut ed, b"\x17");
+        assert_eq!(ed.line(), "one ");
+        run(&mut ed, b"\x17\x17");
+        assert_eq!(ed.line(), "");
+    }
+
+    #[test]
+    fn history_keys() {
+        let mut ed = editor();
+        let (out, events) = run(&mut ed, b"\x1b[A\x1b[B\x1bOA\x10\x0e");
+        assert_eq!(out, "");
+        assert_eq!(
+            events,
+            [
+                Event::HistoryPrev,
+                Event::HistoryNext,
+                Event::HistoryPrev,
+                Event::HistoryPrev,
+                Event::HistoryNext,
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_escape_sequences_are_swallowed() {
+        let mut ed = editor();
+        let (out, events) = run(&mut ed, b"a\x1b[1;5Z\x1b[200~b\x1bxc");
+        assert_eq!(ed.line(), "abc");
+        assert_eq!(out, "a\x1b[Kb\x1b[Kc\x1b[K");
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn interrupt() {
+        let mut ed = editor();
+        let (_, events) = run(&mut ed, b"abc\x03");
+        assert_eq!(events, [Event::Interrupt]);
+        assert!(ed.is_empty());
+    }
+
+    #[test]
+    fn discard_rest_of_line() {
+        let mut ed = editor();
+        run(&mut ed, b"ab");
+        ed.discard_rest_of_line();
+        assert!(ed.is_empty());
+        let (out, events) = run(&mut ed, b"c\x1b[D\xc3d\r");
+        assert_eq!(out, "");
+        assert!(events.is_empty());
+        assert!(!ed.is_discarding());
+        let (_, events) = run(&mut ed, b"\nxy\r");
+        assert_eq!(events, [Event::Submit("xy".into())]);
+    }
+
+    #[test]
+    fn full() {
+        let mut ed = LineEditor::new(3);
+        run(&mut ed, b"abc");
+        let (out, events) = run(&mut ed, b"d\x1b[De");
+        assert_eq!(events, [Event::Full, Event::Full]);
+        assert_eq!(out, "\x07\x1b[1D\x07");
+        assert_eq!(ed.line(), "abc");
+        ed.set_max_len(2);
+        let (_, events) = run(&mut ed, b"\x7fx");
+        assert_eq!(events, [Event::Full]);
+        assert_eq!(ed.line(), "ac");
+        let (_, events) = run(&mut ed, b"\x7fx");
+        assert!(events.is_empty());
+        assert_eq!(ed.line(), "xc");
+    }
+
+    #[test]
+    fn utf8() {
+        let mut ed = editor();
+        let (out, _) = run(&mut ed, "né€".as_bytes());
+        assert_eq!(ed.line(), "né€");
+        assert_eq!(out, "n\x1b[Ké\x1b[K€\x1b[K");
+        let (out, _) = run(&mut ed, b"\x1b[D\x1b[D");
+        assert_eq!(ed.cursor(), 1);
+        assert_eq!(out, "\x1b[1D\x1b[1D");
+        let (out, _) = run(&mut ed, "ü".as_bytes());
+        assert_eq!(ed.line(), "nüé€");
+        assert_eq!(out, "üé€\x1b[K\x1b[2D");
+        let (out, _) = run(&mut ed, b"\x1b[3~");
+        assert_eq!(ed.line(), "nü€");
+        assert_eq!(out, "€\x1b[K\x1b[1D");
+        let (out, _) = run(&mut ed, b"\x7f");
+        assert_eq!(ed.line(), "n€");
+        assert_eq!(out, "\x1b[1D€\x1b[K\x1b[1D");
+        let mut out = String::new();
+        ed.redraw(&mut out).unwrap();
+        assert_eq!(out, "n€\x1b[1D");
+    }
+
+    #[test]
+    fn utf8_split_and_invalid() {
+        let mut ed = editor();
+        let euro = "€".as_bytes();
+        run(&mut ed, &euro[..2]);
+        assert_eq!(ed.line(), "");
+        run(&mut ed, &euro[2..]);
+        assert_eq!(ed.line(), "€");
+        let (out, events) = run(&mut ed, b"\xe2\x82\r");
+        assert_eq!(out, "\x07");
+        assert_eq!(events, [Event::Submit("€".into())]);
+        let (out, _) = run(&mut ed, b"\xff\x1b\xc3\xa9");
+        assert_eq!(ed.line(), "é");
+        assert_eq!(out, "\x07é\x1b[K");
+    }
+
+    #[test]
+    fn utf8_max_len_counts_characters() {
+        let mut ed = LineEditor::new(2);
+        let (_, events) = run(&mut ed, "ééé".as_bytes());
+        assert_eq!(events, [Event::Full]);
+        assert_eq!(ed.line(), "éé");
+    }
+
+    #[test]
+    fn replace_and_redraw() {
+        let mut ed = editor();
+        run(&mut ed, b"abc\x1b[D");
+        let mut out = String::new();
+        ed.replace("xy", &mut out).unwrap();
+        assert_eq!(ed.line(), "xy");
+        assert_eq!(ed.cursor(), 2);
+        assert_eq!(out, "\x1b[2Dxy\x1b[K");
+        run(&mut ed, b"\x1b[D");
+        let mut out = String::new();
+        ed.redraw(&mut out).unwrap();
+        assert_eq!(out, "xy\x1b[1D");
+    }
+}
@@ -0,0 +1,334 @@
+//! Works as records for a program on the host, rather than as art for a
+//! person to look at.
+//!
+//! A record is encoded as JSON or CBOR and sent in a frame:
+//!
+//! ```text
+//! "BNSC" | length (u32, big-endian) | record | CRC-32 of length and record (u32, big-endian)
+//! ```
+//!
+//! The CRC is the one used by PNG and zlib's `crc32`. After line noise, a host
+//! can find its place again with [`parse_frame`].
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::base64;
+use crate::export::crc32;
+use crate::json::write_string;
+
+pub const FRAME_MAGIC: [u8; 4] = *b"BNSC";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The art, then the signature in hex.
+    #[default]
+    Human,
+    Json,
+    Cbor,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Human => write!(f, "human"),
+            Self::Json => write!(f, "json"),
+            Self::Cbor => write!(f, "cbor"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "cbor" => Ok(Self::Cbor),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct WorkRecord<'a> {
+    /// `None` for a work made from an image.
+    pub subject: Option<&'a str>,
+    pub width: usize,
+    pub height: usize,
+    pub algorithm: &'a str,
+    /// SHA-256 of the artist's public key, as DER.
+    pub key_fingerprint: &'a [u8],
+    pub serial: usize,
+    pub edition: usize,
+    pub edition_size: usize,
+    /// Seconds since the Unix epoch when the artist signed the work.
+    pub timestamp: u64,
+    /// The head of the artist's audit log before this work.
+    pub chain_head: &'a [u8],
+    /// The masterpiece's rows run together.
+    pub pixel_data: &'a [u8],
+    pub signature: &'a [u8],
+}
+
+impl WorkRecord<'_> {
+    /// An object in which byte strings are base64, apart from the key
+    /// fingerprint, which is hex.
+    pub fn json(&self) -> String {
+        alloc::format!("{}", self)
+    }
+
+    /// A map with the same keys as [`Self::json`], in which byte strings are
+    /// just that.
+    pub fn cbor(&self) -> Vec<u8> {
+        let mut out = Cbor(Vec::new());
+        out.head(MAP, 12);
+        out.text("subject");
+        match self.subject {
+            Some(subject) => out.text(subject),
+            None => out.0.push(NULL),
+        }
+        out.text("width");
+        out.head(UNSIGNED, self.width as u64);
+        out.text("height");
+        out.head(UNSIGNED, self.height as u64);
+        out.text("algorithm");
+        out.text(self.algorithm);
+        out.text("key_fingerprint");
+        out.bytes(self.key_fingerprint);
+        out.text("serial");
+        out.head(UNSIGNED, self.serial as u64);
+        out.text("edition");
+        out.head(UNSIGNED, self.edition as u64);
+        out.text("edition_size");
+        out.head(UNSIGNED, self.edition_size as u64);
+        out.text("timestamp");
+        out.head(UNSIGNED, self.timestamp);
+        out.text("chain_head");
+        out.bytes(self.chain_head);
+        out.text("art");
+        out.bytes(self.pixel_data);
+        out.text("signature");
+        out.bytes(self.signature);
+        out.0
+    }
+}
+
+impl fmt::Display for WorkRecord<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{\"subject\":")?;
+        match self.subject {
+            Some(subject) => write_string(f, subject)?,
+            None => write!(f, "null")?,
+        }
+        write!(
+            f,
+            ",\"width\":{},\"height\":{},\"algorithm\":",
+            self.width, self.height,
+        )?;
+        write_string(f, self.algorithm)?;
+        write!(f, ",\"key_fingerprint\":\"")?;
+        for b in self.key_fingerprint {
+            write!(f, "{:02x}", b)?;
+        }
+        write!(
+            f,
+            "\",\"serial\":{},\"edition\":{},\"edition_size\":{},\"timestamp\":{},\
+             \"chain_head\":\"",
+            self.serial, self.edition, self.edition_size, self.timestamp,
+        )?;
+        for b in self.chain_head {
+            write!(f, "{:02x}", b)?;
+        }
+        write!(
+            f,
+            "\",\"art\":\"{}\",\"signature\":\"{}\"}}",
+            base64::encode(self.pixel_data),
+            base64::encode(self.signature),
+        )
+    }
+}
+
+const UNSIGNED: u8 = 0;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const MAP: u8 = 5;
+const NULL: u8 = 0xf6;
+
+// Definite lengths only, each in as few bytes as it takes
+struct Cbor(Vec<u8>);
+
+impl Cbor {
+    fn head(&mut self, major: u8, value: u64) {
+        let major = major << 5;
+        match value {
+            0..=23 => self.0.push(major | value as u8),
+            24..=0xff => self.0.extend([major | 24, value as u8]),
+            0x100..=0xffff => {
+                self.0.push(major | 25);
+                self.0.extend((value as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                self.0.push(major | 26);
+                self.0.extend((value as u32).to_be_bytes());
+            }
+            _ => {
+                self.0.push(major | 27);
+                self.0.extend(value.to_be_bytes());
+            }
+        }
+    }
+
+    fn bytes(&mut self, data: &[u8]) {
+        self.head(BYTES, data.len() as u64);
+        self.0.extend_from_slice(data);
+    }
+
+    fn text(&mut self, s: &str) {
+        self.head(TEXT, s.len() as u64);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+}
+
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 12);
+    out.extend(FRAME_MAGIC);
+    out.extend((payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+    let crc = crc32(&out[FRAME_MAGIC.len()..]);
+    out.extend(crc.to_be_bytes());
+    out
+}
+
+/// The payload of the first intact frame in `data`, and whatever follows it.
+/// Anything before it, including frames that fail their CRC, is skipped.
+pub fn parse_frame(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut start = 0;
+    loop {
+        let offset = data[start..]
+            .windows(FRAME_MAGIC.len())
+            .position(|window| window == FRAME_MAGIC)?;
+        let frame = &data[start + offset + FRAME_MAGIC.len()..];
+        if let Some(parsed) = parse_after_magic(frame) {
+            return Some(parsed);
+        }
+        start += offset + 1;
+    }
+}
+
+fn parse_after_magic(frame: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u32::from_be_bytes(frame.get(..4)?.try_into().unwrap()) as usize;
+    let end = len.checked_add(4)?;
+    let crc = frame.get(end..end.checked_add(4)?)?;
+    if crc32(&frame[..end]) != u32::from_be_bytes(crc.try_into().unwrap()) {
+        return None;
+    }
+    Some((&frame[4..end], &frame[end + 4..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn record() -> WorkRecord<'static> {
+        WorkRecord {
+            subject: Some("Hi"),
+            width: 2,
+            height: 1,
+            algorithm: "test",
+            key_fingerprint: &[0xab, 0x01],
+            serial: 300,
+            edition: 2,
+            edition_size: 10,
+            timestamp: 1_700_000_000,
+            chain_head: &[0xcd, 0x02],
+            pixel_data: b"@ ",
+            signature: &[0xff, 0x00],
+        }
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            record().json(),
+            "{\"subject\":\"Hi\",\"width\":2,\"height\":1,\"algorithm\":\"test\",\
+             \"key_fingerprint\":\"ab01\",\"serial\":300,\"edition\":2,\"edition_size\":10,\
+             \"timestamp\":1700000000,\"chain_head\":\"cd02\",\"art\":\"QCA=\",\"signature\":\"/wA=\"}",
+        );
+        let image = WorkRecord {
+            subject: None,
+            ..record()
+        };
+        assert!(image.json().starts_with("{\"subject\":null,"));
+    }
+
+    #[test]
+    fn cbor() {
+        // As given by the diagnostic notation of RFC 8949:
+        // {"subject": "Hi", "width": 2, "height": 1, "algorithm": "test",
+        //  "key_fingerprint": h'ab01', "serial": 300, "edition": 2,
+        //  "edition_size": 10, "timestamp": 1700000000, "chain_head": h'cd02',
+        //  "art": h'4020', "signature": h'ff00'}
+        let mut expected = vec![0xac];
+        expected.extend(b"\x67subject\x62Hi");
+        expected.extend(b"\x65width\x02");
+        expected.extend(b"\x66height\x01");
+        expected.extend(b"\x69algorithm\x64test");
+        expected.extend(b"\x6fkey_fingerprint\x42\xab\x01");
+        expected.extend(b"\x66serial\x19\x01\x2c");
+        expected.extend(b"\x67edition\x02");
+        expected.extend(b"\x6cedition_size\x0a");
+        expected.extend(b"\x69timestamp\x1a\x65\x53\xf1\x00");
+        expected.extend(b"\x6achain_head\x42\xcd\x02");
+        expected.extend(b"\x63art\x42@ ");
+        expected.extend(b"\x69signature\x42\xff\x00");
+        assert_eq!(record().cbor(), expected);
+    }
+
+    #[test]
+    fn cbor_heads() {
+        for (value, expected) in [
+            (23, &[0x17][..]),
+            (24, &[0x18, 0x18]),
+            (0x100, &[0x19, 0x01, 0x00]),
+            (0x1_0000, &[0x1a, 0x00, 0x01, 0x00, 0x00]),
+            (1 << 32, &[0x1b, 0, 0, 0, 1, 0, 0, 0, 0]),
+        ] {
+            let mut out = Cbor(Vec::new());
+            out.head(UNSIGNED, value);
+            assert_eq!(out.0, expected);
+        }
+    }
+
+    #[test]
+    fn frames() {
+        let framed = frame(b"{}");
+        assert_eq!(&framed[..8], b"BNSC\0\0\0\x02");
+        assert_eq!(&framed[8..10], b"{}");
+        assert_eq!(parse_frame(&framed), Some((&b"{}"[..], &b""[..])));
+
+        // Noise before a frame, a frame damaged in transit, and a partial one
+        let mut stream = b"\r\nBNSC garbage".to_vec();
+        let mut damaged = frame(b"first");
+        damaged[9] ^= 0x20;
+        stream.extend(damaged);
+        stream.extend(frame(b"second"));
+        stream.extend(&frame(b"third")[..10]);
+        let (payload, rest) = parse_frame(&stream).unwrap();
+        assert_eq!(payload, b"second");
+        assert_eq!(parse_frame(rest), None);
+    }
+
+    #[test]
+    fn output_format() {
+        for format in [OutputFormat::Human, OutputFormat::Json, OutputFormat::Cbor] {
+            assert_eq!(format.to_string().parse(), Ok(format));
+        }
+        assert_eq!("yaml".parse::<OutputFormat>(), Err(()));
+    }
+}
@@ -0,0 +1,684 @@
+//! QR codes (ISO/IEC 18004), so that a signature can be read off the screen
+//! with a phone. Only byte mode is supported, which is all that text needs.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorCorrection {
+    Low,
+    Medium,
+    Quartile,
+    High,
+}
+
+impl ErrorCorrection {
+    // As they appear in the format information
+    fn format_bits(self) -> u32 {
+        match self {
+            Self::Low => 1,
+            Self::Medium => 0,
+            Self::Quartile => 3,
+            Self::High => 2,
+        }
+    }
+}
+
+const MAX_VERSION: usize = 40;
+
+// Indexed by error correction level, then by version
+const ECC_CODEWORDS_PER_BLOCK: [[u8; MAX_VERSION + 1]; 4] = [
+    [
+        0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28,
+        30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ],
+    [
+        0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30,
+        30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+];
+
+const ERROR_CORRECTION_BLOCKS: [[u8; MAX_VERSION + 1]; 4] = [
+    [
+        0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ],
+    [
+        0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21,
+        23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ],
+    [
+        0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ],
+    [
+        0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32,
+        35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ],
+];
+
+// Light modules around the code, as the standard asks for
+const QUIET_ZONE: usize = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QrCode {
+    size: usize,
+    // Row by row, true for dark
+    modules: Vec<bool>,
+}
+
+impl QrCode {
+    /// The smallest code which holds `data` with at least `min_ecc`, with as
+    /// much more error correction as fits in the same size, or `None` if
+    /// `data` is too long for any code.
+    pub fn encode(data: &[u8], min_ecc: ErrorCorrection) -> Option<Self> {
+        let version = (1..=MAX_VERSION)
+            .find(|version| data_bits(data.len(), *version) <= capacity(*version, min_ecc))?;
+        let ecc = [
+            ErrorCorrection::High,
+            ErrorCorrection::Quartile,
+            ErrorCorrection::Medium,
+        ]
+        .into_iter()
+        .find(|ecc| *ecc > min_ecc && data_bits(data.len(), version) <= capacity(version, *ecc))
+        .unwrap_or(min_ecc);
+
+        let codewords = interleave(&data_codewords(data, version, ecc), version, ecc);
+        let mut grid = Grid::new(version);
+        grid.draw_codewords(&codewords);
+
+        // The mask which leaves the fewest patterns that confuse a reader
+        let mask = (0..8)
+            .min_by_key(|mask| {
+                let mut masked = grid.clone();
+                masked.apply_mask(*mask);
+                masked.draw_format(ecc, *mask);
+                masked.penalty()
+            })
+            .unwrap();
+        grid.apply_mask(mask);
+        grid.draw_format(ecc, mask);
+
+        Some(Self {
+            size: grid.size,
+            modules: grid.modules,
+        })
+    }
+
+    /// Modules along each side, excluding the quiet zone.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Outside of the code is light.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[x + y * self.size]
+    }
+
+    /// Draws each module as a pair of characters, `#` for dark, including the
+    /// quiet zone. Dark is ink, as with the artist's palette.
+    pub fn write_ascii(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        for y in self.with_quiet_zone() {
+            for x in self.with_quiet_zone() {
+                out.write_str(if self.is(x, y) { "##" } else { "  " })?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Draws two rows of modules to each line with half blocks, so that the
+    /// code is about square in a terminal and half the height of
+    /// [`Self::write_ascii`]'s.
+    pub fn write_unicode(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        for y in self.with_quiet_zone().step_by(2) {
+            for x in self.with_quiet_zone() {
+                let c = match (self.is(x, y), self.is(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                };
+                out.write_char(c)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    fn with_quiet_zone(&self) -> core::ops::Range<isize> {
+        -(QUIET_ZONE as isize)..(self.size + QUIET_ZONE) as isize
+    }
+
+    fn is(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && self.is_dark(x as usize, y as usize)
+    }
+}
+
+fn size(version: usize) -> usize {
+    version * 4 + 17
+}
+
+// Bits in byte mode: the mode indicator, the length, then the data
+fn data_bits(len: usize, version: usize) -> usize {
+    let count_bits = if version <= 9 { 8 } else { 16 };
+    if len >= 1 << count_bits {
+        return usize::MAX;
+    }
+    4 + count_bits + len * 8
+}
+
+fn capacity(version: usize, ecc: ErrorCorrection) -> usize {
+    data_codeword_count(version, ecc) * 8
+}
+
+fn data_codeword_count(version: usize, ecc: ErrorCorrection) -> usize {
+    raw_module_count(version) / 8
+        - usize::from(ECC_CODEWORDS_PER_BLOCK[ecc as usize][version])
+            * usize::from(ERROR_CORRECTION_BLOCKS[ecc as usize][version])
+}
+
+// Modules left for codewords once the function patterns are drawn, which
+// may not be a whole number of codewords
+fn raw_module_count(version: usize) -> usize {
+    let mut count = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        count -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            count -= 36;
+        }
+    }
+    count
+}
+
+fn data_codewords(data: &[u8], version: usize, ecc: ErrorCorrection) -> Vec<u8> {
+    let capacity = capacity(version, ecc);
+    let mut bits = BitBuffer::default();
+    bits.push(0b0100, 4);
+    bits.push(data.len() as u32, if version <= 9 { 8 } else { 16 });
+    for b in data {
+        bits.push(u32::from(*b), 8);
+    }
+    // A terminator of up to four zeros, zeros to the end of the byte, and
+    // then alternating pad bytes
+    bits.push(0, (capacity - bits.len).min(4));
+    bits.push(0, (8 - bits.len % 8) % 8);
+    for pad in [0xec, 0x11].into_iter().cycle() {
+        if bits.len >= capacity {
+            break;
+        }
+        bits.push(pad, 8);
+    }
+    bits.bytes
+}
+
+#[derive(Default)]
+struct BitBuffer {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitBuffer {
+    // Most significant bit first
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.len % 8 == 0 {
+                self.bytes.push(0);
+            }
+            let bit = (value >> i & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.len % 8);
+            self.len += 1;
+        }
+    }
+}
+
+// Splits the data into blocks, appends error correction to each, and then
+// interleaves them, so that damage to one part of the code is spread out
+fn interleave(data: &[u8], version: usize, ecc: ErrorCorrection) -> Vec<u8> {
+    let block_count = usize::from(ERROR_CORRECTION_BLOCKS[ecc as usize][version]);
+    let ecc_len = usize::from(ECC_CODEWORDS_PER_BLOCK[ecc as usize][version]);
+    let raw_codewords = raw_module_count(version) / 8;
+    // Blocks after the short ones have one more data codeword
+    let short_count = block_count - raw_codewords % block_count;
+    let short_len = raw_codewords / block_count - ecc_len;
+
+    let divisor = reed_solomon_divisor(ecc_len);
+    let mut blocks = Vec::with_capacity(block_count);
+    let mut rest = data;
+    for i in 0..block_count {
+        let len = short_len + usize::from(i >= short_count);
+        let (block, after) = rest.split_at(len);
+        rest = after;
+        blocks.push((block, reed_solomon_remainder(block, &divisor)));
+    }
+
+    let mut out = Vec::with_capacity(raw_codewords);
+    for i in 0..=short_len {
+        out.extend(blocks.iter().filter_map(|(block, _)| block.get(i)));
+    }
+    for i in 0..ecc_len {
+        out.extend(blocks.iter().map(|(_, ecc)| ecc[i]));
+    }
+    out
+}
+
+// The generator polynomial of degree `degree`, highest power first, with its
+// leading coefficient of one left out
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut divisor = vec![0; degree];
+    divisor[degree - 1] = 1;
+    // Multiply by (x - r^i) for each i, where r = 2 generates the field
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            divisor[j] = gf_multiply(divisor[j], root);
+            if j + 1 < degree {
+                divisor[j] ^= divisor[j + 1];
+            }
+        }
+        root = gf_multiply(root, 2);
+    }
+    divisor
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut remainder = vec![0; divisor.len()];
+    for b in data {
+        let factor = b ^ remainder.remove(0);
+        remainder.push(0);
+        for (r, d) in remainder.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(*d, factor);
+        }
+    }
+    remainder
+}
+
+// In GF(2^8), modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut product = 0u16;
+    for i in (0..8).rev() {
+        product = (product << 1) ^ ((product >> 7) * 0x11d);
+        product ^= u16::from(y >> i & 1) * u16::from(x);
+    }
+    product as u8
+}
+
+#[derive(Clone)]
+struct Grid {
+    size: usize,
+    modules: Vec<bool>,
+    // Modules which hold something other than codewords, and so are left
+    // alone by masks
+    function: Vec<bool>,
+}
+
+impl Grid {
+    // With everything but the codewords drawn, and room left for the format
+    fn new(version: usize) -> Self {
+        let size = size(version);
+        let mut grid = Self {
+            size,
+            modules: vec![false; size * size],
+            function: vec![false; size * size],
+        };
+
+        for i in 0..size {
+            grid.set_function(6, i, i % 2 == 0);
+            grid.set_function(i, 6, i % 2 == 0);
+        }
+
+        // Finder patterns in three corners, each with a light border
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4..=4isize {
+                for dx in -4..=4isize {
+                    let (x, y) = (cx as isize + dx, cy as isize + dy);
+                    if (0..size as isize).contains(&x) && (0..size as isize).contains(&y) {
+                        let ring = dx.abs().max(dy.abs());
+                        grid.set_function(x as usize, y as usize, ring != 2 && ring != 4);
+                    }
+                }
+            }
+        }
+
+        // Alignment patterns wherever they don't overlap the finders
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, cx) in positions.iter().enumerate() {
+            for (j, cy) in positions.iter().enumerate() {
+                if (i, j) == (0, 0) || (i, j) == (0, last) || (i, j) == (last, 0) {
+                    continue;
+                }
+                for dy in -2..=2isize {
+                    for dx in -2..=2isize {
+                        let x = (*cx as isize + dx) as usize;
+                        let y = (*cy as isize + dy) as usize;
+                        grid.set_function(x, y, dx.abs().max(dy.abs()) != 1);
+                    }
+                }
+            }
+        }
+
+        grid.draw_format(ErrorCorrection::Low, 0);
+
+        if version >= 7 {
+            let bits = bch(version as u32, 0x1f25, 12);
+            for i in 0..18 {
+                let dark = bits >> i & 1 != 0;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                grid.set_function(a, b, dark);
+                grid.set_function(b, a, dark);
+            }
+        }
+
+        grid
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[x + y * self.size] = dark;
+        self.function[x + y * self.size] = true;
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.modules[x + y * self.size]
+    }
+
+    // Both copies of the error correction level and mask, along with the
+    // module which is always dark
+    fn draw_format(&mut self, ecc: ErrorCorrection, mask: u32) {
+        let bits = bch(ecc.format_bits() << 3 | mask, 0x537, 10) ^ 0x5412;
+        let bit = |i: usize| bits >> i & 1 != 0;
+        let size = self.size;
+
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    // In pairs of columns from the right, zigzagging up and then down, and
+    // skipping the vertical timing pattern. Any bits left over are light.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let mut bits = codewords
+            .iter()
+            .flat_map(|b| (0..8).rev().map(move |i| b >> i & 1 != 0));
+        let mut right = self.size - 1;
+        loop {
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..self.size {
+                let y = if upward {
+                    self.size - 1 - vertical
+                } else {
+                    vertical
+                };
+                for x in [right, right - 1] {
+                    if !self.function[x + y * self.size] {
+                        self.modules[x + y * self.size] = bits.next().unwrap_or(false);
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && !self.function[x + y * self.size] {
+                    self.modules[x + y * self.size] ^= true;
+                }
+            }
+        }
+    }
+
+    fn penalty(&self) -> usize {
+        let mut penalty = 0;
+
+        // Runs of five or more alike, and patterns that look like finders,
+        // along rows and then columns
+        for transpose in [false, true] {
+            for i in 0..self.size {
+                let module = |j: usize| {
+                    if transpose {
+                        self.get(i, j)
+                    } else {
+                        self.get(j, i)
+                    }
+                };
+                let mut runs = Runs::new(self.size);
+                let mut colour = false;
+                let mut len = 0;
+                for j in 0..self.size {
+                    if module(j) == colour {
+                        len += 1;
+                        match len {
+                            5 => penalty += 3,
+                            6.. => penalty += 1,
+                            _ => {}
+                        }
+                    } else {
+                        runs.push(len);
+                        if !colour {
+                            penalty += runs.finder_like() * 40;
+                        }
+                        colour = module(j);
+                        len = 1;
+                    }
+                }
+                penalty += runs.finish(colour, len) * 40;
+            }
+        }
+
+        // Blocks of two by two alike
+        for y in 0..self.size - 1 {
+            for x in 0..self.size - 1 {
+                let colour = self.get(x, y);
+                if colour == self.get(x + 1, y)
+                    && colour == self.get(x, y + 1)
+                    && colour == self.get(x + 1, y + 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+
+        // How far the proportion of dark modules strays from a half, in
+        // steps of 5%
+        let dark = self.modules.iter().filter(|dark| **dark).count();
+        let total = self.modules.len();
+        let k = ((dark * 20).abs_diff(total * 10) + total - 1) / total - 1;
+        penalty + k * 10
+    }
+}
+
+// The lengths of the last seven runs along a row or column, most recent
+// first, starting with a light one
+struct Runs {
+    size: usize,
+    lengths: [usize; 7],
+}
+
+impl Runs {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            lengths: [0; 7],
+        }
+    }
+
+    fn push(&mut self, mut len: usize) {
+        // Outside of the code counts as light
+        if self.lengths[0] == 0 {
+            len += self.size;
+        }
+        self.lengths.copy_within(0..6, 1);
+        self.lengths[0] = len;
+    }
+
+    // Called after pushing a light run: dark, light, dark three times as
+    // wide, light, dark, with four times the width of light on one side
+    fn finder_like(&self) -> usize {
+        let [after, a, b, c, d, e, before] = self.lengths;
+        let n = a;
+        let core = n > 0 && b == n && c == n * 3 && d == n && e == n;
+        usize::from(core && after >= n * 4 && before >= n)
+            + usize::from(core && before >= n * 4 && after >= n)
+    }
+
+    fn finish(mut self, colour: bool, mut len: usize) -> usize {
+        if colour {
+            self.push(len);
+            len = 0;
+        }
+        self.push(len + self.size);
+        self.finder_like()
+    }
+}
+
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+    };
+    let mut positions = vec![6];
+    positions.extend((0..count - 1).rev().map(|i| size(version) - 7 - i * step));
+    positions
+}
+
+// `value` followed by the remainder of its division by `generator`, which is
+// of degree `degree`
+fn bch(value: u32, generator: u32, degree: u32) -> u32 {
+    let mut remainder = value;
+    for _ in 0..degree {
+        remainder = (remainder << 1) ^ ((remainder >> (degree - 1)) * generator);
+    }
+    value << degree | remainder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::string::String;
+    use qrcodegen::{QrCodeEcc, Version};
+
+    fn reference(data: &[u8], ecc: QrCodeEcc) -> qrcodegen::QrCode {
+        let segments = [qrcodegen::QrSegment::make_bytes(data)];
+        qrcodegen::QrCode::encode_segments_advanced(
+            &segments,
+            ecc,
+            Version::MIN,
+            Version::MAX,
+            None,
+            true,
+        )
+        .unwrap()
+    }
+
+    fn assert_matches(ours: &QrCode, reference: &qrcodegen::QrCode) {
+        assert_eq!(ours.size() as i32, reference.size());
+        for y in 0..ours.size() {
+            for x in 0..ours.size() {
+                assert_eq!(
+                    ours.is_dark(x, y),
+                    reference.get_module(x as i32, y as i32),
+                    "module ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matches_reference_across_versions() {
+        // Lengths either side of where the version steps up, from the
+        // smallest code to one with version information
+        for len in [0, 1, 13, 14, 50, 100, 200, 271, 272, 400, 1000, 2953] {
+            let data = (0..len).map(|i| (i * 7 + 3) as u8).collect::<Vec<u8>>();
+            for (ecc, reference_ecc) in [
+                (ErrorCorrection::Low, QrCodeEcc::Low),
+                (ErrorCorrection::Medium, QrCodeEcc::Medium),
+                (ErrorCorrection::High, QrCodeEcc::High),
+            ] {
+                let Some(ours) = QrCode::encode(&data, ecc) else {
+                    assert!(len > 1273, "{} bytes", len);
+                    continue;
+                };
+                assert_matches(&ours, &reference(&data, reference_ecc));
+            }
+        }
+    }
+
+    #[test]
+    fn too_long() {
+        assert!(QrCode::encode(&[0; 2953], ErrorCorrection::Low).is_some());
+        assert!(QrCode::encode(&[0; 2954], ErrorCorrection::Low).is_none());
+    }
+
+    #[test]
+    fn rendering() {
+        let code = QrCode::encode(b"banscii", ErrorCorrection::Low).unwrap();
+        assert_eq!(code.size(), 21);
+        let side = 21 + 2 * QUIET_ZONE;
+
+        let mut ascii = String::new();
+        code.write_ascii(&mut ascii).unwrap();
+        let lines = ascii.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), side);
+        assert!(lines.iter().all(|line| line.len() == side * 2));
+        // The top left finder, after the quiet zone
+        assert_eq!(&lines[4][8..22], "##############");
+        assert_eq!(&lines[5][8..22], "##          ##");
+
+        let mut unicode = String::new();
+        code.write_unicode(&mut unicode).unwrap();
+        let lines = unicode.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), (side + 1) / 2);
+        assert!(lines.iter().all(|line| line.chars().count() == side));
+        assert!(lines[2].chars().skip(4).take(7).eq("█▀▀▀▀▀█".chars()));
+    }
+}
@@ -0,0 +1,351 @@
+//! Ways of printing a signature, for whoever has to carry it off the device.
+//!
+//! The armoured block is modelled on OpenPGP's ASCII armour (RFC 4880), with
+//! the work's serial number and edition as headers and a CRC-24 of the
+//! signature as a checksum:
+//!
+//! ```text
+//! -----BEGIN BANSCII SIGNATURE-----
+//! Serial: 7
+//! Edition: 2
+//!
+//! P0MU7xjdI4DOV5eLaJKcEUTtQGal9y8UrISWPDhw3R+nzsPO08T30nw6t3D8Ajd...
+//! =lKXV
+//! -----END BANSCII SIGNATURE-----
+//! ```
+//!
+//! A QR code holds `banscii:<serial>:<edition>:<signature in base64>`.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::base64;
+use crate::qr::{ErrorCorrection, QrCode};
+
+pub const BEGIN_MARKER: &str = "-----BEGIN BANSCII SIGNATURE-----";
+pub const END_MARKER: &str = "-----END BANSCII SIGNATURE-----";
+
+// Characters of base64 per line, as in PEM
+const LINE_LEN: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SignatureEncoding {
+    /// Lines of 32 bytes.
+    #[default]
+    Hex,
+    Base64,
+    Armour,
+    /// Drawn with half blocks.
+    Qr,
+    /// Drawn with `#`, for terminals without Unicode.
+    QrAscii,
+}
+
+impl fmt::Display for SignatureEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Hex => write!(f, "hex"),
+            Self::Base64 => write!(f, "base64"),
+            Self::Armour => write!(f, "armour"),
+            Self::Qr => write!(f, "qr"),
+            Self::QrAscii => write!(f, "qr-ascii"),
+        }
+    }
+}
+
+impl FromStr for SignatureEncoding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(Self::Hex),
+            "base64" => Ok(Self::Base64),
+            "armour" | "armor" | "pem" => Ok(Self::Armour),
+            "qr" => Ok(Self::Qr),
+            "qr-ascii" => Ok(Self::QrAscii),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Writes `signature` as `encoding`, ending with a newline. A signature too
+/// long for a QR code is armoured instead.
+pub fn write(
+    out: &mut impl fmt::Write,
+    encoding: SignatureEncoding,
+    signature: &[u8],
+    serial: usize,
+    edition: usize,
+) -> fmt::Result {
+    match encoding {
+        SignatureEncoding::Hex => {
+            for line in signature.chunks(32) {
+                for b in line {
+                    write!(out, "{:02x}", b)?;
+                }
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        SignatureEncoding::Base64 => write_wrapped(out, &base64::encode(signature)),
+        SignatureEncoding::Armour => out.write_str(&armour(signature, serial, edition)),
+        SignatureEncoding::Qr | SignatureEncoding::QrAscii => {
+            let payload = qr_payload(signature, serial, edition);
+            match QrCode::encode(payload.as_bytes(), ErrorCorrection::Low) {
+                Some(code) if encoding == SignatureEncoding::Qr => code.write_unicode(out),
+                Some(code) => code.write_ascii(out),
+                None => out.write_str(&armour(signature, serial, edition)),
+            }
+        }
+    }
+}
+
+fn write_wrapped(out: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    // Base64 is ASCII, so this never splits a character
+    for line in s.as_bytes().chunks(LINE_LEN) {
+        writeln!(out, "{}", core::str::from_utf8(line).unwrap())?;
+    }
+    Ok(())
+}
+
+pub fn qr_payload(signature: &[u8], serial: usize, edition: usize) -> String {
+    format!(
+        "banscii:{}:{}:{}",
+        serial,
+        edition,
+        base64::encode(signature)
+    )
+}
+
+pub fn armour(signature: &[u8], serial: usize, edition: usize) -> String {
+    let mut out = format!(
+        "{}\nSerial: {}\nEdition: {}\n\n",
+        BEGIN_MARKER, serial, edition
+    );
+    write_wrapped(&mut out, &base64::encode(signature)).unwrap();
+    let checksum = crc24(signature).to_be_bytes();
+    out.push('=');
+    out.push_str(&base64::encode(&checksum[1..]));
+    out.push('\n');
+    out.push_str(END_MARKER);
+    out.push('\n');
+    out
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Armoured {
+    pub signature: Vec<u8>,
+    pub serial: Option<usize>,
+    pub edition: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArmourError {
+    Malformed(&'static str),
+    /// The checksum doesn't match, so something was mistyped or lost.
+    Checksum,
+}
+
+impl fmt::Display for ArmourError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed(what) => write!(f, "malformed signature block: {}", what),
+            Self::Checksum => write!(f, "signature block fails its checksum"),
+        }
+    }
+}
+
+/// Reads back a block written by [`armour`]. Surrounding text and unknown
+/// headers are ignored, and the checksum is optional.
+pub fn dearmour(text: &str) -> Result<Armoured, ArmourError> {
+    let mut lines = text.lines().map(str::trim);
+    lines
+        .find(|line| *line == BEGIN_MARKER)
+        .ok_or(ArmourError::Malformed("no begin marker"))?;
+
+    let mut serial = None;
+    let mut edition = None;
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or(ArmourError::Malformed("bad header"))?;
+        let value = value.trim().parse();
+        match key {
+            "Serial" => serial = Some(value.map_err(|_| ArmourError::Malformed("bad serial"))?),
+            "Edition" => edition = Some(value.map_err(|_| ArmourError::Malformed("bad edition"))?),
+            _ => {}
+        }
+    }
+
+    let mut body = String::new();
+    let mut checksum = None;
+    for line in lines {
+        if line == END_MARKER {
+            let signature = base64::decode(&body).ok_or(ArmourError::Malformed("bad base64"))?;
+            if let Some(checksum) = checksum {
+                if checksum != crc24(&signature) {
+                    return Err(ArmourError::Checksum);
+                }
+            }
+            return Ok(Armoured {
+                signature,
+                serial,
+                edition,
+            });
+        }
+        match line.strip_prefix('=') {
+            Some(encoded) => {
+                let bytes = base64::decode(encoded)
+                    .filter(|bytes| bytes.len() == 3)
+                    .ok_or(ArmourError::Malformed("bad checksum"))?;
+                checksum = Some(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]));
+            }
+            None if checksum.is_some() => {
+                return Err(ArmourError::Malformed("data after the checksum"))
+            }
+            None => body.push_str(line),
+        }
+    }
+    Err(ArmourError::Malformed("no end marker"))
+}
+
+// As in RFC 4880, section 6.1
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc = 0xb7_04ce;
+    for b in data {
+        crc ^= u32::from(*b) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= 0x186_4cfb;
+            }
+        }
+    }
+    crc & 0xff_ffff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::string::ToString;
+
+    fn signature() -> Vec<u8> {
+        (0..=255).collect()
+    }
+
+    fn written(encoding: SignatureEncoding) -> String {
+        let mut out = String::new();
+        write(&mut out, encoding, &signature(), 7, 2).unwrap();
+        out
+    }
+
+    #[test]
+    fn hex_and_base64() {
+        let hex = written(SignatureEncoding::Hex);
+        assert_eq!(hex.lines().count(), 8);
+        assert!(hex.starts_with("000102"));
+        assert!(hex.lines().all(|line| line.len() == 64));
+
+        let base64 = written(SignatureEncoding::Base64);
+        let lines = base64.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[..5].iter().all(|line| line.len() == LINE_LEN));
+        assert_eq!(base64::decode(&base64).unwrap(), signature());
+    }
+
+    #[test]
+    fn crc24_check_value() {
+        assert_eq!(crc24(b"123456789"), 0x21_cf02);
+    }
+
+    #[test]
+    fn armour_round_trip() {
+        let armoured = written(SignatureEncoding::Armour);
+        assert!(
+            armoured.starts_with("-----BEGIN BANSCII SIGNATURE-----\nSerial: 7\nEdition: 2\n\n")
+        );
+        assert!(armoured.ends_with("\n-----END BANSCII SIGNATURE-----\n"));
+        let expected = Armoured {
+            signature: signature(),
+            serial: Some(7),
+            edition: Some(2),
+        };
+        assert_eq!(dearmour(&armoured), Ok(expected.clone()));
+
+        // Pasted with other text around it and different line endings
+        let pasted = format!(
+            "banscii> \r\n  {}\r\nbanscii> ",
+            armoured.replace('\n', "\r\n")
+        );
+        assert_eq!(dearmour(&pasted), Ok(expected));
+    }
+
+    #[test]
+    fn armour_damage() {
+        let armoured = written(SignatureEncoding::Armour);
+        // A character mistyped in the body
+        let typo = armoured.replacen("AAEC", "AAED", 1);
+        assert_eq!(dearmour(&typo), Err(ArmourError::Checksum));
+        // A line lost
+        let lines = armoured.lines().collect::<Vec<_>>();
+        let lost = [&lines[..5], &lines[6..]].concat().join("\n");
+        assert!(dearmour(&lost).is_err());
+
+        let unterminated = &armoured[..armoured.find(END_MARKER).unwrap()];
+        assert_eq!(
+            dearmour(unterminated),
+            Err(ArmourError::Malformed("no end marker"))
+        );
+        assert_eq!(
+            dearmour("hello"),
+            Err(ArmourError::Malformed("no begin marker"))
+        );
+    }
+
+    #[test]
+    fn qr() {
+        assert_eq!(qr_payload(&[0xff, 0x00], 12, 3), "banscii:12:3:/wA=");
+        let qr = written(SignatureEncoding::Qr);
+        let code = QrCode::encode(
+            qr_payload(&signature(), 7, 2).as_bytes(),
+            ErrorCorrection::Low,
+        )
+        .unwrap();
+        // Half blocks, with the quiet zone on every side
+        assert_eq!(qr.lines().count(), (code.size() + 8 + 1) / 2);
+        assert!(qr
+            .lines()
+            .all(|line| line.chars().count() == code.size() + 8));
+        assert_eq!(
+            written(SignatureEncoding::QrAscii).lines().count(),
+            code.size() + 8
+        );
+
+        // Too long for any QR code
+        let mut out = String::new();
+        write(&mut out, SignatureEncoding::Qr, &[0; 3000], 1, 1).unwrap();
+        assert!(out.starts_with(BEGIN_MARKER));
+    }
+
+    #[test]
+    fn encoding_names() {
+        for encoding in [
+            SignatureEncoding::Hex,
+            SignatureEncoding::Base64,
+            SignatureEncoding::Armour,
+            SignatureEncoding::Qr,
+            SignatureEncoding::QrAscii,
+        ] {
+            assert_eq!(encoding.to_string().parse(), Ok(encoding));
+        }
+        assert_eq!("pem".parse(), Ok(SignatureEncoding::Armour));
+        assert_eq!("jpeg".parse::<SignatureEncoding>(), Err(()));
+    }
+}
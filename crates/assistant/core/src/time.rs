@@ -0,0 +1,102 @@
+//! Times as told by the timer, written out for a person to read.
+
+use core::fmt;
+use core::time::Duration;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Seconds since the Unix epoch, shown as a date and time in UTC, such as
+/// `2023-11-14 22:13:20 UTC`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnixTime(pub u64);
+
+impl UnixTime {
+    /// The year, month, and day, by the proleptic Gregorian calendar.
+    pub fn date(&self) -> (u64, u32, u32) {
+        civil_from_days(self.0 / SECS_PER_DAY)
+    }
+}
+
+impl fmt::Display for UnixTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = self.date();
+        let secs = self.0 % SECS_PER_DAY;
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+        )
+    }
+}
+
+// Derived from Howard Hinnant's `civil_from_days`:
+// https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    // Counted from 0000-03-01, so that the leap day ends the year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A duration in milliseconds, to the microsecond, such as `12.345 ms`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Millis(pub Duration);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let micros = self.0.as_micros();
+        write!(f, "{}.{:03} ms", micros / 1000, micros % 1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::format;
+
+    #[test]
+    fn unix_time() {
+        assert_eq!(format!("{}", UnixTime(0)), "1970-01-01 00:00:00 UTC");
+        assert_eq!(
+            format!("{}", UnixTime(1_700_000_000)),
+            "2023-11-14 22:13:20 UTC"
+        );
+        // Either side of a leap day, and of a century which isn't a leap year
+        assert_eq!(UnixTime(951_782_400).date(), (2000, 2, 29));
+        assert_eq!(UnixTime(951_868_800).date(), (2000, 3, 1));
+        assert_eq!(UnixTime(4_107_542_400).date(), (2100, 3, 1));
+        assert_eq!(UnixTime(4_107_542_399).date(), (2100, 2, 28));
+        assert_eq!(
+            format!("{}", UnixTime(u32::MAX.into())),
+            "2106-02-07 06:28:15 UTC"
+        );
+    }
+
+    #[test]
+    fn millis() {
+        assert_eq!(
+            format!("{}", Millis(Duration::from_micros(12_345))),
+            "12.345 ms"
+        );
+        assert_eq!(format!("{}", Millis(Duration::from_nanos(999))), "0.000 ms");
+        assert_eq!(format!("{}", Millis(Duration::from_secs(2))), "2000.000 ms");
+    }
+}
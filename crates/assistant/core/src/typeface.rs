@@ -0,0 +1,134 @@
+use alloc::boxed::Box;
+
+use once_cell::race::OnceBox;
+use rusttype::{Font, GlyphId, Scale};
+use ttf_parser::gpos::{PairAdjustment, PositioningSubtable};
+use ttf_parser::opentype_layout::{LookupIndex, LookupList};
+use ttf_parser::{LazyArray16, Tag};
+
+/// A font, along with the kerning that rusttype doesn't know about.
+///
+/// rusttype's `pair_kerning` only reads the legacy `kern` table, whereas most
+/// fonts made in the last couple of decades, the bundled one included, only
+/// kern through pair adjustments in the `GPOS` table.
+pub struct Typeface<'a> {
+    font: Font<'a>,
+    gpos_kerning: Option<GposKerning<'a>>,
+    max_kerning: OnceBox<f32>,
+}
+
+// The lookups of the `GPOS` table's `kern` feature
+struct GposKerning<'a> {
+    lookups: LookupList<'a>,
+    indices: LazyArray16<'a, LookupIndex>,
+}
+
+impl<'a> Typeface<'a> {
+    pub fn try_from_bytes(data: &'a [u8]) -> Option<Self> {
+        let font = Font::try_from_bytes(data)?;
+        let face = ttf_parser::Face::from_slice(data, 0).ok()?;
+        let gpos_kerning = face.tables().gpos.and_then(|gpos| {
+            let feature = gpos.features.find(Tag::from_bytes(b"kern"))?;
+            Some(GposKerning {
+                lookups: gpos.lookups,
+                indices: feature.lookup_indices,
+            })
+        });
+        Some(Self {
+            font,
+            gpos_kerning,
+            max_kerning: OnceBox::new(),
+        })
+    }
+
+    /// Takes the bound for [`Self::max_kerning`] as already worked out, in
+    /// font units, rather than trying every pair of glyphs for it.
+    pub(crate) fn with_max_kerning(self, unscaled: f32) -> Self {
+        let _ = self.max_kerning.set(Box::new(unscaled));
+        self
+    }
+
+    pub(crate) fn font(&self) -> &Font<'a> {
+        &self.font
+    }
+
+    /// Additional space between a pair of glyphs, on top of the first one's
+    /// advance width.
+    pub(crate) fn pair_kerning(&self, scale: Scale, first: GlyphId, second: GlyphId) -> f32 {
+        self.factor(scale) * self.unscaled_pair_kerning(first, second)
+    }
+
+    /// An upper bound on what [`Self::pair_kerning`] adds between any pair of
+    /// glyphs, or zero if kerning only ever brings glyphs closer together.
+    ///
+    /// Unless given with [`Self::with_max_kerning`], it's worked out on first
+    /// use.
+    pub(crate) fn max_kerning(&self, scale: Scale) -> f32 {
+        let max = self
+            .max_kerning
+            .get_or_init(|| Box::new(self.unscaled_max_kerning()));
+        self.factor(scale) * max
+    }
+
+    /// [`Self::max_kerning`] in font units, found by trying every pair of
+    /// glyphs.
+    pub(crate) fn unscaled_max_kerning(&self) -> f32 {
+        let glyphs = || (0..self.font.glyph_count()).map(|id| GlyphId(id as u16));
+        glyphs()
+            .flat_map(|first| glyphs().map(move |second| (first, second)))
+            .map(|(first, second)| self.unscaled_pair_kerning(first, second))
+            .fold(0.0, f32::max)
+    }
+
+    // As rusttype scales the legacy table's values
+    fn factor(&self, scale: Scale) -> f32 {
+        self.font.scale_for_pixel_height(scale.y) * (scale.x / scale.y)
+    }
+
+    // In font units, preferring the legacy table where it has the pair
+    fn unscaled_pair_kerning(&self, first: GlyphId, second: GlyphId) -> f32 {
+        let v_metrics = self.font.v_metrics_unscaled();
+        let unscaled = Scale::uniform(v_metrics.ascent - v_metrics.descent);
+        let legacy = self.font.pair_kerning(unscaled, first, second);
+        if legacy != 0.0 {
+            return legacy;
+        }
+        f32::from(self.gpos_pair_kerning(first, second))
+    }
+
+    // In font units
+    fn gpos_pair_kerning(&self, first: GlyphId, second: GlyphId) -> i16 {
+        let Some(kerning) = &self.gpos_kerning else {
+            return 0;
+        };
+        let first = ttf_parser::GlyphId(first.0);
+        let second = ttf_parser::GlyphId(second.0);
+        for index in kerning.indices {
+            let Some(lookup) = kerning.lookups.get(index) else {
+                continue;
+            };
+            for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+                let PositioningSubtable::Pair(pair) = subtable else {
+                    continue;
+                };
+                let Some(coverage_index) = pair.coverage().get(first) else {
+                    continue;
+                };
+                let records = match pair {
+                    PairAdjustment::Format1 { sets, .. } => {
+                        sets.get(coverage_index).and_then(|set| set.get(second))
+                    }
+                    PairAdjustment::Format2 {
+                        classes, matrix, ..
+                    } => matrix.get((classes.0.get(first), classes.1.get(second))),
+                };
+                // The first pair which covers these glyphs is the one that
+                // applies, as in a shaper
+                if let Some((record, _)) = records {
+                    return record.x_advance;
+                }
+            }
+        }
+        0
+    }
+}
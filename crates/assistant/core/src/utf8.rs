@@ -0,0 +1,146 @@
+use core::str;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidSequence;
+
+/// Decodes UTF-8 one byte at a time, so that a code point may arrive split
+/// across any number of reads.
+#[derive(Clone, Debug, Default)]
+pub struct Utf8Decoder {
+    buf: [u8; 4],
+    len: usize,
+    needed: usize,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.len != 0
+    }
+
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.needed = 0;
+    }
+
+    /// A byte which interrupts an incomplete sequence both invalidates that
+    /// sequence and starts something new, so up to two results may come out.
+    pub fn push(&mut self, b: u8) -> impl Iterator<Item = Result<char, InvalidSequence>> {
+        let mut results = [None, None];
+        if self.is_pending() {
+            if is_continuation(b) {
+                self.buf[self.len] = b;
+                self.len += 1;
+                if self.len == self.needed {
+                    results[0] = Some(self.finish());
+                }
+                return results.into_iter().flatten();
+            }
+            self.reset();
+            results[0] = Some(Err(InvalidSequence));
+        }
+        results[1] = self.start(b);
+        results.into_iter().flatten()
+    }
+
+    fn start(&mut self, b: u8) -> Option<Result<char, InvalidSequence>> {
+        let needed = match b {
+            0x00..=0x7f => return Some(Ok(char::from(b))),
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return Some(Err(InvalidSequence)),
+        };
+        self.buf[0] = b;
+        self.len = 1;
+        self.needed = needed;
+        None
+    }
+
+    fn finish(&mut self) -> Result<char, InvalidSequence> {
+        // Leaves rejecting overlong encodings, surrogates, and out-of-range
+        // code points to core
+        let result = str::from_utf8(&self.buf[..self.len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .ok_or(InvalidSequence);
+        self.reset();
+        result
+    }
+}
+
+fn is_continuation(b: u8) -> bool {
+    b & 0xc0 == 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    fn decode(decoder: &mut Utf8Decoder, bytes: &[u8]) -> Vec<Result<char, InvalidSequence>> {
+        bytes.iter().flat_map(|b| decoder.push(*b)).collect()
+    }
+
+    #[test]
+    fn ascii() {
+        let mut decoder = Utf8Decoder::new();
+        assert_eq!(decode(&mut decoder, b"a\r"), [Ok('a'), Ok('\r')]);
+    }
+
+    #[test]
+    fn multi_byte() {
+        let mut decoder = Utf8Decoder::new();
+        let s = "é€😀";
+        let decoded = decode(&mut decoder, s.as_bytes());
+        assert_eq!(decoded, s.chars().map(Ok).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn split_across_pushes() {
+        let mut decoder = Utf8Decoder::new();
+        let bytes = "€".as_bytes();
+        assert!(decode(&mut decoder, &bytes[..1]).is_empty());
+        assert!(decoder.is_pending());
+        assert!(decode(&mut decoder, &bytes[1..2]).is_empty());
+        assert_eq!(decode(&mut decoder, &bytes[2..]), [Ok('€')]);
+        assert!(!decoder.is_pending());
+    }
+
+    #[test]
+    fn invalid() {
+        let mut decoder = Utf8Decoder::new();
+        // Stray continuation byte, invalid lead bytes
+        assert_eq!(
+            decode(&mut decoder, b"\x80\xc0\xff"),
+            [Err(InvalidSequence); 3]
+        );
+        // Overlong encoding of '/'
+        assert_eq!(
+            decode(&mut decoder, b"\xe0\x80\xaf"),
+            [Err(InvalidSequence)]
+        );
+        // Surrogate
+        assert_eq!(
+            decode(&mut decoder, b"\xed\xa0\x80"),
+            [Err(InvalidSequence)]
+        );
+    }
+
+    #[test]
+    fn interrupted() {
+        let mut decoder = Utf8Decoder::new();
+        assert_eq!(
+            decode(&mut decoder, b"\xe2\x82a"),
+            [Err(InvalidSequence), Ok('a')]
+        );
+        assert_eq!(
+            decode(&mut decoder, b"\xe2\xc3\xa9"),
+            [Err(InvalidSequence), Ok('é')]
+        );
+    }
+}
@@ -1,23 +1,162 @@
 #![feature(int_roundings)]
 
-use banscii_assistant_core::Draft;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process::ExitCode;
 
-fn main() {
+use banscii_assistant_core::{export, Align, Draft, RenderOptions};
+
+const USAGE: &str = "\
+usage: banscii-assistant-core-test [options] [subject]
+
+options:
+    --format <format>   ascii, pgm, png, or svg [default: ascii]
+    --size <pixels>     font pixel height [default: 12.4]
+    --no-kerning        ignore the font's pair kerning
+    --spacing <pixels>  extra space between characters [default: 0]
+    --width <pixels>    fixed width to align the subject within
+    --align <align>     left, centre, or right [default: left]
+    --output <path>     where to write the draft [default: stdout]";
+
+#[derive(Clone, Copy)]
+enum Format {
+    Ascii,
+    Pgm,
+    Png,
+    Svg,
+}
+
+struct Options {
+    format: Format,
+    render: RenderOptions,
+    output: Option<String>,
+    subject: String,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            format: Format::Ascii,
+            render: RenderOptions::default(),
+            output: None,
+            subject: "Hello, World!".to_owned(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--format" => {
+                    options.format = match value()?.as_str() {
+                        "ascii" => Format::Ascii,
+                        "pgm" => Format::Pgm,
+                        "png" => Format::Png,
+                        "svg" => Format::Svg,
+                        other => return Err(format!("unknown format '{}'", other)),
+                    }
+                }
+                "--size" => {
+                    let size = value()?;
+                    options.render.size = size
+                        .parse()
+                        .ok()
+                        .filter(|size| {
+                            (RenderOptions::MIN_SIZE..=RenderOptions::MAX_SIZE).contains(size)
+                        })
+                        .ok_or(format!(
+                            "size must be between {} and {}",
+                            RenderOptions::MIN_SIZE,
+                            RenderOptions::MAX_SIZE
+                        ))?;
+                }
+                "--no-kerning" => options.render.kerning = false,
+                "--spacing" => {
+                    let spacing = value()?;
+                    options.render.letter_spacing = spacing
+                        .parse()
+                        .ok()
+                        .filter(|spacing| {
+                            (0.0..=RenderOptions::MAX_LETTER_SPACING).contains(spacing)
+                        })
+                        .ok_or(format!(
+                            "spacing must be between 0 and {}",
+                            RenderOptions::MAX_LETTER_SPACING
+                        ))?;
+                }
+                "--width" => {
+                    let width = value()?;
+                    options.render.width = Some(
+                        width
+                            .parse()
+                            .map_err(|_| format!("invalid width '{}'", width))?,
+                    );
+                }
+                "--align" => {
+                    let align = value()?;
+                    options.render.align = align
+                        .parse::<Align>()
+                        .map_err(|_| format!("unknown alignment '{}'", align))?;
+                }
+                "--output" => options.output = Some(value()?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ => options.subject = arg,
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn main() -> ExitCode {
     env_logger::init();
 
-    let subject = "Hello, World!";
+    if env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let draft = Draft::new(&options.subject, &options.render);
+
+    let bytes = match options.format {
+        Format::Ascii => ascii(&draft),
+        Format::Pgm => export::pgm(&draft),
+        Format::Png => match export::png(&draft) {
+            Some(png) => png,
+            None => {
+                eprintln!("error: can't write an empty draft as a PNG");
+                return ExitCode::FAILURE;
+            }
+        },
+        Format::Svg => export::svg(&draft).into_bytes(),
+    };
 
-    let draft = Draft::new(subject);
+    let written = match &options.output {
+        Some(path) => fs::write(path, bytes),
+        None => io::stdout().write_all(&bytes),
+    };
+    if let Err(err) = written {
+        eprintln!("error: failed to write draft: {}", err);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
 
+fn ascii(draft: &Draft) -> Vec<u8> {
+    let mut out = vec![];
     for row in 0..draft.height {
         for col in 0..draft.width {
             let i = row * draft.width + col;
             let grey = draft.pixel_data[i];
-            let color = colorize(grey);
-            print!("{}", color as char);
+            out.push(colorize(grey));
         }
-        println!();
+        out.push(b'\n');
     }
+    out
 }
 
 const PALETTE: &[u8] = b"@%#x+=:-. ";
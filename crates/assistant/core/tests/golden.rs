@@ -0,0 +1,227 @@
+//! Compares renderings of a corpus of subjects against the files in
+//! `tests/golden`, one file per combination of font, size, and palette, plus
+//! some in the bundled font showing off layout options.
+//!
+//! After an intentional change to rendering, regenerate them with
+//!
+//!     BLESS=1 cargo test -p banscii-assistant-core --test golden
+//!
+//! and review the diff.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use banscii_assistant_core::{Align, Draft, RenderOptions, Typeface};
+
+const SUBJECTS: &[&str] = &[
+    "Hello, World!",
+    "The quick brown fox",
+    "0123456789",
+    "é ñ ü ß €",
+    "中文",
+    "",
+];
+
+// Pairs which the bundled font kerns, and some which it doesn't
+const LAYOUT_SUBJECTS: &[&str] = &["AV", "To", "LT", "PQ", "ZOO", "Bytes", "last step"];
+
+const SIZES: &[f32] = &[RenderOptions::MIN_SIZE, 12.4, RenderOptions::MAX_SIZE];
+
+// Darkest first, as in the artist
+const PALETTES: &[(&str, &[u8])] = &[("standard", b"@%#x+=:-. "), ("binary", b"@ ")];
+
+struct Font {
+    name: &'static str,
+    // None for the one bundled with the assistant
+    typeface: Option<Typeface<'static>>,
+}
+
+fn fonts() -> Vec<Font> {
+    let dejavu_sans_mono = include_bytes!("fonts/dejavu-sans-mono/DejaVuSansMono.ttf");
+    vec![
+        Font {
+            name: "rock-salt",
+            typeface: None,
+        },
+        Font {
+            name: "dejavu-sans-mono",
+            typeface: Some(Typeface::try_from_bytes(dejavu_sans_mono).unwrap()),
+        },
+    ]
+}
+
+fn layouts() -> Vec<(&'static str, RenderOptions)> {
+    let fixed = |align| RenderOptions {
+        width: Some(120),
+        align,
+        ..Default::default()
+    };
+    vec![
+        ("kerned", RenderOptions::default()),
+        (
+            "unkerned",
+            RenderOptions {
+                kerning: false,
+                ..Default::default()
+            },
+        ),
+        (
+            "spaced",
+            RenderOptions {
+                letter_spacing: 3.0,
+                ..Default::default()
+            },
+        ),
+        ("left", fixed(Align::Left)),
+        ("centre", fixed(Align::Centre)),
+        ("right", fixed(Align::Right)),
+    ]
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn header(subject: &str) -> String {
+    format!("# {:?}", subject)
+}
+
+fn render(font: &Font, subjects: &[&str], options: &RenderOptions, palette: &[u8]) -> String {
+    let mut out = String::new();
+    for subject in subjects {
+        let draft = match &font.typeface {
+            Some(typeface) => Draft::with_typeface(subject, typeface, options),
+            None => Draft::new(subject, options),
+        };
+        writeln!(out, "{}", header(subject)).unwrap();
+        for row in draft.pixel_data.chunks(draft.width.max(1)) {
+            let row = row
+                .iter()
+                .map(|grey| char::from(palette[usize::from(*grey) * palette.len() / 256]))
+                .collect::<String>();
+            // Framed, so that trailing spaces survive editors
+            writeln!(out, "|{}|", row).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    out
+}
+
+// Lines which differ, grouped by subject
+fn diff(name: &str, subjects: &[&str], expected: &str, actual: &str) -> String {
+    let mut out = String::new();
+    let sections = |s: &str| -> Vec<String> {
+        subjects
+            .iter()
+            .map(|subject| {
+                s.split(&format!("{}\n", header(subject)))
+                    .nth(1)
+                    .and_then(|rest| rest.split("\n\n").next())
+                    .unwrap_or("")
+                    .to_owned()
+            })
+            .collect()
+    };
+    for ((subject, expected), actual) in subjects
+        .iter()
+        .zip(sections(expected))
+        .zip(sections(actual))
+    {
+        if expected == actual {
+            continue;
+        }
+        writeln!(out, "{} {}:", name, header(subject)).unwrap();
+        let (expected, actual) = (
+            expected.lines().collect::<Vec<_>>(),
+            actual.lines().collect::<Vec<_>>(),
+        );
+        for i in 0..expected.len().max(actual.len()) {
+            let (e, a) = (expected.get(i), actual.get(i));
+            if e != a {
+                if let Some(e) = e {
+                    writeln!(out, "-{}", e).unwrap();
+                }
+                if let Some(a) = a {
+                    writeln!(out, "+{}", a).unwrap();
+                }
+            }
+        }
+    }
+    out
+}
+
+// Checks renderings against golden files, or with BLESS=1, overwrites them
+struct Golden {
+    bless: bool,
+    failures: String,
+}
+
+impl Golden {
+    fn new() -> Self {
+        let bless = env::var_os("BLESS").map_or(false, |v| v == "1");
+        if bless {
+            fs::create_dir_all(golden_dir()).unwrap();
+        }
+        Self {
+            bless,
+            failures: String::new(),
+        }
+    }
+
+    fn check(&mut self, name: &str, subjects: &[&str], actual: &str) {
+        let path = golden_dir().join(name);
+        if self.bless {
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => self
+                .failures
+                .push_str(&diff(name, subjects, &expected, actual)),
+            Err(err) => writeln!(self.failures, "{}: {}", name, err).unwrap(),
+        }
+    }
+
+    fn finish(self) {
+        assert!(
+            self.failures.is_empty(),
+            "renderings differ from golden files:\n{}\nRerun with BLESS=1 to accept them.",
+            self.failures,
+        );
+    }
+}
+
+#[test]
+fn golden() {
+    let mut golden = Golden::new();
+    for font in fonts() {
+        for size in SIZES {
+            let options = RenderOptions {
+                size: *size,
+                ..Default::default()
+            };
+            for (palette_name, palette) in PALETTES {
+                let name = format!("{}-{}-{}.txt", font.name, size, palette_name);
+                let actual = render(&font, SUBJECTS, &options, palette);
+                golden.check(&name, SUBJECTS, &actual);
+            }
+        }
+    }
+    golden.finish();
+}
+
+#[test]
+fn layout() {
+    let mut golden = Golden::new();
+    let bundled = &fonts()[0];
+    let (_, palette) = PALETTES[0];
+    for (layout, options) in layouts() {
+        let name = format!("{}-layout-{}.txt", bundled.name, layout);
+        let actual = render(bundled, LAYOUT_SUBJECTS, &options, palette);
+        golden.check(&name, LAYOUT_SUBJECTS, &actual);
+    }
+    golden.finish();
+}
@@ -0,0 +1,557 @@
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+use core::fmt::Write;
+use core::str;
+
+use sel4cp::message::{MessageInfo, NoMessageValue};
+use sha2::{Digest, Sha256};
+
+use banscii_artist_interface_types as artist;
+use banscii_assistant_core::batch::{self, Batch};
+use banscii_assistant_core::command::{Args, Command};
+use banscii_assistant_core::output::OutputFormat;
+use banscii_assistant_core::signature::SignatureEncoding;
+use banscii_assistant_core::{Align, RenderOptions};
+
+use super::{artist_range, check_artist_status, AssistantError};
+use super::{print_bad_signature, print_work, Gathering, PutCharWrite, ThisHandler, Work};
+use super::{MAX_BATCH_SIZE, MAX_DRAFT_SIZE, TALENT};
+
+pub(super) const COMMANDS: &[Command<ThisHandler, AssistantError>] = &[
+    Command {
+        name: "help",
+        usage: "",
+        summary: "list commands",
+        run: help,
+    },
+    Command {
+        name: "set",
+        usage: "<key> <value>",
+        summary: "change a setting",
+        run: set,
+    },
+    Command {
+        name: "show",
+        usage: "settings",
+        summary: "show current settings",
+        run: show,
+    },
+    Command {
+        name: "batch",
+        usage: "",
+        summary: "complete several subjects, reporting each as JSON",
+        run: batch,
+    },
+    Command {
+        name: "history",
+        usage: "",
+        summary: "list previous subjects (recall with !! or !n)",
+        run: history,
+    },
+    Command {
+        name: "gallery",
+        usage: "<what> [n]",
+        summary: "list works since starting, or show, export, or drop one",
+        run: gallery,
+    },
+    Command {
+        name: "pubkey",
+        usage: "",
+        summary: "print the artist's public key",
+        run: pubkey,
+    },
+    Command {
+        name: "verify",
+        usage: "[artist]",
+        summary: "check the most recent work's signature",
+        run: verify,
+    },
+    Command {
+        name: "audit",
+        usage: "",
+        summary: "check the artist's log of every work it has signed",
+        run: audit,
+    },
+    Command {
+        name: "stats",
+        usage: "",
+        summary: "show the artist's counters and the glyph cache's",
+        run: stats,
+    },
+    Command {
+        name: "clear",
+        usage: "",
+        summary: "clear the screen",
+        run: clear,
+    },
+];
+
+// For mistakes on the user's part, which don't warrant resetting the REPL
+fn report(err: impl fmt::Display) -> Result<(), AssistantError> {
+    writeln!(PutCharWrite, "error: {}", err)?;
+    Ok(())
+}
+
+fn help(_handler: &mut ThisHandler, args: Args) -> Result<(), AssistantError> {
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+    writeln!(PutCharWrite, "Commands (prefix with ':' or '/'):")?;
+    for command in COMMANDS {
+        let synopsis = format!("{} {}", command.name, command.usage);
+        writeln!(PutCharWrite, "  {:<20}{}", synopsis, command.summary)?;
+    }
+    writeln!(PutCharWrite, "Other lines are taken as subjects.")?;
+    Ok(())
+}
+
+fn set(handler: &mut ThisHandler, mut args: Args) -> Result<(), AssistantError> {
+    let key = match args.required("key") {
+        Ok(key) => key,
+        Err(err) => return report(err),
+    };
+    let value = args.rest();
+    if value.is_empty() {
+        return report("missing argument <value>");
+    }
+    if key == "output" {
+        match value.parse::<OutputFormat>() {
+            Ok(output) => handler.output = output,
+            Err(()) => return report("output must be 'human', 'json', or 'cbor'"),
+        }
+        return Ok(());
+    }
+    if key == "signature" {
+        match value.parse::<SignatureEncoding>() {
+            Ok(encoding) => handler.signature_encoding = encoding,
+            Err(()) => {
+                return report("signature must be 'hex', 'base64', 'armour', 'qr', or 'qr-ascii'")
+            }
+        }
+        return Ok(());
+    }
+    if key == "verify" {
+        match value {
+            "on" => handler.verify = true,
+            "off" => handler.verify = false,
+            _ => return report("verify must be 'on' or 'off'"),
+        }
+        return Ok(());
+    }
+    let mut options = handler.render_options.clone();
+    match key {
+        "size" => match value.parse::<f32>() {
+            Ok(size) if (RenderOptions::MIN_SIZE..=RenderOptions::MAX_SIZE).contains(&size) => {
+                options.size = size;
+            }
+            _ => {
+                return report(format!(
+                    "size must be a number between {} and {}",
+                    RenderOptions::MIN_SIZE,
+                    RenderOptions::MAX_SIZE,
+                ))
+            }
+        },
+        "kerning" => match value {
+            "on" => options.kerning = true,
+            "off" => options.kerning = false,
+            _ => return report("kerning must be 'on' or 'off'"),
+        },
+        "spacing" => match value.parse::<f32>() {
+            Ok(spacing) if (0.0..=RenderOptions::MAX_LETTER_SPACING).contains(&spacing) => {
+                options.letter_spacing = spacing;
+            }
+            _ => {
+                return report(format!(
+                    "spacing must be a number between 0 and {}",
+                    RenderOptions::MAX_LETTER_SPACING,
+                ))
+            }
+        },
+        "width" => match value {
+            "auto" => options.width = None,
+            // Every row is at least a pixel tall, so no wider draft fits
+            _ => match value.parse::<usize>() {
+                Ok(width) if width <= MAX_DRAFT_SIZE => options.width = Some(width),
+                _ => {
+                    return report(format!(
+                        "width must be a number of pixels up to {} or 'auto'",
+                        MAX_DRAFT_SIZE,
+                    ))
+                }
+            },
+        },
+        "align" => match value.parse::<Align>() {
+            Ok(align) => options.align = align,
+            Err(()) => return report("align must be 'left', 'centre', or 'right'"),
+        },
+        _ => return report(format!("unknown setting '{}'", key)),
+    }
+    let max_len = options.max_subject_len(MAX_DRAFT_SIZE);
+    if max_len == 0 {
+        return report("no subject would fit in a draft with those settings");
+    }
+    handler.render_options = options;
+    handler.editor.set_max_len(max_len);
+    Ok(())
+}
+
+fn show(handler: &mut ThisHandler, mut args: Args) -> Result<(), AssistantError> {
+    match args.required("what") {
+        Ok("settings") => {}
+        Ok(what) => return report(format!("don't know how to show '{}'", what)),
+        Err(err) => return report(err),
+    }
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+    let options = &handler.render_options;
+    writeln!(PutCharWrite, "size = {}", options.size)?;
+    writeln!(
+        PutCharWrite,
+        "kerning = {}",
+        if options.kerning { "on" } else { "off" },
+    )?;
+    writeln!(PutCharWrite, "spacing = {}", options.letter_spacing)?;
+    match options.width {
+        Some(width) => writeln!(PutCharWrite, "width = {}", width)?,
+        None => writeln!(PutCharWrite, "width = auto")?,
+    }
+    writeln!(PutCharWrite, "align = {}", options.align)?;
+    writeln!(PutCharWrite, "output = {}", handler.output)?;
+    writeln!(PutCharWrite, "signature = {}", handler.signature_encoding)?;
+    writeln!(
+        PutCharWrite,
+        "verify = {}",
+        if handler.verify { "on" } else { "off" },
+    )?;
+    writeln!(
+        PutCharWrite,
+        "(subjects are limited to {} characters)",
+        handler.editor.max_len(),
+    )?;
+    Ok(())
+}
+
+fn batch(handler: &mut ThisHandler, args: Args) -> Result<(), AssistantError> {
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+    handler.gathering = Some(Gathering::Batch(Batch::new(MAX_BATCH_SIZE)));
+    writeln!(
+        PutCharWrite,
+        "(enter subjects one per line, then '{}', or ^C to cancel)",
+        batch::END_MARKER,
+    )?;
+    Ok(())
+}
+
+fn history(handler: &mut ThisHandler, args: Args) -> Result<(), AssistantError> {
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+    for entry in handler.history.iter() {
+        writeln!(
+            PutCharWrite,
+            "{:>5}  {}  {}",
+            entry.number,
+            hex::encode(entry.fingerprint),
+            entry.subject,
+        )?;
+    }
+    Ok(())
+}
+
+fn gallery(handler: &mut ThisHandler, mut args: Args) -> Result<(), AssistantError> {
+    let action = match args.required("what") {
+        Ok(action) => action,
+        Err(err) => return report(err),
+    };
+    if action == "list" {
+        if let Err(err) = args.finish() {
+            return report(err);
+        }
+        for (number, piece) in handler.gallery.iter() {
+            writeln!(
+                PutCharWrite,
+                "{:>5}  #{} ({} of {})  {}x{}  {}",
+                number,
+                piece.serial,
+                piece.edition,
+                piece.edition_size,
+                piece.width,
+                piece.height,
+                piece.subject.unwrap_or("(image)"),
+            )?;
+        }
+        return Ok(());
+    }
+    if !["show", "export", "drop"].contains(&action) {
+        return report(format!(
+            "don't know how to '{}' the gallery (try list, show, export, or drop)",
+            action
+        ));
+    }
+
+    let number = match args.required("n").map(str::parse::<usize>) {
+        Ok(Ok(number)) => number,
+        Ok(Err(_)) => return report("n must be the number of a work in the gallery"),
+        Err(err) => return report(err),
+    };
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+    if action == "drop" {
+        if !handler.gallery.remove(number) {
+            return report(format!("no work {} in the gallery", number));
+        }
+        return Ok(());
+    }
+    let Some(piece) = handler.gallery.get(number) else {
+        return report(format!("no work {} in the gallery", number));
+    };
+    // Copied out, since printing a record may need to ask the artist for its
+    // key
+    let subject = piece.subject.map(String::from);
+    let work = Work::from_piece(&piece);
+    if action == "show" {
+        print_work(&work, handler.signature_encoding)?;
+    } else {
+        // As a record in the current output format, or JSON when that's for
+        // people
+        handler.print_record(subject.as_deref(), &work, handler.output)?;
+    }
+    Ok(())
+}
+
+fn pubkey(handler: &mut ThisHandler, args: Args) -> Result<(), AssistantError> {
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+
+    let pem = handler.public_key_pem()?;
+    let pem = str::from_utf8(&pem).map_err(|_| AssistantError::UnexpectedArtistResponse)?;
+    write!(PutCharWrite, "{}", pem)?;
+    Ok(())
+}
+
+// Checks here with the artist's public key, or with "artist", asks the artist
+// to check instead
+fn verify(handler: &mut ThisHandler, mut args: Args) -> Result<(), AssistantError> {
+    let ask_artist = match args.next() {
+        None => false,
+        Some("artist") => true,
+        Some(other) => return report(format!("can't verify with '{}'", other)),
+    };
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+
+    let Some(work) = handler.last_work.take() else {
+        return report("nothing to verify yet");
+    };
+    let valid = if ask_artist {
+        artist_verify(handler, &work)
+    } else {
+        handler.check_signature(&work)
+    };
+    handler.last_work = Some(work);
+
+    if valid? {
+        writeln!(PutCharWrite, "Signature is valid")?;
+    } else {
+        print_bad_signature()?;
+    }
+    Ok(())
+}
+
+fn artist_verify(handler: &mut ThisHandler, work: &Work) -> Result<bool, AssistantError> {
+    let masterpiece_start = 0;
+    let masterpiece_size = work.pixel_data.len();
+    let masterpiece_end = masterpiece_start + masterpiece_size;
+
+    let signature_start = masterpiece_end;
+    let signature_size = work.signature.len();
+
+    // Each was bounded on its own on the way in, but they go back out
+    // through the region together
+    let signature_end = artist_range(signature_start, signature_size)?.end;
+
+    handler
+        .region_out
+        .as_mut_ptr()
+        .index(masterpiece_start..masterpiece_end)
+        .copy_from_slice(&work.pixel_data);
+
+    handler
+        .region_out
+        .as_mut_ptr()
+        .index(signature_start..signature_end)
+        .copy_from_slice(&work.signature);
+
+    let msg_info = TALENT.pp_call(MessageInfo::send(
+        artist::RequestTag::Verify,
+        artist::VerifyRequest {
+            masterpiece_start,
+            masterpiece_size,
+            signature_start,
+            signature_size,
+            serial: work.serial,
+            edition: work.edition,
+            timestamp: work.timestamp,
+            chain_head: work.chain_head,
+        },
+    ));
+
+    check_artist_status(&msg_info)?;
+
+    let msg = msg_info
+        .recv::<artist::VerifyResponse>()
+        .map_err(|_| AssistantError::UnexpectedArtistResponse)?;
+
+    Ok(msg.valid != 0)
+}
+
+// Pages through the artist's audit log, from the oldest entry it still keeps,
+// checking that each entry follows on from the last, and that works still in
+// the gallery are the ones recorded
+fn audit(handler: &mut ThisHandler, args: Args) -> Result<(), AssistantError> {
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+
+    let mut chain = artist::Chain::START;
+    loop {
+        let msg_info = TALENT.pp_call(MessageInfo::send(
+            artist::RequestTag::GetAuditLog,
+            artist::GetAuditLogRequest {
+                after: chain.serial,
+            },
+        ));
+
+        check_artist_status(&msg_info)?;
+
+        let msg = msg_info
+            .recv::<artist::GetAuditLogResponse>()
+            .map_err(|_| AssistantError::UnexpectedArtistResponse)?;
+
+        let entries_size = msg
+            .count
+            .checked_mul(artist::AUDIT_ENTRY_SIZE)
+            .ok_or(AssistantError::UnexpectedArtistResponse)?;
+        let entries = handler
+            .region_in
+            .as_ptr()
+            .index(artist_range(msg.entries_start, entries_size)?)
+            .copy_to_vec();
+
+        for bytes in entries.chunks(artist::AUDIT_ENTRY_SIZE) {
+            let entry = artist::AuditEntry::from_bytes(bytes.try_into().unwrap());
+            // The artist only keeps the latest entries, so the log may start
+            // part way through
+            if chain == artist::Chain::START && entry.serial == msg.first {
+                if let Some(before) = artist::Chain::before(&entry).filter(|c| c.serial > 0) {
+                    writeln!(
+                        PutCharWrite,
+                        "works before {} are no longer logged",
+                        entry.serial,
+                    )?;
+                    chain = before;
+                }
+            }
+            writeln!(
+                PutCharWrite,
+                "{:>5}  {}",
+                entry.serial,
+                hex::encode(entry.envelope_hash),
+            )?;
+            if let Err(err) = chain.extend(&entry) {
+                return print_broken_chain(err);
+            }
+            let exhibited = handler
+                .gallery
+                .iter()
+                .find(|(_, piece)| piece.serial == entry.serial)
+                .map(|(_, piece)| Work::from_piece(&piece));
+            if let Some(work) = exhibited {
+                if Sha256::digest(work.signed_data())[..] != entry.envelope_hash {
+                    writeln!(
+                        PutCharWrite,
+                        "WARNING: work {} in the gallery is not the one logged",
+                        entry.serial,
+                    )?;
+                }
+            }
+        }
+
+        if msg.more == 0 || msg.count == 0 {
+            break;
+        }
+    }
+    writeln!(
+        PutCharWrite,
+        "{} works logged, chain head {}",
+        chain.serial,
+        hex::encode(chain.head),
+    )?;
+    Ok(())
+}
+
+fn print_broken_chain(err: artist::ChainError) -> Result<(), AssistantError> {
+    match err {
+        artist::ChainError::Gap { after, next } => writeln!(
+            PutCharWrite,
+            "WARNING: audit log skips from work {} to work {}",
+            after, next,
+        )?,
+        artist::ChainError::Broken { serial } => writeln!(
+            PutCharWrite,
+            "WARNING: audit log entry {} doesn't follow on from the one before",
+            serial,
+        )?,
+    }
+    Ok(())
+}
+
+fn stats(handler: &mut ThisHandler, args: Args) -> Result<(), AssistantError> {
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+
+    let msg_info = TALENT.pp_call(MessageInfo::send(
+        artist::RequestTag::GetStats,
+        NoMessageValue,
+    ));
+
+    check_artist_status(&msg_info)?;
+
+    let msg = msg_info
+        .recv::<artist::GetStatsResponse>()
+        .map_err(|_| AssistantError::UnexpectedArtistResponse)?;
+
+    writeln!(PutCharWrite, "completed = {}", msg.completed)?;
+    writeln!(PutCharWrite, "verified = {}", msg.verified)?;
+    writeln!(PutCharWrite, "rejected = {}", msg.rejected)?;
+    writeln!(PutCharWrite, "rate limited = {}", msg.rate_limited)?;
+    writeln!(PutCharWrite, "tokens = {} of {}", msg.tokens, msg.burst)?;
+
+    let cache = &handler.glyph_cache;
+    writeln!(
+        PutCharWrite,
+        "glyph cache: {} glyphs in {} bytes, {} hits, {} misses",
+        cache.len(),
+        cache.size(),
+        cache.hits(),
+        cache.misses(),
+    )?;
+    Ok(())
+}
+
+fn clear(_handler: &mut ThisHandler, args: Args) -> Result<(), AssistantError> {
+    if let Err(err) = args.finish() {
+        return report(err);
+    }
+    write!(PutCharWrite, "\x1b[2J\x1b[H")?;
+    Ok(())
+}
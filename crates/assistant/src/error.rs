@@ -0,0 +1,109 @@
+use core::fmt;
+use core::ops::Range;
+
+use sel4cp::message::{MessageInfo, StatusMessageLabel};
+
+use banscii_artist_interface_types::{RejectReason, RejectResponse};
+
+use super::REGION_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum AssistantError {
+    /// The serial driver answered a read with something other than a character or nothing.
+    UnexpectedDriverResponse,
+    /// The serial driver failed to write a character.
+    ConsoleWrite,
+    /// A subject rendered to a draft too large for the artist to take.
+    DraftTooLarge,
+    /// A work's signature didn't check out against the artist's public key.
+    BadSignature,
+    /// The artist has completed every edition of a draft.
+    SoldOut,
+    /// The artist can't count the editions of any more drafts.
+    TooManySubjects,
+    /// The artist couldn't record a work, and so didn't complete it.
+    Unrecorded,
+    /// The artist found its records in storage altered, and won't sign until they're restored.
+    Tampered,
+    /// The artist found its records in storage older than it last saw, and won't sign until the
+    /// latest are put back.
+    RolledBack,
+    /// The artist has had too many requests lately, and will take another after this many
+    /// milliseconds at most.
+    RateLimited { retry_after: usize },
+    /// The artist couldn't tell the time to sign a work with.
+    NoTime,
+    /// The artist declined a request.
+    ArtistRejected,
+    /// The artist answered with a label, payload, or region bounds we can't make sense of.
+    UnexpectedArtistResponse,
+}
+
+impl fmt::Display for AssistantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedDriverResponse => {
+                write!(f, "unexpected response from the serial driver")
+            }
+            Self::ConsoleWrite => write!(f, "failed to write to the console"),
+            Self::DraftTooLarge => write!(f, "subject is too large to render at this size"),
+            Self::BadSignature => write!(f, "signature is not valid for this work"),
+            Self::SoldOut => write!(f, "every edition of this subject has been sold"),
+            Self::TooManySubjects => {
+                write!(f, "the artist can't take on any more subjects")
+            }
+            Self::Unrecorded => write!(f, "the artist couldn't keep a record of the work"),
+            Self::Tampered => write!(
+                f,
+                "the artist's records have been tampered with, so it won't sign anything"
+            ),
+            Self::RolledBack => write!(
+                f,
+                "the artist's records are older than it last saw, so it won't sign anything"
+            ),
+            Self::RateLimited { retry_after } => {
+                write!(f, "the artist is busy; try again in {} ms", retry_after,)
+            }
+            Self::NoTime => write!(f, "the artist couldn't tell the time to sign the work"),
+            Self::ArtistRejected => write!(f, "the artist rejected the request"),
+            Self::UnexpectedArtistResponse => write!(f, "unexpected response from the artist"),
+        }
+    }
+}
+
+impl From<fmt::Error> for AssistantError {
+    fn from(_: fmt::Error) -> Self {
+        Self::ConsoleWrite
+    }
+}
+
+pub(super) fn check_artist_status(msg_info: &MessageInfo) -> Result<(), AssistantError> {
+    match msg_info.label().try_into() {
+        Ok(StatusMessageLabel::Ok) => Ok(()),
+        Ok(_) => {
+            let msg = msg_info.recv::<RejectResponse>().ok();
+            let reason = msg.and_then(|msg| RejectReason::try_from(msg.reason).ok());
+            Err(match reason {
+                Some(RejectReason::SoldOut) => AssistantError::SoldOut,
+                Some(RejectReason::TooManySubjects) => AssistantError::TooManySubjects,
+                Some(RejectReason::Unrecorded) => AssistantError::Unrecorded,
+                Some(RejectReason::Tampered) => AssistantError::Tampered,
+                Some(RejectReason::RolledBack) => AssistantError::RolledBack,
+                Some(RejectReason::RateLimited) => AssistantError::RateLimited {
+                    retry_after: msg.map_or(0, |msg| msg.retry_after),
+                },
+                Some(RejectReason::NoTime) => AssistantError::NoTime,
+                _ => AssistantError::ArtistRejected,
+            })
+        }
+        Err(_) => Err(AssistantError::UnexpectedArtistResponse),
+    }
+}
+
+/// Checks that a span the artist pointed us at lies within our incoming region.
+pub(super) fn artist_range(start: usize, size: usize) -> Result<Range<usize>, AssistantError> {
+    match start.checked_add(size) {
+        Some(end) if end <= REGION_SIZE => Ok(start..end),
+        _ => Err(AssistantError::UnexpectedArtistResponse),
+    }
+}
@@ -5,27 +5,105 @@
 
 extern crate alloc;
 
+use alloc::borrow::ToOwned;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt;
 use core::fmt::Write;
-use core::mem;
 use core::str;
+use core::time::Duration;
 
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::signature::Verifier;
+use rsa::RsaPublicKey;
 use sel4cp::memory_region::{memory_region_symbol, ExternallySharedRef, ReadOnly, ReadWrite};
-use sel4cp::message::{MessageInfo, NoMessageLabel, NoMessageValue, StatusMessageLabel};
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
 use sel4cp::{protection_domain, Channel, Handler};
+use sha2::{Digest, Sha256};
 
 use banscii_artist_interface_types as artist;
-use banscii_assistant_core::Draft;
+use banscii_assistant_core::base64;
+use banscii_assistant_core::batch::{self, Batch, Completed, Outcome, Record, Summary};
+use banscii_assistant_core::command::{self, Input, Invocation};
+use banscii_assistant_core::gallery::{Gallery, Piece};
+use banscii_assistant_core::history::{Fingerprint, History, FINGERPRINT_SIZE};
+use banscii_assistant_core::image::{self, Paste};
+use banscii_assistant_core::line_editor::{self, Event, LineEditor};
+use banscii_assistant_core::output::{self, OutputFormat, WorkRecord};
+use banscii_assistant_core::signature::{self, SignatureEncoding};
+use banscii_assistant_core::time::{Millis, UnixTime};
+use banscii_assistant_core::{Draft, GlyphCache, RenderOptions, PLACEHOLDER};
 use banscii_pl011_driver_interface_types as driver;
+use banscii_storage_client::Storage;
+use banscii_timer_client::{Now, Timer};
+
+mod commands;
+mod error;
+
+use error::{artist_range, check_artist_status, AssistantError};
+
+const BAD_SIGNATURE_WARNING: &str = "WARNING: signature is NOT valid for this work";
 
 const PL011_DRIVER: Channel = Channel::new(0);
 const TALENT: Channel = Channel::new(1);
+const STORAGE: Channel = Channel::new(2);
 
-const REGION_SIZE: usize = 0x4_000;
+const TIMER_CHANNEL: Channel = Channel::new(3);
+
+const TIMER: Timer = Timer::new(TIMER_CHANNEL);
 
-const MAX_SUBJECT_LEN: usize = 16;
+const REGION_SIZE: usize = 0x4_000;
 
+// The draft must fit in the outgoing region, and the masterpiece along with
+// its signature in the incoming one.
+const MAX_DRAFT_SIZE: usize = REGION_SIZE - artist::MAX_SIGNATURE_SIZE;
+
+// Bytes of heap set aside for past subjects
+const HISTORY_SIZE: usize = 0x1_000;
+
+// Followed by an entry's number as a big-endian `u64`, this is where it's
+// kept in storage
+const HISTORY_KEY_PREFIX: &[u8] = b"history/";
+
+// The size of the gallery's own region, as in banscii.system
+const GALLERY_SIZE: usize = 0x10_000;
+
+// Bytes of heap set aside for rasterised glyphs
+const GLYPH_CACHE_SIZE: usize = 0x2_000;
+
+// Bytes of heap set aside for the subjects of a batch
+const MAX_BATCH_SIZE: usize = 0x1_000;
+
+// Base64 characters accepted between the markers of a pasted image
+const MAX_PASTE_SIZE: usize = 0x1_000;
+
+// Pixels in a pasted image, before it's scaled to the width of a draft
+const MAX_IMAGE_PIXELS: usize = 0x1_000;
+
+// Characters per line of a paste, with room to spare over the 76 that
+// `base64` wraps at by default
+const PASTE_LINE_LEN: usize = 128;
+
+// The width of a draft made from an image, unless one is set
+const DEFAULT_IMAGE_WIDTH: usize = 80;
+
+// Decoding the largest PNG that fits in a paste is what takes the most heap.
+// At its peak, while the image data is inflated:
+//
+//   0x1_000   history (HISTORY_SIZE)
+//   0x2_000   glyph cache (GLYPH_CACHE_SIZE)
+//   0x4_000   the last work (REGION_SIZE at most)
+//     0xc00   the paste decoded from base64 (3/4 of MAX_PASTE_SIZE)
+//     0xc00   the compressed image data, gathered from its chunks
+//   0x5_000   the inflated scanlines (4 bytes per pixel and a filter byte per
+//             row, of MAX_IMAGE_PIXELS)
+//   0x1_000   the image's own pixels (MAX_IMAGE_PIXELS)
+//
+// which is 0xe_800, and the rest is slack for the line editor, the artist's
+// key, and fragmentation. Scaling the image takes no more than the draft it
+// makes, and a batch is gathered in place of a paste rather than alongside
+// one, and needs far less.
 #[protection_domain(heap_size = 0x10000)]
 fn init() -> impl Handler {
     let region_in = unsafe {
@@ -40,19 +118,75 @@ fn init() -> impl Handler {
         )
     };
 
-    prompt();
+    let storage_region = unsafe {
+        ExternallySharedRef::<'static, [u8]>::new(memory_region_symbol!(
+            storage_region_start: *mut [u8],
+            n = banscii_storage_interface_types::REGION_SIZE
+        ))
+    };
+    let mut storage = Storage::new(STORAGE, storage_region);
+    let history = restore_history(&mut storage);
+
+    // The gallery's region is mapped into this protection domain alone, so
+    // it's safe to treat as an ordinary slice
+    let gallery_region = memory_region_symbol!(gallery_start: *mut [u8], n = GALLERY_SIZE);
+    let gallery = Gallery::new(unsafe { &mut *gallery_region.as_ptr() });
+
+    let render_options = RenderOptions::default();
+    let editor = LineEditor::new(render_options.max_subject_len(MAX_DRAFT_SIZE));
+
+    let _ = prompt();
 
     ThisHandler {
         region_in,
         region_out,
-        buffer: Vec::new(),
+        storage,
+        editor,
+        history,
+        gallery,
+        glyph_cache: GlyphCache::new(GLYPH_CACHE_SIZE),
+        render_options,
+        output: OutputFormat::default(),
+        signature_encoding: SignatureEncoding::default(),
+        verify: true,
+        artist_key: None,
+        gathering: None,
+        running: None,
+        last_work: None,
     }
 }
 
 struct ThisHandler {
     region_in: ExternallySharedRef<'static, [u8], ReadOnly>,
     region_out: ExternallySharedRef<'static, [u8], ReadWrite>,
-    buffer: Vec<u8>,
+    storage: Storage,
+    editor: LineEditor,
+    history: History,
+    gallery: Gallery<&'static mut [u8]>,
+    glyph_cache: GlyphCache,
+    render_options: RenderOptions,
+    output: OutputFormat,
+    signature_encoding: SignatureEncoding,
+    // Whether to check each work's signature as it arrives
+    verify: bool,
+    artist_key: Option<ArtistKey>,
+    gathering: Option<Gathering>,
+    // A batch waiting for the artist to take more requests
+    running: Option<Running>,
+    last_work: Option<Work>,
+}
+
+// Lines being gathered up for something other than the prompt
+enum Gathering {
+    Image(Paste),
+    Batch(Batch),
+}
+
+// How far a batch has got
+struct Running {
+    subjects: Vec<String>,
+    next: usize,
+    summary: Summary,
 }
 
 impl Handler for ThisHandler {
@@ -61,25 +195,13 @@ impl Handler for ThisHandler {
     fn notified(&mut self, channel: Channel) -> Result<(), Self::Error> {
         match channel {
             PL011_DRIVER => {
-                while let Some(b) = get_char() {
-                    if let b'\n' | b'\r' = b {
-                        newline();
-                        if !self.buffer.is_empty() {
-                            self.try_create();
-                        }
-                        prompt();
-                    } else {
-                        let c = char::from(b);
-                        if c.is_ascii() && !c.is_ascii_control() {
-                            if self.buffer.len() == MAX_SUBJECT_LEN {
-                                writeln!(PutCharWrite, "\n(char limit reached)").unwrap();
-                                self.try_create();
-                                prompt();
-                            }
-                            put_char(b);
-                            self.buffer.push(b);
-                        }
-                    }
+                if let Err(err) = self.poll_input() {
+                    self.recover(err);
+                }
+            }
+            TIMER_CHANNEL => {
+                if let Err(err) = self.timed_out() {
+                    self.recover(err);
                 }
             }
             _ => {
@@ -91,23 +213,473 @@ impl Handler for ThisHandler {
 }
 
 impl ThisHandler {
-    fn try_create(&mut self) {
-        let mut buffer = Vec::new();
-        mem::swap(&mut buffer, &mut self.buffer);
-        match str::from_utf8(&buffer) {
-            Ok(subject) => {
-                self.create(&subject);
+    // Input is left with the driver while a batch is running, and picked up
+    // once it's done
+    fn poll_input(&mut self) -> Result<(), AssistantError> {
+        while self.running.is_none() {
+            let Some(b) = get_char()? else {
+                break;
+            };
+            match self.editor.feed(b, &mut PutCharWrite)? {
+                Some(Event::Submit(line)) => {
+                    newline()?;
+                    self.history.end_browsing();
+                    if self.gathering.is_some() {
+                        self.gather(&line)?;
+                    } else if !line.is_empty() {
+                        self.submit(&line)?;
+                    }
+                    self.prompt()?;
+                }
+                Some(Event::Interrupt) => {
+                    self.history.end_browsing();
+                    writeln!(PutCharWrite, "^C")?;
+                    match self.end_gathering() {
+                        Some(Gathering::Image(_)) => writeln!(PutCharWrite, "(paste cancelled)")?,
+                        Some(Gathering::Batch(_)) => writeln!(PutCharWrite, "(batch cancelled)")?,
+                        None => {}
+                    }
+                    self.prompt()?;
+                }
+                Some(Event::Full) => {
+                    if let Some(Gathering::Image(_)) = self.gathering {
+                        writeln!(
+                            PutCharWrite,
+                            "\n(lines of a paste are limited to {} characters)",
+                            self.editor.max_len(),
+                        )?;
+                    } else {
+                        writeln!(
+                            PutCharWrite,
+                            "\n(subjects are limited to {} characters at this size)",
+                            self.editor.max_len(),
+                        )?;
+                    }
+                    self.prompt()?;
+                    self.editor.redraw(&mut PutCharWrite)?;
+                }
+                Some(Event::HistoryPrev) => match self.history.older(self.editor.line()) {
+                    Some(line) => self.editor.replace(line, &mut PutCharWrite)?,
+                    None => line_editor::bell(&mut PutCharWrite)?,
+                },
+                Some(Event::HistoryNext) => match self.history.newer() {
+                    Some(line) => self.editor.replace(line, &mut PutCharWrite)?,
+                    None => line_editor::bell(&mut PutCharWrite)?,
+                },
+                None => {}
             }
-            Err(_) => {
-                writeln!(PutCharWrite, "error: input is not valid utf-8").unwrap();
+        }
+        Ok(())
+    }
+
+    // Drops whatever was in progress and starts over at a fresh prompt. This
+    // is best-effort, since the console itself may be what failed.
+    fn recover(&mut self, err: AssistantError) {
+        if self.editor.is_empty() {
+            self.editor.clear();
+        } else {
+            // Whatever is left of a line that failed part way through isn't a
+            // command of its own, so drain what has already arrived of it
+            self.editor.discard_rest_of_line();
+            while self.editor.is_discarding() {
+                match get_char() {
+                    Ok(Some(b)) => {
+                        let _ = self.editor.feed(b, &mut PutCharWrite);
+                    }
+                    _ => break,
+                }
+            }
+        }
+        self.history.end_browsing();
+        self.end_gathering();
+        self.running = None;
+        let _ = writeln!(PutCharWrite, "\nerror: {}", err);
+        let _ = prompt();
+    }
+
+    // Gathered lines are entered without a prompt, so that a paste doesn't
+    // end up interleaved with them on the terminal, and there's none until a
+    // batch is done
+    fn prompt(&self) -> fmt::Result {
+        if self.gathering.is_some() || self.running.is_some() {
+            return Ok(());
+        }
+        prompt()
+    }
+
+    fn submit(&mut self, line: &str) -> Result<(), AssistantError> {
+        if line.trim() == image::BEGIN_MARKER {
+            self.gathering = Some(Gathering::Image(Paste::new(MAX_PASTE_SIZE)));
+            self.editor.set_max_len(PASTE_LINE_LEN);
+            writeln!(
+                PutCharWrite,
+                "(paste a base64-encoded PGM or PNG, then '{}', or ^C to cancel)",
+                image::END_MARKER,
+            )?;
+            return Ok(());
+        }
+        match command::parse(line) {
+            Input::Command(Invocation { name, args }) => {
+                match command::lookup(commands::COMMANDS, name) {
+                    Some(command) => (command.run)(self, args)?,
+                    None => {
+                        writeln!(
+                            PutCharWrite,
+                            "error: unknown command '{}' (try :help)",
+                            name
+                        )?;
+                    }
+                }
+            }
+            Input::Subject(subject) => {
+                let subject = match self.history.expand(subject) {
+                    Ok(Some(entry)) => {
+                        writeln!(PutCharWrite, "{}", entry.subject)?;
+                        entry.subject.clone()
+                    }
+                    Ok(None) => subject.to_owned(),
+                    Err(err) => {
+                        writeln!(PutCharWrite, "error: {}", err)?;
+                        return Ok(());
+                    }
+                };
+                self.create(&subject)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn gather(&mut self, line: &str) -> Result<(), AssistantError> {
+        match &mut self.gathering {
+            Some(Gathering::Image(paste)) => {
+                if line.trim() == image::END_MARKER {
+                    if let Some(Gathering::Image(paste)) = self.end_gathering() {
+                        self.finish_paste(paste)?;
+                    }
+                } else {
+                    paste.push_line(line);
+                }
+            }
+            Some(Gathering::Batch(batch)) => {
+                if line.trim() == batch::END_MARKER {
+                    if let Some(Gathering::Batch(batch)) = self.end_gathering() {
+                        match batch.into_subjects() {
+                            Ok(subjects) => self.run_batch(subjects)?,
+                            Err(err) => writeln!(PutCharWrite, "error: {}", err)?,
+                        }
+                    }
+                } else if !line.is_empty() {
+                    batch.push(line);
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn end_gathering(&mut self) -> Option<Gathering> {
+        self.editor
+            .set_max_len(self.render_options.max_subject_len(MAX_DRAFT_SIZE));
+        self.gathering.take()
+    }
+
+    fn finish_paste(&mut self, paste: Paste) -> Result<(), AssistantError> {
+        let image = match paste.finish(MAX_IMAGE_PIXELS) {
+            Ok(image) => image,
+            Err(err) => {
+                writeln!(PutCharWrite, "error: {}", err)?;
+                return Ok(());
             }
         };
-        self.buffer.clear();
+
+        let width = self.render_options.width.unwrap_or(DEFAULT_IMAGE_WIDTH);
+        let size = Draft::image_height(&image, width).and_then(|height| width.checked_mul(height));
+        if size.map_or(true, |size| size > MAX_DRAFT_SIZE) {
+            writeln!(
+                PutCharWrite,
+                "error: image is too large to draft at a width of {}",
+                width,
+            )?;
+            return Ok(());
+        }
+        let started = TIMER.now().ok();
+        let draft = Draft::from_image(&image, width);
+        let render_time = elapsed(started);
+        self.commission(None, &draft, render_time)?;
+        Ok(())
+    }
+
+    // Reports on each subject as a line of JSON rather than printing the
+    // work, and carries on past any that fail
+    fn run_batch(&mut self, subjects: Vec<String>) -> Result<(), AssistantError> {
+        self.running = Some(Running {
+            subjects,
+            next: 0,
+            summary: Summary {
+                completed: 0,
+                failed: 0,
+            },
+        });
+        self.resume_batch()
     }
 
-    fn create(&mut self, subject: &str) {
-        let draft = Draft::new(subject);
+    // Runs the rest of the batch, unless the artist turns a subject away
+    // until later, in which case the batch waits on a timeout and picks up
+    // from that subject
+    fn resume_batch(&mut self) -> Result<(), AssistantError> {
+        let Some(mut running) = self.running.take() else {
+            return Ok(());
+        };
+        while let Some(subject) = running.subjects.get(running.next) {
+            let index = running.next;
+            let draft = Draft::with_cache(subject, &self.render_options, &mut self.glyph_cache);
+            let work = if draft.pixel_data.len() > MAX_DRAFT_SIZE {
+                Err(AssistantError::DraftTooLarge)
+            } else {
+                self.complete(&draft).and_then(|work| {
+                    if self.verify && !self.check_signature(&work)? {
+                        return Err(AssistantError::BadSignature);
+                    }
+                    Ok(work)
+                })
+            };
+            if let Err(AssistantError::RateLimited { retry_after }) = work {
+                let after = Duration::from_millis(retry_after as u64);
+                if TIMER.set_timeout(after).is_ok() {
+                    self.running = Some(running);
+                    return Ok(());
+                }
+            }
+            running.next += 1;
+            match work {
+                Ok(work) => {
+                    let record = Record {
+                        index,
+                        subject,
+                        outcome: Outcome::Completed(Completed {
+                            width: work.width,
+                            height: work.height,
+                            serial: work.serial,
+                            edition: work.edition,
+                            timestamp: work.timestamp,
+                            chain_head: &work.chain_head,
+                            missing_glyphs: &draft.missing_glyphs,
+                            pixel_data: &work.pixel_data,
+                            signature: &work.signature,
+                        }),
+                    };
+                    writeln!(PutCharWrite, "{}", record)?;
+                    running.summary.completed += 1;
+                    self.remember(subject, fingerprint(&work.signature));
+                    self.exhibit(Some(subject), &work);
+                    self.last_work = Some(work);
+                }
+                Err(err) => {
+                    let record = Record {
+                        index,
+                        subject,
+                        outcome: Outcome::Failed(&err),
+                    };
+                    writeln!(PutCharWrite, "{}", record)?;
+                    running.summary.failed += 1;
+                }
+            }
+        }
+        writeln!(PutCharWrite, "{}", running.summary)?;
+        Ok(())
+    }
 
+    // Only a batch waits on the timer
+    fn timed_out(&mut self) -> Result<(), AssistantError> {
+        if self.running.is_none() {
+            return Ok(());
+        }
+        self.resume_batch()?;
+        if self.running.is_none() {
+            self.prompt()?;
+            self.poll_input()?;
+        }
+        Ok(())
+    }
+
+    fn create(&mut self, subject: &str) -> Result<(), AssistantError> {
+        let started = TIMER.now().ok();
+        let draft = Draft::with_cache(subject, &self.render_options, &mut self.glyph_cache);
+        let render_time = elapsed(started);
+
+        if draft.pixel_data.len() > MAX_DRAFT_SIZE {
+            writeln!(PutCharWrite, "error: {}", AssistantError::DraftTooLarge)?;
+            return Ok(());
+        }
+
+        if !draft.missing_glyphs.is_empty() {
+            write!(PutCharWrite, "warning: no glyph for")?;
+            for c in &draft.missing_glyphs {
+                write!(PutCharWrite, " {:?}", c)?;
+            }
+            writeln!(PutCharWrite, ", drawn as {:?}", PLACEHOLDER)?;
+        }
+
+        let fingerprint = self.commission(Some(subject), &draft, render_time)?;
+        self.remember(subject, fingerprint);
+        Ok(())
+    }
+
+    // History is a convenience, so it's kept in storage on a best-effort
+    // basis, and a subject that can't be kept is only forgotten on restart
+    fn remember(&mut self, subject: &str, fingerprint: Fingerprint) {
+        let oldest = self.history.first().map(|entry| entry.number);
+        let number = self.history.push(subject, fingerprint);
+        let now_oldest = self
+            .history
+            .first()
+            .map_or(number + 1, |entry| entry.number);
+        for evicted in oldest.unwrap_or(now_oldest)..now_oldest {
+            let _ = self.storage.delete(&history_key(evicted));
+        }
+        if self.history.get(number).is_some() {
+            let value = [&fingerprint[..], subject.as_bytes()].concat();
+            let _ = self.storage.put(&history_key(number), &value);
+        }
+    }
+
+    // Has the artist complete and sign a draft, and prints the result, along
+    // with how long the draft took to render if that's known
+    fn commission(
+        &mut self,
+        subject: Option<&str>,
+        draft: &Draft,
+        render_time: Option<Duration>,
+    ) -> Result<Fingerprint, AssistantError> {
+        let started = TIMER.now().ok();
+        let work = self.complete(draft)?;
+        let sign_time = elapsed(started);
+        // Only works that check out are hung in the gallery
+        let valid = !self.verify || self.check_signature(&work)?;
+        if valid {
+            self.exhibit(subject, &work);
+        }
+        match self.output {
+            OutputFormat::Human => {
+                print_work(&work, self.signature_encoding)?;
+                if let (Some(render_time), Some(sign_time)) = (render_time, sign_time) {
+                    writeln!(
+                        PutCharWrite,
+                        "Rendered in {}, signed in {}",
+                        Millis(render_time),
+                        Millis(sign_time),
+                    )?;
+                }
+            }
+            format => self.print_record(subject, &work, format)?,
+        }
+        if !valid {
+            print_bad_signature()?;
+        }
+        let fingerprint = fingerprint(&work.signature);
+        self.last_work = Some(work);
+        Ok(fingerprint)
+    }
+
+    // Prints a work as a framed record in `format`, which is JSON or CBOR
+    fn print_record(
+        &mut self,
+        subject: Option<&str>,
+        work: &Work,
+        format: OutputFormat,
+    ) -> Result<(), AssistantError> {
+        let key_fingerprint = self.artist_key()?.fingerprint;
+        let record = WorkRecord {
+            subject,
+            width: work.width,
+            height: work.height,
+            algorithm: artist::SIGNATURE_ALGORITHM,
+            key_fingerprint: &key_fingerprint,
+            serial: work.serial,
+            edition: work.edition,
+            edition_size: work.edition_size,
+            timestamp: work.timestamp,
+            chain_head: &work.chain_head,
+            pixel_data: &work.pixel_data,
+            signature: &work.signature,
+        };
+        let payload = match format {
+            OutputFormat::Cbor => record.cbor(),
+            _ => record.json().into_bytes(),
+        };
+        put_bytes(&output::frame(&payload))?;
+        newline()?;
+        Ok(())
+    }
+
+    // Every work fits in the gallery, since it's larger than the region the
+    // work came through
+    fn exhibit(&mut self, subject: Option<&str>, work: &Work) {
+        let _ = self.gallery.push(&Piece {
+            subject,
+            width: work.width,
+            height: work.height,
+            serial: work.serial,
+            edition: work.edition,
+            edition_size: work.edition_size,
+            timestamp: work.timestamp,
+            chain_head: work.chain_head,
+            pixel_data: &work.pixel_data,
+            signature: &work.signature,
+        });
+    }
+
+    // Asks the artist the first time, since its key never changes
+    fn artist_key(&mut self) -> Result<&ArtistKey, AssistantError> {
+        if self.artist_key.is_none() {
+            let pem = self.public_key_pem()?;
+            let der = str::from_utf8(&pem)
+                .ok()
+                .and_then(base64::decode_pem)
+                .ok_or(AssistantError::UnexpectedArtistResponse)?;
+            let public_key = RsaPublicKey::from_public_key_der(&der)
+                .map_err(|_| AssistantError::UnexpectedArtistResponse)?;
+            self.artist_key = Some(ArtistKey {
+                fingerprint: Sha256::digest(der).into(),
+                verifying_key: VerifyingKey::new_with_prefix(public_key),
+            });
+        }
+        Ok(self.artist_key.as_ref().unwrap())
+    }
+
+    // Checks a work against the artist's public key here, rather than taking
+    // the artist's word for it, so that a work altered on its way out of the
+    // artist is caught too
+    fn check_signature(&mut self, work: &Work) -> Result<bool, AssistantError> {
+        let key = &self.artist_key()?.verifying_key;
+        let valid = match Signature::try_from(&work.signature[..]) {
+            Ok(signature) => key.verify(&work.signed_data(), &signature).is_ok(),
+            Err(_) => false,
+        };
+        Ok(valid)
+    }
+
+    fn public_key_pem(&mut self) -> Result<Vec<u8>, AssistantError> {
+        let msg_info = TALENT.pp_call(MessageInfo::send(
+            artist::RequestTag::GetPublicKey,
+            NoMessageValue,
+        ));
+
+        check_artist_status(&msg_info)?;
+
+        let msg = msg_info
+            .recv::<artist::GetPublicKeyResponse>()
+            .map_err(|_| AssistantError::UnexpectedArtistResponse)?;
+
+        let pem = self
+            .region_in
+            .as_ptr()
+            .index(artist_range(msg.pem_start, msg.pem_size)?)
+            .copy_to_vec();
+        Ok(pem)
+    }
+
+    fn complete(&mut self, draft: &Draft) -> Result<Work, AssistantError> {
         let draft_start = 0;
         let draft_size = draft.pixel_data.len();
         let draft_end = draft_start + draft_size;
@@ -118,8 +690,8 @@ impl ThisHandler {
             .copy_from_slice(&draft.pixel_data);
 
         let msg_info = TALENT.pp_call(MessageInfo::send(
-            NoMessageLabel,
-            artist::Request {
+            artist::RequestTag::Complete,
+            artist::CompleteRequest {
                 height: draft.height,
                 width: draft.width,
                 draft_start,
@@ -127,91 +699,239 @@ impl ThisHandler {
             },
         ));
 
-        assert_eq!(msg_info.label().try_into(), Ok(StatusMessageLabel::Ok));
+        check_artist_status(&msg_info)?;
 
-        let msg = msg_info.recv::<artist::Response>().unwrap();
+        let msg = msg_info
+            .recv::<artist::CompleteResponse>()
+            .map_err(|_| AssistantError::UnexpectedArtistResponse)?;
 
         let height = msg.height;
         let width = msg.width;
 
+        if height.checked_mul(width) != Some(msg.masterpiece_size) {
+            return Err(AssistantError::UnexpectedArtistResponse);
+        }
+
         let pixel_data = self
             .region_in
             .as_ptr()
-            .index(msg.masterpiece_start..msg.masterpiece_start + msg.masterpiece_size)
+            .index(artist_range(msg.masterpiece_start, msg.masterpiece_size)?)
             .copy_to_vec();
 
         let signature = self
             .region_in
             .as_ptr()
-            .index(msg.signature_start..msg.signature_start + msg.signature_size)
+            .index(artist_range(msg.signature_start, msg.signature_size)?)
             .copy_to_vec();
 
-        newline();
+        Ok(Work {
+            width,
+            height,
+            serial: msg.serial,
+            edition: msg.edition,
+            edition_size: msg.edition_size,
+            timestamp: msg.timestamp,
+            chain_head: msg.chain_head,
+            pixel_data,
+            signature,
+        })
+    }
+}
 
-        for row in 0..height {
-            for col in 0..width {
-                let i = row * width + col;
-                let b = pixel_data[i];
-                put_char(b);
-            }
-            newline();
+fn print_work(work: &Work, encoding: SignatureEncoding) -> Result<(), AssistantError> {
+    newline()?;
+
+    for row in 0..work.height {
+        for col in 0..work.width {
+            let i = row * work.width + col;
+            let b = work.pixel_data[i];
+            put_char(b)?;
         }
+        newline()?;
+    }
 
-        newline();
+    newline()?;
+
+    writeln!(PutCharWrite, "Serial: {}", work.serial)?;
+    writeln!(
+        PutCharWrite,
+        "Edition: {} of {}",
+        work.edition, work.edition_size
+    )?;
+    writeln!(
+        PutCharWrite,
+        "Signed: {} ({})",
+        UnixTime(work.timestamp),
+        work.timestamp
+    )?;
+    writeln!(PutCharWrite, "Chain: {}", hex::encode(work.chain_head))?;
+    writeln!(PutCharWrite, "Signature:")?;
+    signature::write(
+        &mut PutCharWrite,
+        encoding,
+        &work.signature,
+        work.serial,
+        work.edition,
+    )?;
+
+    newline()?;
+    Ok(())
+}
+
+struct Work {
+    width: usize,
+    height: usize,
+    serial: usize,
+    edition: usize,
+    edition_size: usize,
+    // Seconds since the Unix epoch when the artist signed it
+    timestamp: u64,
+    // The head of the artist's audit log before this work
+    chain_head: artist::ChainHash,
+    pixel_data: Vec<u8>,
+    signature: Vec<u8>,
+}
 
-        writeln!(PutCharWrite, "Signature:").unwrap();
-        for line in signature.chunks(32) {
-            writeln!(PutCharWrite, "{}", hex::encode(line)).unwrap();
+impl Work {
+    fn from_piece(piece: &Piece) -> Self {
+        Self {
+            width: piece.width,
+            height: piece.height,
+            serial: piece.serial,
+            edition: piece.edition,
+            edition_size: piece.edition_size,
+            timestamp: piece.timestamp,
+            chain_head: piece.chain_head,
+            pixel_data: piece.pixel_data.to_vec(),
+            signature: piece.signature.to_vec(),
         }
+    }
 
-        newline();
+    // What the artist signed
+    fn signed_data(&self) -> Vec<u8> {
+        [
+            &self.pixel_data[..],
+            &artist::provenance(self.serial, self.edition, self.timestamp, &self.chain_head),
+        ]
+        .concat()
     }
 }
 
-fn prompt() {
-    write!(PutCharWrite, "banscii> ").unwrap();
+struct ArtistKey {
+    // SHA-256 of the key, as DER
+    fingerprint: [u8; 32],
+    verifying_key: VerifyingKey<Sha256>,
+}
+
+// Hard to miss among the art, since it means the work can't be trusted
+fn print_bad_signature() -> Result<(), AssistantError> {
+    let line = "*".repeat(BAD_SIGNATURE_WARNING.len() + 4);
+    writeln!(PutCharWrite, "{}", line)?;
+    writeln!(PutCharWrite, "* {} *", BAD_SIGNATURE_WARNING)?;
+    writeln!(PutCharWrite, "{}", line)?;
+    Ok(())
 }
 
-fn newline() {
-    writeln!(PutCharWrite, "").unwrap();
+// How long since `since`, if the timer could tell us both then and now
+fn elapsed(since: Option<Now>) -> Option<Duration> {
+    let since = since?;
+    let now = TIMER.now().ok()?;
+    now.since_start.checked_sub(since.since_start)
 }
 
-fn get_char() -> Option<u8> {
+// Picks up the subjects kept from before a restart, and lets go of any which
+// no longer fit
+fn restore_history(storage: &mut Storage) -> History {
+    let mut history = History::new(HISTORY_SIZE);
+    let Ok(keys) = storage.keys() else {
+        return history;
+    };
+    let mut restored = Vec::new();
+    for key in keys {
+        let Some(number) = key
+            .strip_prefix(HISTORY_KEY_PREFIX)
+            .and_then(|number| number.try_into().ok())
+            .map(|number| u64::from_be_bytes(number) as usize)
+        else {
+            continue;
+        };
+        let Ok(Some(value)) = storage.get(&key) else {
+            continue;
+        };
+        if value.len() < FINGERPRINT_SIZE {
+            continue;
+        }
+        let (fingerprint, subject) = value.split_at(FINGERPRINT_SIZE);
+        if let Ok(subject) = str::from_utf8(subject) {
+            history.restore(number, subject, fingerprint.try_into().unwrap());
+            restored.push(number);
+        }
+    }
+    for number in restored {
+        if history.get(number).is_none() {
+            let _ = storage.delete(&history_key(number));
+        }
+    }
+    history
+}
+
+fn history_key(number: usize) -> Vec<u8> {
+    [HISTORY_KEY_PREFIX, &(number as u64).to_be_bytes()].concat()
+}
+
+fn fingerprint(signature: &[u8]) -> Fingerprint {
+    let digest = Sha256::digest(signature);
+    let mut fingerprint = Fingerprint::default();
+    fingerprint.copy_from_slice(&digest[..FINGERPRINT_SIZE]);
+    fingerprint
+}
+
+fn prompt() -> fmt::Result {
+    write!(PutCharWrite, "banscii> ")
+}
+
+fn newline() -> fmt::Result {
+    writeln!(PutCharWrite)
+}
+
+fn get_char() -> Result<Option<u8>, AssistantError> {
     let msg_info = PL011_DRIVER.pp_call(MessageInfo::send(
         driver::RequestTag::GetChar,
         NoMessageValue,
     ));
     match msg_info.label().try_into().ok() {
         Some(driver::GetCharResponseTag::Some) => match msg_info.recv() {
-            Ok(driver::GetCharSomeResponse { val }) => Some(val),
-            Err(_) => {
-                panic!()
-            }
+            Ok(driver::GetCharSomeResponse { val }) => Ok(Some(val)),
+            Err(_) => Err(AssistantError::UnexpectedDriverResponse),
         },
-        Some(driver::GetCharResponseTag::None) => None,
-        _ => {
-            panic!()
-        }
+        Some(driver::GetCharResponseTag::None) => Ok(None),
+        _ => Err(AssistantError::UnexpectedDriverResponse),
     }
 }
 
-fn put_char(val: u8) {
+fn put_char(val: u8) -> Result<(), AssistantError> {
     let msg_info = PL011_DRIVER.pp_call(MessageInfo::send(
         driver::RequestTag::PutChar,
         driver::PutCharRequest { val },
     ));
-    assert_eq!(msg_info.label().try_into(), Ok(StatusMessageLabel::Ok));
+    match msg_info.label().try_into() {
+        Ok(StatusMessageLabel::Ok) => Ok(()),
+        _ => Err(AssistantError::ConsoleWrite),
+    }
 }
 
-fn put_str(s: &str) {
-    s.as_bytes().iter().copied().for_each(put_char)
+fn put_str(s: &str) -> Result<(), AssistantError> {
+    put_bytes(s.as_bytes())
+}
+
+fn put_bytes(bytes: &[u8]) -> Result<(), AssistantError> {
+    bytes.iter().copied().try_for_each(put_char)
 }
 
 struct PutCharWrite;
 
 impl fmt::Write for PutCharWrite {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        put_str(s);
-        Ok(())
+        put_str(s).map_err(|_| fmt::Error)
     }
 }
@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+/// QEMU's serial port, attached to its stdio. Everything received is copied
+/// to a transcript as it arrives, so that the transcript is useful even when a
+/// run is cut short.
+pub(crate) struct Console {
+    qemu: Child,
+    stdin: ChildStdin,
+    output: Receiver<io::Result<Vec<u8>>>,
+    pending: Vec<u8>,
+    transcript: File,
+}
+
+impl Console {
+    pub(crate) fn spawn(mut qemu: Command, transcript: File) -> Result<Self, Error> {
+        let mut qemu = qemu
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|err| Error::Io("failed to start QEMU", err))?;
+
+        let stdin = qemu.stdin.take().unwrap();
+        let mut stdout = qemu.stdout.take().unwrap();
+
+        let (tx, output) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            loop {
+                let chunk = match stdout.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => Ok(buf[..n].to_vec()),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => Err(err),
+                };
+                if tx.send(chunk).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            qemu,
+            stdin,
+            output,
+            pending: Vec::new(),
+            transcript,
+        })
+    }
+
+    pub(crate) fn send(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.stdin
+            .write_all(bytes)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|err| Error::Io("failed to write to QEMU", err))
+    }
+
+    /// Waits for `pattern`, and returns everything received up to and
+    /// including it.
+    pub(crate) fn expect(&mut self, pattern: &str, timeout: Duration) -> Result<String, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(i) = find(&self.pending, pattern.as_bytes()) {
+                let rest = self.pending.split_off(i + pattern.len());
+                let received = std::mem::replace(&mut self.pending, rest);
+                return Ok(String::from_utf8_lossy(&received).into_owned());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.output.recv_timeout(remaining) {
+                Ok(Ok(chunk)) => {
+                    self.transcript
+                        .write_all(&chunk)
+                        .map_err(|err| Error::Io("failed to write transcript", err))?;
+                    self.pending.extend(chunk);
+                }
+                Ok(Err(err)) => return Err(Error::Io("failed to read from QEMU", err)),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(Error::Timeout {
+                        pattern: pattern.to_owned(),
+                        timeout,
+                    })
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::QemuExited(self.qemu.wait().ok()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        let _ = self.qemu.kill();
+        let _ = self.qemu.wait();
+        let _ = self.transcript.flush();
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
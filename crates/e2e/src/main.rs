@@ -0,0 +1,387 @@
+//! Boots the Banscii system in QEMU, enters a list of subjects at the serial
+//! console, and checks every masterpiece's signature against the artist's
+//! public key. Then it checks the works' timestamps, boots the system again
+//! to check that the artist carries on from its store on disk, and runs a
+//! batch long enough to need the timer.
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, ExitCode, ExitStatus};
+use std::time::{Duration, SystemTime};
+
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::sha2::Sha256;
+use rsa::signature::Verifier;
+use rsa::RsaPublicKey;
+
+use banscii_work::{check_chain, Work};
+
+mod console;
+
+use console::Console;
+
+const USAGE: &str = "\
+usage: banscii-e2e [options] [subject]...
+
+options:
+    --loader <path>         system image to boot [default: build/loader.img]
+    --disk <path>           disk image for storage, best started empty [default: build/e2e-disk.img]
+    --qemu <program>        [default: qemu-system-aarch64]
+    --boot-timeout <secs>   time allowed to reach the first prompt [default: 60]
+    --timeout <secs>        time allowed for each subject [default: 30]
+    --transcript <path>     where to save console output [default: build/e2e-transcript.txt]
+    --public-key <path>     PEM file to verify against, instead of asking the artist";
+
+const DEFAULT_SUBJECTS: &[&str] = &["Hello, World!", "Banscii", "seL4", "Ünïcödé"];
+
+const PROMPT: &str = "banscii> ";
+
+// More than the artist takes in its default burst, so the batch only finishes
+// if the timer refills the artist's rate limiter and wakes the assistant to
+// try again
+const BATCH_SIZE: usize = 40;
+
+// How far the artist's clock may be from the host's, which QEMU's RTC starts
+// from
+const CLOCK_SLACK: Duration = Duration::from_secs(24 * 60 * 60);
+
+const PUBLIC_KEY_BEGIN: &str = "-----BEGIN PUBLIC KEY-----";
+const PUBLIC_KEY_END: &str = "-----END PUBLIC KEY-----";
+
+struct Options {
+    loader: PathBuf,
+    disk: PathBuf,
+    qemu: String,
+    boot_timeout: Duration,
+    timeout: Duration,
+    transcript: PathBuf,
+    public_key: Option<PathBuf>,
+    subjects: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            loader: "build/loader.img".into(),
+            disk: "build/e2e-disk.img".into(),
+            qemu: "qemu-system-aarch64".to_owned(),
+            boot_timeout: Duration::from_secs(60),
+            timeout: Duration::from_secs(30),
+            transcript: "build/e2e-transcript.txt".into(),
+            public_key: None,
+            subjects: vec![],
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--loader" => options.loader = value()?.into(),
+                "--disk" => options.disk = value()?.into(),
+                "--qemu" => options.qemu = value()?,
+                "--boot-timeout" => options.boot_timeout = parse_secs(&value()?)?,
+                "--timeout" => options.timeout = parse_secs(&value()?)?,
+                "--transcript" => options.transcript = value()?.into(),
+                "--public-key" => options.public_key = Some(value()?.into()),
+                "--" => options.subjects.extend(args.by_ref()),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ => options.subjects.push(arg),
+            }
+        }
+        if options.subjects.is_empty() {
+            options.subjects = DEFAULT_SUBJECTS.iter().map(|s| (*s).to_owned()).collect();
+        }
+        Ok(options)
+    }
+
+    fn qemu_command(&self) -> Command {
+        let mut cmd = Command::new(&self.qemu);
+        cmd.args(["-machine", "virt"])
+            .args(["-cpu", "cortex-a53", "-m", "size=1G"])
+            .arg("-device")
+            .arg(format!(
+                "loader,file={},addr=0x70000000,cpu-num=0",
+                self.loader.display()
+            ))
+            .args(["-global", "virtio-mmio.force-legacy=false"])
+            .arg("-drive")
+            .arg(format!(
+                "file={},if=none,format=raw,id=disk",
+                self.disk.display()
+            ))
+            .args(["-device", "virtio-blk-device,drive=disk"])
+            .args(["-serial", "stdio", "-monitor", "none", "-display", "none"]);
+        cmd
+    }
+}
+
+fn parse_secs(s: &str) -> Result<Duration, String> {
+    s.parse()
+        .map(Duration::from_secs)
+        .map_err(|_| format!("invalid number of seconds '{}'", s))
+}
+
+#[derive(Debug)]
+enum Error {
+    Io(&'static str, io::Error),
+    Timeout { pattern: String, timeout: Duration },
+    QemuExited(Option<ExitStatus>),
+    PublicKey(String),
+    Failed { failures: usize, total: usize },
+    BrokenChain(String),
+    Timestamps(String),
+    Reboot(String),
+    Batch(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(context, err) => write!(f, "{}: {}", context, err),
+            Self::Timeout { pattern, timeout } => {
+                write!(f, "timed out after {:?} waiting for {:?}", timeout, pattern)
+            }
+            Self::QemuExited(Some(status)) => write!(f, "QEMU exited unexpectedly ({})", status),
+            Self::QemuExited(None) => write!(f, "QEMU exited unexpectedly"),
+            Self::PublicKey(reason) => write!(f, "couldn't get the public key: {}", reason),
+            Self::Failed { failures, total } => {
+                write!(f, "{} of {} subjects failed", failures, total)
+            }
+            Self::BrokenChain(reason) => {
+                write!(f, "works don't follow on in the audit log: {}", reason)
+            }
+            Self::Timestamps(reason) => write!(f, "works aren't timestamped properly: {}", reason),
+            Self::Reboot(reason) => {
+                write!(f, "the artist didn't carry on after a reboot: {}", reason)
+            }
+            Self::Batch(reason) => write!(f, "batch didn't complete: {}", reason),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    if env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let result = run(&options);
+    eprintln!("transcript: {}", options.transcript.display());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<(), Error> {
+    let transcript = File::create(&options.transcript)
+        .map_err(|err| Error::Io("failed to create transcript", err))?;
+    let boot = || {
+        let transcript = transcript
+            .try_clone()
+            .map_err(|err| Error::Io("failed to reopen transcript", err))?;
+        Console::spawn(options.qemu_command(), transcript)
+    };
+    let mut console = boot()?;
+
+    console.expect(PROMPT, options.boot_timeout)?;
+
+    let public_key = match &options.public_key {
+        Some(path) => {
+            fs::read_to_string(path).map_err(|err| Error::Io("failed to read public key", err))?
+        }
+        None => {
+            console.send(b":pubkey\r")?;
+            let output = console.expect(PROMPT, options.timeout)?;
+            find_public_key(&output)
+                .ok_or_else(|| Error::PublicKey("no key in :pubkey output".to_owned()))?
+                .to_owned()
+        }
+    };
+    let public_key = RsaPublicKey::from_public_key_pem(&public_key)
+        .map_err(|err| Error::PublicKey(err.to_string()))?;
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key);
+
+    let mut failures = 0;
+    let mut works = vec![];
+    for subject in &options.subjects {
+        console.send(subject.as_bytes())?;
+        console.send(b"\r")?;
+        let output = console.expect(PROMPT, options.timeout)?;
+        match check(&verifying_key, &output) {
+            Ok(work) => {
+                println!(
+                    "ok    {:?} ({}x{})",
+                    subject,
+                    work.masterpiece.first().map_or(0, String::len),
+                    work.masterpiece.len(),
+                );
+                works.push(work);
+            }
+            Err(reason) => {
+                failures += 1;
+                println!("FAIL  {:?}: {}", subject, reason);
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(Error::Failed {
+            failures,
+            total: options.subjects.len(),
+        });
+    }
+
+    // Every work came back, so any gap is the artist's
+    check_chain(&works).map_err(|err| Error::BrokenChain(format!("{:?}", err)))?;
+    println!("ok    audit log chain");
+
+    check_timestamps(&works, SystemTime::now()).map_err(Error::Timestamps)?;
+    println!("ok    timestamps");
+
+    // QEMU has to have exited before the disk image is opened again
+    drop(console);
+    console = boot()?;
+    console.expect(PROMPT, options.boot_timeout)?;
+    let subject = &options.subjects[0];
+    console.send(subject.as_bytes())?;
+    console.send(b"\r")?;
+    let output = console.expect(PROMPT, options.timeout)?;
+    let work = check(&verifying_key, &output).map_err(|reason| Error::Reboot(reason.to_owned()))?;
+    let last = works.last().unwrap().clone();
+    check_chain(&[last, work]).map_err(|err| Error::Reboot(format!("{:?}", err)))?;
+    println!("ok    audit log chain across a reboot");
+
+    console.send(b":batch\r")?;
+    console.expect("to cancel)\n", options.timeout)?;
+    for i in 0..BATCH_SIZE {
+        console.send(format!("Batch {}\r", i).as_bytes())?;
+        console.expect("\n", options.timeout)?;
+    }
+    console.send(b":end\r")?;
+    let output = console.expect(PROMPT, options.timeout * BATCH_SIZE as u32)?;
+    check_batch(&output, BATCH_SIZE).map_err(Error::Batch)?;
+    println!("ok    batch of {} subjects", BATCH_SIZE);
+    Ok(())
+}
+
+fn check(verifying_key: &VerifyingKey<Sha256>, output: &str) -> Result<Work, &'static str> {
+    let work = Work::parse(output).ok_or("no masterpiece in output")?;
+    let signature = Signature::try_from(&work.signature[..]).map_err(|_| "malformed signature")?;
+    verifying_key
+        .verify(&work.signed_data(), &signature)
+        .map_err(|_| "signature doesn't match")?;
+    Ok(work)
+}
+
+fn check_timestamps(works: &[Work], now: SystemTime) -> Result<(), String> {
+    let now = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| "the host's clock is before 1970".to_owned())?;
+    for pair in works.windows(2) {
+        if pair[1].timestamp < pair[0].timestamp {
+            return Err(format!(
+                "work {} is older than work {}",
+                pair[1].serial, pair[0].serial
+            ));
+        }
+    }
+    for work in works {
+        let timestamp = Duration::from_secs(work.timestamp);
+        if timestamp.max(now) - timestamp.min(now) > CLOCK_SLACK {
+            return Err(format!(
+                "work {} is from {} s after 1970",
+                work.serial, work.timestamp
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_batch(output: &str, size: usize) -> Result<(), String> {
+    let summary = output
+        .lines()
+        .find(|line| line.starts_with("{\"completed\":"))
+        .ok_or("no summary")?;
+    if summary == format!("{{\"completed\":{},\"failed\":0}}", size) {
+        Ok(())
+    } else {
+        Err(format!("expected {} completed, got {}", size, summary))
+    }
+}
+
+fn find_public_key(output: &str) -> Option<&str> {
+    let start = output.find(PUBLIC_KEY_BEGIN)?;
+    let end = start + output[start..].find(PUBLIC_KEY_END)? + PUBLIC_KEY_END.len();
+    Some(&output[start..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use banscii_sim::Banscii;
+
+    // The simulator prints exactly what the real system does over serial
+    #[test]
+    fn check_simulated_output() {
+        let mut banscii = Banscii::new();
+        let output = banscii.enter(":pubkey");
+        let public_key =
+            RsaPublicKey::from_public_key_pem(find_public_key(&output).unwrap()).unwrap();
+        let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key);
+        let output = banscii.enter("Hello, World!");
+        assert!(check(&verifying_key, &output).is_ok());
+        let tampered = output.replacen('@', "#", 1);
+        assert_eq!(
+            check(&verifying_key, &tampered).unwrap_err(),
+            "signature doesn't match"
+        );
+        assert!(check(&verifying_key, "banscii> ").is_err());
+
+        let works = ["Hello", "World"].map(|subject| {
+            let output = banscii.enter(subject);
+            check(&verifying_key, &output).unwrap()
+        });
+        assert!(check_chain(&works).is_ok());
+        assert!(check_chain(&[works[1].clone(), works[0].clone()]).is_err());
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(works[0].timestamp);
+        assert!(check_timestamps(&works, now).is_ok());
+        assert!(check_timestamps(&works, now + CLOCK_SLACK * 2).is_err());
+        let mut earlier = works[1].clone();
+        earlier.timestamp -= 1;
+        assert!(check_timestamps(&[works[0].clone(), earlier], now).is_err());
+    }
+
+    // What the real system does over serial when a batch outlasts the burst
+    #[test]
+    fn check_simulated_batch() {
+        let mut banscii = Banscii::new();
+        assert!(banscii.enter(":batch").ends_with("to cancel)\n"));
+        for i in 0..BATCH_SIZE {
+            assert!(banscii.enter(&format!("Batch {}", i)).ends_with('\n'));
+        }
+        let mut output = banscii.enter(":end");
+        while !output.ends_with(PROMPT) {
+            banscii.advance_time(Duration::from_secs(1));
+            output += &banscii.take_output();
+        }
+        assert_eq!(check_batch(&output, BATCH_SIZE), Ok(()));
+        assert!(check_batch(&output, BATCH_SIZE + 1).is_err());
+        assert!(check_batch("banscii> ", BATCH_SIZE).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+//! Well-formed requests to complete a draft, which mostly get past the
+//! artist's checks and into rendering and signing.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sel4cp::message::{MessageInfo, StatusMessageLabel};
+
+use banscii_artist_interface_types::*;
+use banscii_fuzz::check_response;
+use banscii_sim::{IsolatedArtist, REGION_SIZE};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    height: u8,
+    width: u8,
+    draft_start: u16,
+    // Taken from the dimensions if absent
+    draft_size: Option<u16>,
+    region_in: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let req = CompleteRequest {
+        height: input.height.into(),
+        width: input.width.into(),
+        draft_start: input.draft_start.into(),
+        draft_size: input.draft_size.map_or(
+            usize::from(input.height) * usize::from(input.width),
+            usize::from,
+        ),
+    };
+    let mut artist = IsolatedArtist::new();
+    let (response, region_out) = artist.call(
+        MessageInfo::send(RequestTag::Complete, req),
+        &input.region_in,
+    );
+    check_response(Some(RequestTag::Complete), &response, &region_out);
+
+    let fits = req.draft_start + req.draft_size + MAX_SIGNATURE_SIZE <= REGION_SIZE;
+    if req.height * req.width == req.draft_size && fits {
+        assert_eq!(response.label(), StatusMessageLabel::Ok.into());
+        let resp = response.recv::<CompleteResponse>().unwrap();
+        assert_eq!((resp.height, resp.width), (req.height, req.width));
+    }
+});
@@ -0,0 +1,29 @@
+//! Any request at all, with any contents in the region shared with the
+//! artist.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sel4cp::message::{MessageInfo, MAX_MESSAGE_SIZE};
+
+use banscii_artist_interface_types::RequestTag;
+use banscii_fuzz::check_response;
+use banscii_sim::IsolatedArtist;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    // Narrow, so that most labels are valid tags
+    label: u8,
+    message: Vec<u8>,
+    region_in: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let label = input.label.into();
+    let message = &input.message[..input.message.len().min(MAX_MESSAGE_SIZE)];
+    let mut artist = IsolatedArtist::new();
+    let (response, region_out) =
+        artist.call(MessageInfo::from_raw(label, message), &input.region_in);
+    check_response(RequestTag::try_from(label).ok(), &response, &region_out);
+});
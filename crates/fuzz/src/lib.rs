@@ -0,0 +1,75 @@
+//! Checks shared by the fuzz targets in `fuzz_targets`, which drive the
+//! artist through [`IsolatedArtist`](banscii_sim::IsolatedArtist).
+
+use std::ops::Range;
+
+use sel4cp::message::{MessageInfo, StatusMessageLabel};
+
+use banscii_artist_interface_types::*;
+use banscii_sim::REGION_SIZE;
+
+/// Asserts that `response` is one that the assistant could make sense of,
+/// given the request it answers. Anything the artist accepts must point only
+/// into its outgoing region, `region_out`.
+pub fn check_response(tag: Option<RequestTag>, response: &MessageInfo, region_out: &[u8]) {
+    let status = StatusMessageLabel::try_from(response.label())
+        .unwrap_or_else(|_| panic!("unknown status label {}", response.label()));
+    if status == StatusMessageLabel::Error {
+        let resp = response.recv::<RejectResponse>().unwrap();
+        let reason = RejectReason::try_from(resp.reason).unwrap();
+        assert_eq!(resp.retry_after > 0, reason == RejectReason::RateLimited);
+        return;
+    }
+    match tag.expect("accepted a request with an unknown tag") {
+        RequestTag::Complete => {
+            let resp = response.recv::<CompleteResponse>().unwrap();
+            let masterpiece = span(resp.masterpiece_start, resp.masterpiece_size);
+            let signature = span(resp.signature_start, resp.signature_size);
+            assert!(masterpiece.end <= signature.start || signature.end <= masterpiece.start);
+            assert_eq!(
+                resp.height.checked_mul(resp.width),
+                Some(resp.masterpiece_size)
+            );
+            assert!(resp.signature_size <= MAX_SIGNATURE_SIZE);
+            assert!(resp.serial > 0);
+            assert!((1..=resp.edition_size).contains(&resp.edition));
+            assert!(region_out[masterpiece]
+                .iter()
+                .all(|c| c.is_ascii_graphic() || *c == b' '));
+        }
+        RequestTag::GetPublicKey => {
+            let resp = response.recv::<GetPublicKeyResponse>().unwrap();
+            let pem = &region_out[span(resp.pem_start, resp.pem_size)];
+            assert!(std::str::from_utf8(pem).is_ok());
+        }
+        RequestTag::Verify => {
+            let resp = response.recv::<VerifyResponse>().unwrap();
+            assert!(resp.valid <= 1);
+        }
+        RequestTag::GetStats => {
+            response.recv::<GetStatsResponse>().unwrap();
+        }
+        RequestTag::GetAuditLog => {
+            let resp = response.recv::<GetAuditLogResponse>().unwrap();
+            let size = resp.count.checked_mul(AUDIT_ENTRY_SIZE).unwrap();
+            let mut chain = Chain::START;
+            for entry in region_out[span(resp.entries_start, size)].chunks(AUDIT_ENTRY_SIZE) {
+                let entry = AuditEntry::from_bytes(entry.try_into().unwrap());
+                // The page may start part way through the log
+                if chain == Chain::START {
+                    chain = Chain::before(&entry).unwrap_or(chain);
+                }
+                chain.extend(&entry).unwrap();
+            }
+            assert!(resp.more <= 1);
+        }
+    }
+}
+
+fn span(start: usize, size: usize) -> Range<usize> {
+    let end = start
+        .checked_add(size)
+        .filter(|end| *end <= REGION_SIZE)
+        .unwrap_or_else(|| panic!("span of {} bytes at {} is out of bounds", size, start));
+    start..end
+}
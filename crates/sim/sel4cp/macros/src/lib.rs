@@ -0,0 +1,37 @@
+use proc_macro::{TokenStream, TokenTree};
+
+/// Leaves the initialization function as it is, and adds a `sim_init` next to
+/// it which boxes up the handler it returns, so that the simulator can hold
+/// protection domains of different types. Arguments such as `heap_size` are
+/// accepted and ignored, since the host's allocator is used instead.
+#[proc_macro_attribute]
+pub fn protection_domain(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let name = init_fn_name(item.clone()).expect("#[protection_domain] must be applied to a fn");
+    let mut out = item;
+    out.extend(
+        format!(
+            "pub(crate) fn sim_init() -> ::std::boxed::Box<dyn ::sel4cp::DynHandler> {{ \
+                ::std::boxed::Box::new({}()) \
+            }}",
+            name,
+        )
+        .parse::<TokenStream>()
+        .unwrap(),
+    );
+    out
+}
+
+fn init_fn_name(item: TokenStream) -> Option<String> {
+    let mut tokens = item.into_iter();
+    while let Some(token) = tokens.next() {
+        if let TokenTree::Ident(ident) = token {
+            if ident.to_string() == "fn" {
+                return match tokens.next() {
+                    Some(TokenTree::Ident(name)) => Some(name.to_string()),
+                    _ => None,
+                };
+            }
+        }
+    }
+    None
+}
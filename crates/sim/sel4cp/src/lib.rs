@@ -0,0 +1,104 @@
+//! A host stand-in for the subset of the `sel4cp` crate that Banscii uses.
+//!
+//! Protection domains built against this crate run as ordinary objects in one
+//! process, scheduled by a [`sim::System`] which is wired up according to a
+//! system description file. Protected procedure calls become direct calls into
+//! the callee's handler, notifications are queued until the system is run, and
+//! memory regions are backed by heap allocations.
+
+pub mod memory_region;
+pub mod message;
+pub mod sim;
+
+pub use banscii_sim_sel4cp_macros::protection_domain;
+
+use message::MessageInfo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Channel {
+    index: usize,
+}
+
+impl Channel {
+    pub const fn new(index: usize) -> Self {
+        Self { index }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn notify(&self) {
+        sim::notify(*self)
+    }
+
+    pub fn irq_ack(&self) -> Result<(), IrqAckError> {
+        sim::irq_ack(*self)
+    }
+
+    pub fn pp_call(&self, msg_info: MessageInfo) -> MessageInfo {
+        sim::pp_call(*self, msg_info)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IrqAckError;
+
+pub trait Handler {
+    type Error;
+
+    fn notified(&mut self, channel: Channel) -> Result<(), Self::Error> {
+        panic!("unexpected notification from channel {:?}", channel)
+    }
+
+    fn protected(
+        &mut self,
+        channel: Channel,
+        msg_info: MessageInfo,
+    ) -> Result<MessageInfo, Self::Error> {
+        panic!(
+            "unexpected protected procedure call from channel {:?} with label {}",
+            channel,
+            msg_info.label(),
+        )
+    }
+}
+
+/// An object-safe view of a [`Handler`], so that protection domains of
+/// different types can be held together. A handler returning an error is
+/// treated like a protection domain faulting, which the simulator doesn't
+/// recover from.
+pub trait DynHandler {
+    fn notified(&mut self, channel: Channel);
+
+    fn protected(&mut self, channel: Channel, msg_info: MessageInfo) -> MessageInfo;
+}
+
+impl<T: Handler> DynHandler for T {
+    fn notified(&mut self, channel: Channel) {
+        if Handler::notified(self, channel).is_err() {
+            panic!("handler failed on notification from channel {:?}", channel)
+        }
+    }
+
+    fn protected(&mut self, channel: Channel, msg_info: MessageInfo) -> MessageInfo {
+        match Handler::protected(self, channel, msg_info) {
+            Ok(msg_info) => msg_info,
+            Err(_) => panic!("handler failed on call from channel {:?}", channel),
+        }
+    }
+}
+
+/// Resolves a symbol which the system description patches with the virtual
+/// address of a memory region, by looking up the region mapped with a
+/// matching `setvar_vaddr` into the current protection domain.
+#[macro_export]
+macro_rules! memory_region_symbol {
+    ($symbol:ident: *mut [u8], n = $n:expr) => {
+        $crate::sim::memory_region_symbol(stringify!($symbol), $n)
+    };
+    ($symbol:ident: *mut $ty:ty) => {
+        $crate::sim::memory_region_symbol(stringify!($symbol), ::core::mem::size_of::<$ty>())
+            .cast::<$ty>()
+    };
+}
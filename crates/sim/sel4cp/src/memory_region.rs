@@ -0,0 +1,105 @@
+use core::marker::PhantomData;
+use core::ops::Range;
+use core::ptr::{self, NonNull};
+
+pub use crate::memory_region_symbol;
+
+pub struct ReadOnly;
+
+pub struct ReadWrite;
+
+/// A view of memory which another protection domain may access concurrently.
+/// All accesses go through raw pointers, so no references into the region are
+/// ever handed out.
+pub struct ExternallySharedRef<'a, T: ?Sized, A = ReadWrite> {
+    ptr: NonNull<T>,
+    phantom: PhantomData<(&'a T, A)>,
+}
+
+impl<'a, T: ?Sized> ExternallySharedRef<'a, T> {
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and writes for `'a`.
+    pub unsafe fn new(ptr: NonNull<T>) -> Self {
+        Self {
+            ptr,
+            phantom: PhantomData,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads for `'a`.
+    pub unsafe fn new_read_only(ptr: NonNull<T>) -> ExternallySharedRef<'a, T, ReadOnly> {
+        ExternallySharedRef {
+            ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: ?Sized, A> ExternallySharedRef<'a, T, A> {
+    pub fn as_ptr(&self) -> ExternallySharedPtr<'_, T, ReadOnly> {
+        ExternallySharedPtr {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> ExternallySharedPtr<'_, T, A> {
+        ExternallySharedPtr {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+pub struct ExternallySharedPtr<'a, T: ?Sized, A = ReadWrite> {
+    ptr: NonNull<T>,
+    phantom: PhantomData<(&'a T, A)>,
+}
+
+impl<'a, A> ExternallySharedPtr<'a, [u8], A> {
+    pub fn len(&self) -> usize {
+        self.ptr.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn index(self, range: Range<usize>) -> Self {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range {:?} out of bounds for shared region of length {}",
+            range,
+            self.len(),
+        );
+        // SAFETY: in bounds, as checked above
+        let start = unsafe { self.ptr.cast::<u8>().as_ptr().add(range.start) };
+        Self {
+            ptr: NonNull::slice_from_raw_parts(NonNull::new(start).unwrap(), range.len()),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn copy_into_slice(self, dst: &mut [u8]) {
+        assert_eq!(self.len(), dst.len());
+        // SAFETY: the region is valid for reads for 'a
+        unsafe { ptr::copy(self.ptr.cast::<u8>().as_ptr(), dst.as_mut_ptr(), dst.len()) }
+    }
+
+    pub fn copy_to_vec(self) -> Vec<u8> {
+        let mut v = vec![0; self.len()];
+        self.copy_into_slice(&mut v);
+        v
+    }
+}
+
+impl<'a> ExternallySharedPtr<'a, [u8], ReadWrite> {
+    pub fn copy_from_slice(self, src: &[u8]) {
+        assert_eq!(self.len(), src.len());
+        // SAFETY: the region is valid for writes for 'a
+        unsafe { ptr::copy(src.as_ptr(), self.ptr.cast::<u8>().as_ptr(), src.len()) }
+    }
+}
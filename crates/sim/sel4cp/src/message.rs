@@ -0,0 +1,74 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use zerocopy::{AsBytes, FromBytes};
+
+pub type MessageLabel = u64;
+
+/// seL4 passes at most this many message registers of a word each.
+pub const MAX_MESSAGE_SIZE: usize = 120 * core::mem::size_of::<u64>();
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageInfo {
+    label: MessageLabel,
+    bytes: Vec<u8>,
+}
+
+impl MessageInfo {
+    pub fn send<T: AsBytes>(label: impl Into<MessageLabel>, value: T) -> Self {
+        let bytes = value.as_bytes().to_vec();
+        assert!(
+            bytes.len() <= MAX_MESSAGE_SIZE,
+            "message of {} bytes doesn't fit in the IPC buffer",
+            bytes.len(),
+        );
+        Self {
+            label: label.into(),
+            bytes,
+        }
+    }
+
+    /// A message with arbitrary contents, such as one no well-behaved sender
+    /// would produce. Not part of the real API.
+    pub fn from_raw(label: MessageLabel, bytes: &[u8]) -> Self {
+        assert!(bytes.len() <= MAX_MESSAGE_SIZE);
+        Self {
+            label,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    /// Not part of the real API.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn label(&self) -> MessageLabel {
+        self.label
+    }
+
+    pub fn recv<T: FromBytes>(&self) -> Result<T, MessageRecvError> {
+        T::read_from(&self.bytes[..]).ok_or(MessageRecvError)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageRecvError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u64)]
+pub enum StatusMessageLabel {
+    Ok,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoMessageLabel;
+
+impl From<NoMessageLabel> for MessageLabel {
+    fn from(_: NoMessageLabel) -> Self {
+        0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct NoMessageValue;
@@ -0,0 +1,394 @@
+//! Scheduling and wiring for simulated protection domains.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use crate::message::MessageInfo;
+use crate::{Channel, DynHandler, IrqAckError};
+
+mod description;
+
+use description::Description;
+
+type Init = Box<dyn FnOnce() -> Box<dyn DynHandler>>;
+
+pub struct SystemBuilder {
+    description: Description,
+    inits: BTreeMap<String, Init>,
+    contents: BTreeMap<String, Vec<u8>>,
+    stand_ins: Vec<StandIn>,
+}
+
+// A memory region of the simulator's own, and where it's mapped
+struct StandIn {
+    name: String,
+    size: usize,
+    pd: String,
+    setvar_vaddr: String,
+}
+
+impl SystemBuilder {
+    /// `description` is the contents of a system description file, such as
+    /// `banscii.system`.
+    pub fn new(description: &str) -> Self {
+        Self {
+            description: Description::parse(description),
+            inits: BTreeMap::new(),
+            contents: BTreeMap::new(),
+            stand_ins: Vec::new(),
+        }
+    }
+
+    /// Provides the initialization function for the protection domain called
+    /// `name`, usually the `sim_init` generated by `#[protection_domain]`.
+    pub fn protection_domain(
+        mut self,
+        name: &str,
+        init: impl FnOnce() -> Box<dyn DynHandler> + 'static,
+    ) -> Self {
+        self.inits.insert(name.to_owned(), Box::new(init));
+        self
+    }
+
+    /// Fills the memory region called `name` with `contents` rather than
+    /// zeros, such as those taken from an earlier run with
+    /// [`System::memory_region`] to simulate memory that survives a reboot.
+    pub fn memory_region(mut self, name: &str, contents: Vec<u8>) -> Self {
+        self.contents.insert(name.to_owned(), contents);
+        self
+    }
+
+    /// Adds a memory region of `size` bytes called `name` that isn't in the
+    /// system description, mapped into `pd` at `setvar_vaddr`, to stand in
+    /// for a device that the simulator doesn't model, such as a disk.
+    pub fn stand_in_region(
+        mut self,
+        name: &str,
+        size: usize,
+        pd: &str,
+        setvar_vaddr: &str,
+    ) -> Self {
+        self.stand_ins.push(StandIn {
+            name: name.to_owned(),
+            size,
+            pd: pd.to_owned(),
+            setvar_vaddr: setvar_vaddr.to_owned(),
+        });
+        self
+    }
+
+    /// Initializes every protection domain, highest priority first.
+    pub fn build(mut self) -> System {
+        let sizes = self
+            .description
+            .memory_regions
+            .iter()
+            .map(|mr| (&mr.name, mr.size))
+            .chain(
+                self.stand_ins
+                    .iter()
+                    .map(|stand_in| (&stand_in.name, stand_in.size)),
+            );
+        let regions = sizes
+            .map(|(name, size)| {
+                let contents = self.contents.remove(name).unwrap_or_else(|| vec![0; size]);
+                assert_eq!(
+                    contents.len(),
+                    size,
+                    "contents don't fit memory region '{}'",
+                    name,
+                );
+                (name.clone(), Region::new(contents))
+            })
+            .collect::<BTreeMap<_, _>>();
+        if let Some(name) = self.contents.keys().next() {
+            panic!("no memory region named '{}'", name);
+        }
+
+        let pd_index = |name: &str| {
+            self.description
+                .protection_domains
+                .iter()
+                .position(|pd| pd.name == name)
+                .unwrap_or_else(|| panic!("no protection domain named '{}'", name))
+        };
+
+        let mut pds = self
+            .description
+            .protection_domains
+            .iter()
+            .map(|pd| ProtectionDomain {
+                name: pd.name.clone(),
+                priority: pd.priority,
+                pp: pd.pp,
+                handler: RefCell::new(None),
+                channels: BTreeMap::new(),
+                irqs: pd.irqs.iter().map(|irq| (irq.id, irq.irq)).collect(),
+                symbols: pd
+                    .maps
+                    .iter()
+                    .filter_map(|map| {
+                        let region = regions
+                            .get(&map.mr)
+                            .unwrap_or_else(|| panic!("no memory region named '{}'", map.mr));
+                        Some((map.setvar_vaddr.clone()?, region.ptr))
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        for stand_in in &self.stand_ins {
+            let region = &regions[&stand_in.name];
+            pds[pd_index(&stand_in.pd)]
+                .symbols
+                .insert(stand_in.setvar_vaddr.clone(), region.ptr);
+        }
+
+        for [a, b] in &self.description.channels {
+            let (a_pd, b_pd) = (pd_index(&a.pd), pd_index(&b.pd));
+            pds[a_pd].channels.insert(
+                a.id,
+                End {
+                    pd: b_pd,
+                    channel: Channel::new(b.id),
+                },
+            );
+            pds[b_pd].channels.insert(
+                b.id,
+                End {
+                    pd: a_pd,
+                    channel: Channel::new(a.id),
+                },
+            );
+        }
+
+        let mut order = (0..pds.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| std::cmp::Reverse(pds[*i].priority));
+
+        let inner = Rc::new(Inner {
+            pds,
+            regions,
+            pending: RefCell::new(VecDeque::new()),
+        });
+
+        for i in order {
+            let pd = &inner.pds[i];
+            let init = self
+                .inits
+                .remove(&pd.name)
+                .unwrap_or_else(|| panic!("no initialization function for '{}'", pd.name));
+            let handler = enter(&inner, i, init);
+            *pd.handler.borrow_mut() = Some(handler);
+        }
+
+        System { inner }
+    }
+}
+
+/// A running system. Nothing happens between calls into it, so tests can
+/// inspect the world at any point.
+pub struct System {
+    inner: Rc<Inner>,
+}
+
+impl System {
+    /// Raises an interrupt, which will be delivered the next time the system
+    /// is run to whichever protection domain claims it.
+    pub fn irq(&self, irq: u64) {
+        let (pd, id) = self
+            .inner
+            .pds
+            .iter()
+            .enumerate()
+            .find_map(|(i, pd)| {
+                let id = pd.irqs.iter().find(|(_, n)| **n == irq)?.0;
+                Some((i, *id))
+            })
+            .unwrap_or_else(|| panic!("no protection domain handles irq {}", irq));
+        self.inner
+            .pending
+            .borrow_mut()
+            .push_back((pd, Channel::new(id)));
+    }
+
+    /// Delivers notifications until there are none left.
+    pub fn run(&self) {
+        loop {
+            let next = self.inner.pending.borrow_mut().pop_front();
+            let Some((i, channel)) = next else {
+                break;
+            };
+            let pd = &self.inner.pds[i];
+            let mut handler = pd.handler.borrow_mut();
+            enter(&self.inner, i, || {
+                handler.as_mut().unwrap().notified(channel)
+            });
+        }
+    }
+
+    /// Runs `f` as the protection domain called `name`, so that it can use
+    /// that domain's channels and memory regions. This lets a test stand in
+    /// for a protection domain whose handler does nothing.
+    pub fn run_as<R>(&self, name: &str, f: impl FnOnce() -> R) -> R {
+        let pd = self
+            .inner
+            .pds
+            .iter()
+            .position(|pd| pd.name == name)
+            .unwrap_or_else(|| panic!("no protection domain named '{}'", name));
+        enter(&self.inner, pd, f)
+    }
+
+    /// A copy of what the memory region called `name` holds now.
+    pub fn memory_region(&self, name: &str) -> Vec<u8> {
+        let region = self
+            .inner
+            .regions
+            .get(name)
+            .unwrap_or_else(|| panic!("no memory region named '{}'", name));
+        // SAFETY: no protection domain is running, so nothing else is
+        // accessing the region
+        unsafe { region.ptr.as_ref() }.to_vec()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.inner.pending.borrow().is_empty()
+    }
+}
+
+struct Inner {
+    // Dropped before the regions that handlers may point into
+    pds: Vec<ProtectionDomain>,
+    regions: BTreeMap<String, Region>,
+    pending: RefCell<VecDeque<(usize, Channel)>>,
+}
+
+impl Inner {
+    fn end(&self, pd: usize, channel: Channel) -> &End {
+        let pd = &self.pds[pd];
+        pd.channels
+            .get(&channel.index())
+            .unwrap_or_else(|| panic!("'{}' has no channel {}", pd.name, channel.index()))
+    }
+}
+
+struct ProtectionDomain {
+    name: String,
+    priority: u8,
+    pp: bool,
+    handler: RefCell<Option<Box<dyn DynHandler>>>,
+    channels: BTreeMap<usize, End>,
+    irqs: BTreeMap<usize, u64>,
+    symbols: BTreeMap<String, NonNull<[u8]>>,
+}
+
+struct End {
+    pd: usize,
+    channel: Channel,
+}
+
+struct Region {
+    ptr: NonNull<[u8]>,
+}
+
+impl Region {
+    fn new(contents: Vec<u8>) -> Self {
+        Self {
+            ptr: NonNull::from(Box::leak(contents.into_boxed_slice())),
+        }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        // SAFETY: allocated in Region::new, and no handler outlives Inner
+        drop(unsafe { Box::from_raw(self.ptr.as_ptr()) })
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Vec<(Rc<Inner>, usize)>> = RefCell::new(Vec::new());
+}
+
+fn enter<R>(inner: &Rc<Inner>, pd: usize, f: impl FnOnce() -> R) -> R {
+    struct Exit;
+
+    impl Drop for Exit {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.borrow_mut().pop());
+        }
+    }
+
+    CURRENT.with(|current| current.borrow_mut().push((inner.clone(), pd)));
+    let _exit = Exit;
+    f()
+}
+
+fn current() -> (Rc<Inner>, usize) {
+    CURRENT
+        .with(|current| current.borrow().last().cloned())
+        .expect("not running in a simulated protection domain")
+}
+
+pub(crate) fn pp_call(channel: Channel, msg_info: MessageInfo) -> MessageInfo {
+    let (inner, caller) = current();
+    let end = inner.end(caller, channel);
+    let (caller, callee) = (&inner.pds[caller], &inner.pds[end.pd]);
+    assert!(
+        callee.pp,
+        "'{}' called '{}', which doesn't provide protected procedures",
+        caller.name, callee.name,
+    );
+    assert!(
+        callee.priority > caller.priority,
+        "'{}' called '{}', which doesn't have a higher priority",
+        caller.name,
+        callee.name,
+    );
+    let mut handler = callee.handler.borrow_mut();
+    let handler = handler.as_mut().unwrap_or_else(|| {
+        panic!(
+            "'{}' called '{}' before it was initialized",
+            caller.name, callee.name
+        )
+    });
+    enter(&inner, end.pd, || handler.protected(end.channel, msg_info))
+}
+
+pub(crate) fn notify(channel: Channel) {
+    let (inner, pd) = current();
+    let end = inner.end(pd, channel);
+    inner.pending.borrow_mut().push_back((end.pd, end.channel));
+}
+
+pub(crate) fn irq_ack(channel: Channel) -> Result<(), IrqAckError> {
+    let (inner, pd) = current();
+    if inner.pds[pd].irqs.contains_key(&channel.index()) {
+        Ok(())
+    } else {
+        Err(IrqAckError)
+    }
+}
+
+/// Backs [`memory_region_symbol!`](crate::memory_region_symbol).
+pub fn memory_region_symbol(symbol: &str, size: usize) -> NonNull<[u8]> {
+    let (inner, pd) = current();
+    let pd = &inner.pds[pd];
+    let region = pd.symbols.get(symbol).unwrap_or_else(|| {
+        panic!(
+            "no memory region is mapped into '{}' with setvar_vaddr=\"{}\"",
+            pd.name, symbol
+        )
+    });
+    assert!(
+        size <= region.len(),
+        "'{}' expects {} bytes at {}, but only {} are mapped",
+        pd.name,
+        size,
+        symbol,
+        region.len(),
+    );
+    NonNull::slice_from_raw_parts(region.cast::<u8>(), size)
+}
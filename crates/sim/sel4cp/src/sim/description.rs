@@ -0,0 +1,129 @@
+//! Just enough of the system description format to wire up a simulation.
+
+use roxmltree::{Document, Node};
+
+pub(super) struct Description {
+    pub(super) memory_regions: Vec<MemoryRegion>,
+    pub(super) protection_domains: Vec<ProtectionDomain>,
+    pub(super) channels: Vec<[End; 2]>,
+}
+
+pub(super) struct MemoryRegion {
+    pub(super) name: String,
+    pub(super) size: usize,
+}
+
+pub(super) struct ProtectionDomain {
+    pub(super) name: String,
+    pub(super) priority: u8,
+    pub(super) pp: bool,
+    pub(super) maps: Vec<Map>,
+    pub(super) irqs: Vec<Irq>,
+}
+
+pub(super) struct Map {
+    pub(super) mr: String,
+    pub(super) setvar_vaddr: Option<String>,
+}
+
+pub(super) struct Irq {
+    pub(super) irq: u64,
+    pub(super) id: usize,
+}
+
+pub(super) struct End {
+    pub(super) pd: String,
+    pub(super) id: usize,
+}
+
+impl Description {
+    /// Panics with an explanation if `xml` is malformed, since there's
+    /// nothing sensible a simulation can do without its wiring.
+    pub(super) fn parse(xml: &str) -> Self {
+        let doc = Document::parse(xml)
+            .unwrap_or_else(|err| panic!("invalid system description: {}", err));
+        let mut description = Self {
+            memory_regions: vec![],
+            protection_domains: vec![],
+            channels: vec![],
+        };
+        for node in doc.root_element().children().filter(Node::is_element) {
+            match node.tag_name().name() {
+                "memory_region" => description.memory_regions.push(MemoryRegion {
+                    name: attr(node, "name").to_owned(),
+                    size: parse_int(node, "size"),
+                }),
+                "protection_domain" => description
+                    .protection_domains
+                    .push(ProtectionDomain::parse(node)),
+                "channel" => {
+                    let ends = node
+                        .children()
+                        .filter(|child| child.has_tag_name("end"))
+                        .map(|end| End {
+                            pd: attr(end, "pd").to_owned(),
+                            id: parse_int(end, "id"),
+                        })
+                        .collect::<Vec<_>>();
+                    let ends = ends
+                        .try_into()
+                        .unwrap_or_else(|_| panic!("a channel must have exactly two ends"));
+                    description.channels.push(ends);
+                }
+                _ => {}
+            }
+        }
+        description
+    }
+}
+
+impl ProtectionDomain {
+    fn parse(node: Node) -> Self {
+        let mut pd = Self {
+            name: attr(node, "name").to_owned(),
+            priority: node
+                .attribute("priority")
+                .map_or(0, |_| parse_int(node, "priority")),
+            pp: node.attribute("pp") == Some("true"),
+            maps: vec![],
+            irqs: vec![],
+        };
+        for child in node.children().filter(Node::is_element) {
+            match child.tag_name().name() {
+                "map" => pd.maps.push(Map {
+                    mr: attr(child, "mr").to_owned(),
+                    setvar_vaddr: child.attribute("setvar_vaddr").map(str::to_owned),
+                }),
+                "irq" => pd.irqs.push(Irq {
+                    irq: parse_int(child, "irq"),
+                    id: parse_int(child, "id"),
+                }),
+                _ => {}
+            }
+        }
+        pd
+    }
+}
+
+fn attr<'a>(node: Node<'a, '_>, name: &str) -> &'a str {
+    node.attribute(name).unwrap_or_else(|| {
+        panic!(
+            "<{}> is missing attribute '{}'",
+            node.tag_name().name(),
+            name
+        )
+    })
+}
+
+fn parse_int<T: TryFrom<u64>>(node: Node, name: &str) -> T {
+    let value = attr(node, name);
+    let digits = value.replace('_', "");
+    let parsed = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    parsed
+        .ok()
+        .and_then(|n| n.try_into().ok())
+        .unwrap_or_else(|| panic!("invalid value for '{}': {:?}", name, value))
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use sel4cp::memory_region::{memory_region_symbol, ExternallySharedRef};
+use sel4cp::message::MessageInfo;
+use sel4cp::sim::System;
+use sel4cp::{Channel, Handler};
+
+use crate::timer::{self, Clock};
+use crate::{artist, storage, system_builder, MockStorage, TIMER_IRQ};
+
+// The assistant's end of its channel to the artist
+const ARTIST: Channel = Channel::new(1);
+
+// The size of both regions shared with the artist, as in banscii.system
+pub const REGION_SIZE: usize = 0x4_000;
+
+/// The artist with nothing else running but storage and a timer whose clock
+/// only moves when [`Self::advance_time`] moves it, called directly through the
+/// assistant's channel and regions. Since the artist must not trust the
+/// assistant, this is for trying it out on requests which the real assistant
+/// would never make.
+///
+/// The simulated regions check every access against their bounds, so an
+/// artist which strays outside of them panics.
+pub struct IsolatedArtist {
+    system: System,
+    clock: Clock,
+}
+
+impl IsolatedArtist {
+    pub fn new() -> Self {
+        let clock = Clock::default();
+        let system = system_builder()
+            .protection_domain("pl011_driver", || Box::new(Absent))
+            .protection_domain("assistant", || Box::new(Absent))
+            .protection_domain("timer", {
+                let clock = clock.clone();
+                move || Box::new(timer::init(clock))
+            })
+            .protection_domain("storage", storage::sim_init)
+            .protection_domain("artist", artist::sim_init)
+            .build();
+        Self { system, clock }
+    }
+
+    /// As [`Self::new`], but with the artist's storage kept in `storage`, so
+    /// that a test can meddle with it, or start another artist on the same.
+    pub fn with_storage(storage: MockStorage) -> Self {
+        let clock = Clock::default();
+        let system = system_builder()
+            .protection_domain("pl011_driver", || Box::new(Absent))
+            .protection_domain("assistant", || Box::new(Absent))
+            .protection_domain("timer", {
+                let clock = clock.clone();
+                move || Box::new(timer::init(clock))
+            })
+            .protection_domain("storage", move || Box::new(storage))
+            .protection_domain("artist", artist::sim_init)
+            .build();
+        Self { system, clock }
+    }
+
+    /// Moves time on by `duration`, and runs the system until the artist has
+    /// dealt with any timeout expiring.
+    pub fn advance_time(&mut self, duration: Duration) {
+        self.clock.advance(duration.as_nanos().try_into().unwrap());
+        self.system.irq(TIMER_IRQ);
+        self.system.run();
+    }
+
+    /// Fills the artist's incoming region with `region_in`, truncated or
+    /// padded with zeros, then makes `request`. Returns the artist's response
+    /// along with the contents of its outgoing region.
+    pub fn call(&mut self, request: MessageInfo, region_in: &[u8]) -> (MessageInfo, Vec<u8>) {
+        self.system.run_as("assistant", || {
+            // SAFETY: the simulated regions live as long as the system
+            let mut to_artist = unsafe {
+                ExternallySharedRef::<[u8]>::new(memory_region_symbol!(
+                    region_out_start: *mut [u8],
+                    n = REGION_SIZE
+                ))
+            };
+            let from_artist = unsafe {
+                ExternallySharedRef::<[u8]>::new_read_only(memory_region_symbol!(
+                    region_in_start: *mut [u8],
+                    n = REGION_SIZE
+                ))
+            };
+
+            let mut contents = region_in[..region_in.len().min(REGION_SIZE)].to_vec();
+            contents.resize(REGION_SIZE, 0);
+            to_artist.as_mut_ptr().copy_from_slice(&contents);
+
+            let response = ARTIST.pp_call(request);
+            (response, from_artist.as_ptr().copy_to_vec())
+        })
+    }
+}
+
+impl Default for IsolatedArtist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stands in for the protection domains left out, which are never called.
+pub(crate) struct Absent;
+
+impl Handler for Absent {
+    type Error = !;
+}
@@ -0,0 +1,89 @@
+use sel4cp::memory_region::{memory_region_symbol, ExternallySharedRef};
+use sel4cp::message::MessageInfo;
+use sel4cp::sim::System;
+use sel4cp::Channel;
+
+use banscii_storage_interface_types::REGION_SIZE;
+
+use crate::isolated_artist::Absent;
+use crate::{storage, system_builder};
+
+/// One of the protection domains with a namespace in storage, and a timeout
+/// of its own in the timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Client {
+    Artist,
+    Assistant,
+}
+
+impl Client {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Artist => "artist",
+            Self::Assistant => "assistant",
+        }
+    }
+
+    // The client's end of its channel to storage
+    fn channel(self) -> Channel {
+        match self {
+            Self::Artist => Channel::new(1),
+            Self::Assistant => Channel::new(2),
+        }
+    }
+}
+
+/// The storage protection domain with nothing else running, called directly
+/// through either client's channel and region, as [`IsolatedArtist`] is for
+/// the artist.
+///
+/// [`IsolatedArtist`]: crate::IsolatedArtist
+pub struct IsolatedStorage {
+    system: System,
+}
+
+impl IsolatedStorage {
+    pub fn new() -> Self {
+        let system = system_builder()
+            .protection_domain("pl011_driver", || Box::new(Absent))
+            .protection_domain("storage", storage::sim_init)
+            .protection_domain("timer", || Box::new(Absent))
+            .protection_domain("assistant", || Box::new(Absent))
+            .protection_domain("artist", || Box::new(Absent))
+            .build();
+        Self { system }
+    }
+
+    /// Fills the client's region with `region`, truncated or padded with
+    /// zeros, then makes `request` as that client. Returns the response along
+    /// with what the region holds afterwards.
+    pub fn call(
+        &mut self,
+        client: Client,
+        request: MessageInfo,
+        region: &[u8],
+    ) -> (MessageInfo, Vec<u8>) {
+        self.system.run_as(client.name(), || {
+            // SAFETY: the simulated regions live as long as the system
+            let mut shared = unsafe {
+                ExternallySharedRef::<[u8]>::new(memory_region_symbol!(
+                    storage_region_start: *mut [u8],
+                    n = REGION_SIZE
+                ))
+            };
+
+            let mut contents = region[..region.len().min(REGION_SIZE)].to_vec();
+            contents.resize(REGION_SIZE, 0);
+            shared.as_mut_ptr().copy_from_slice(&contents);
+
+            let response = client.channel().pp_call(request);
+            (response, shared.as_ptr().copy_to_vec())
+        })
+    }
+}
+
+impl Default for IsolatedStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
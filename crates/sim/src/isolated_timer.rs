@@ -0,0 +1,99 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use sel4cp::sim::System;
+use sel4cp::{Channel, Handler};
+
+use banscii_timer_client::Timer;
+
+use crate::isolated_artist::Absent;
+use crate::timer::{self, Clock};
+use crate::{system_builder, Client, TIMER_IRQ};
+
+// The client's end of its channel to the timer
+fn channel(client: Client) -> Channel {
+    match client {
+        Client::Artist => Channel::new(2),
+        Client::Assistant => Channel::new(3),
+    }
+}
+
+/// The timer with nothing else running, called through either client's
+/// channel with the client library, as [`IsolatedStorage`] is for storage.
+/// Time only moves when [`Self::advance`] moves it.
+///
+/// [`IsolatedStorage`]: crate::IsolatedStorage
+pub struct IsolatedTimer {
+    system: System,
+    clock: Clock,
+    notified: Rc<RefCell<Vec<Client>>>,
+}
+
+impl IsolatedTimer {
+    pub fn new() -> Self {
+        let clock = Clock::default();
+        let notified = Rc::new(RefCell::new(Vec::new()));
+        let listener = |client| {
+            let notified = notified.clone();
+            move || Box::new(Listener { client, notified }) as _
+        };
+        let system = system_builder()
+            .protection_domain("pl011_driver", || Box::new(Absent))
+            .protection_domain("storage", || Box::new(Absent))
+            .protection_domain("timer", {
+                let clock = clock.clone();
+                move || Box::new(timer::init(clock))
+            })
+            .protection_domain("assistant", listener(Client::Assistant))
+            .protection_domain("artist", listener(Client::Artist))
+            .build();
+        Self {
+            system,
+            clock,
+            notified,
+        }
+    }
+
+    /// Runs `f` as `client`, with the client library on its channel to the
+    /// timer.
+    pub fn call<R>(&mut self, client: Client, f: impl FnOnce(&Timer) -> R) -> R {
+        self.system
+            .run_as(client.name(), || f(&Timer::new(channel(client))))
+    }
+
+    /// Moves time on by `duration`, and runs the system until the timer has
+    /// notified every client whose timeout expired.
+    pub fn advance(&mut self, duration: Duration) {
+        self.clock.advance(duration.as_nanos().try_into().unwrap());
+        self.system.irq(TIMER_IRQ);
+        self.system.run();
+    }
+
+    /// The clients notified since the last call, in order.
+    pub fn take_notified(&mut self) -> Vec<Client> {
+        std::mem::take(&mut self.notified.borrow_mut())
+    }
+}
+
+impl Default for IsolatedTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Stands in for a client, noting each notification from the timer
+struct Listener {
+    client: Client,
+    notified: Rc<RefCell<Vec<Client>>>,
+}
+
+impl Handler for Listener {
+    type Error = !;
+
+    fn notified(&mut self, from: Channel) -> Result<(), Self::Error> {
+        assert_eq!(from, channel(self.client));
+        self.notified.borrow_mut().push(self.client);
+        Ok(())
+    }
+}
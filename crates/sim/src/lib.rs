@@ -0,0 +1,193 @@
+//! Runs the Banscii system on the host, for testing without the seL4 Core
+//! Platform SDK or QEMU.
+//!
+//! The artist, assistant, and storage are compiled from their own sources
+//! against a mock `sel4cp` crate, and wired together according to
+//! `banscii.system`. The serial driver is replaced by a stand-in which
+//! connects the assistant to an in-memory console, and the timer by one whose
+//! clock only moves when a test moves it. Storage keeps its store on a RAM
+//! disk of the simulator's own, rather than the virtio-blk device it drives
+//! in QEMU.
+
+#![feature(const_trait_impl)]
+#![feature(int_roundings)]
+#![feature(never_type)]
+// The protection domains' crate-level attributes don't apply as modules
+#![allow(unused_attributes)]
+
+extern crate alloc;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use sel4cp::sim::{System, SystemBuilder};
+
+#[path = "../../artist/src/main.rs"]
+mod artist;
+
+#[path = "../../assistant/src/main.rs"]
+mod assistant;
+
+#[path = "../../storage/src/main.rs"]
+mod storage;
+
+mod isolated_artist;
+mod isolated_storage;
+mod isolated_timer;
+mod mock_storage;
+mod pl011_driver;
+mod tampering;
+mod timer;
+
+pub use isolated_artist::{IsolatedArtist, REGION_SIZE};
+pub use isolated_storage::{Client, IsolatedStorage};
+pub use isolated_timer::IsolatedTimer;
+pub use mock_storage::MockStorage;
+pub use timer::START_UNIX_SECONDS;
+
+use pl011_driver::Console;
+use tampering::Tampering;
+use timer::Clock;
+
+const SYSTEM_DESCRIPTION: &str = include_str!("../../../banscii.system");
+
+// The UART's interrupt, as claimed by the driver in banscii.system
+const PL011_IRQ: u64 = 33;
+
+// The generic timer's interrupt, as claimed by the timer
+const TIMER_IRQ: u64 = 30;
+
+// Where the storage protection domain keeps its store, built without the
+// virtio-blk feature, and how large it is, as in crates/storage/src/main.rs
+const RAM_DISK: &str = "storage_ram_disk";
+const RAM_DISK_SIZE: usize = 0x10_000;
+
+pub struct Banscii {
+    system: System,
+    console: Rc<RefCell<Console>>,
+    tamper: Rc<Cell<bool>>,
+    clock: Clock,
+}
+
+impl Banscii {
+    pub fn new() -> Self {
+        let console = Rc::new(RefCell::new(Console::default()));
+        let tamper = Rc::new(Cell::new(false));
+        let clock = Clock::default();
+        let system = boot(&console, &tamper, &clock, None);
+        Self {
+            system,
+            console,
+            tamper,
+            clock,
+        }
+    }
+
+    /// Starts the system afresh, as though it had been rebooted, keeping the
+    /// RAM disk standing in for storage's disk. Everything else is lost, along
+    /// with any console output not yet taken, but for the real-time clock.
+    pub fn restart(&mut self) {
+        let ram_disk = self.system.memory_region(RAM_DISK);
+        self.console.borrow_mut().output.clear();
+        self.system = boot(&self.console, &self.tamper, &self.clock, Some(ram_disk));
+    }
+
+    /// Moves time on by `duration`, which otherwise stands still from
+    /// [`START_UNIX_SECONDS`], and runs the system until it has dealt with
+    /// any timeouts expiring.
+    pub fn advance_time(&mut self, duration: Duration) {
+        self.clock.advance(duration.as_nanos().try_into().unwrap());
+        self.system.irq(TIMER_IRQ);
+        self.system.run();
+    }
+
+    /// Flips a bit of the next masterpiece after the artist has signed it, as
+    /// though something else could write to the region between the artist
+    /// and the assistant.
+    pub fn tamper_with_next_work(&mut self) {
+        self.tamper.set(true);
+    }
+
+    /// Types `input` at the console, and runs the system until it has dealt
+    /// with all of it.
+    pub fn type_bytes(&mut self, input: &[u8]) {
+        self.console.borrow_mut().input.extend(input);
+        loop {
+            let remaining = self.console.borrow().input.len();
+            if remaining == 0 {
+                break;
+            }
+            self.system.irq(PL011_IRQ);
+            self.system.run();
+            assert!(
+                self.console.borrow().input.len() < remaining,
+                "the serial driver stopped accepting input",
+            );
+        }
+    }
+
+    /// Types `line` followed by a carriage return, and returns everything
+    /// printed in response, starting with the echo of `line`.
+    pub fn enter(&mut self, line: &str) -> String {
+        self.take_output();
+        self.type_bytes(line.as_bytes());
+        self.type_bytes(b"\r");
+        self.take_output()
+    }
+
+    /// Everything printed to the console since the last call.
+    pub fn take_output(&mut self) -> String {
+        String::from_utf8(self.take_output_bytes()).expect("console output is not UTF-8")
+    }
+
+    /// As [`Self::take_output`], for when there may be binary output such as
+    /// a CBOR record.
+    pub fn take_output_bytes(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.console.borrow_mut().output)
+    }
+}
+
+fn boot(
+    console: &Rc<RefCell<Console>>,
+    tamper: &Rc<Cell<bool>>,
+    clock: &Clock,
+    ram_disk: Option<Vec<u8>>,
+) -> System {
+    let mut builder = system_builder()
+        .protection_domain("pl011_driver", {
+            let console = console.clone();
+            move || Box::new(pl011_driver::init(console))
+        })
+        .protection_domain("storage", storage::sim_init)
+        .protection_domain("timer", {
+            let clock = clock.clone();
+            move || Box::new(timer::init(clock))
+        })
+        .protection_domain("assistant", assistant::sim_init)
+        .protection_domain("artist", {
+            let tamper = tamper.clone();
+            move || Box::new(Tampering::new(artist::sim_init(), tamper))
+        });
+    if let Some(ram_disk) = ram_disk {
+        builder = builder.memory_region(RAM_DISK, ram_disk);
+    }
+    builder.build()
+}
+
+// The system as described, but for the RAM disk standing in for storage's
+// disk
+fn system_builder() -> SystemBuilder {
+    SystemBuilder::new(SYSTEM_DESCRIPTION).stand_in_region(
+        RAM_DISK,
+        RAM_DISK_SIZE,
+        "storage",
+        "ram_disk_start",
+    )
+}
+
+impl Default for Banscii {
+    fn default() -> Self {
+        Self::new()
+    }
+}
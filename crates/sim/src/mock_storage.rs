@@ -0,0 +1,113 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use sel4cp::memory_region::{memory_region_symbol, ExternallySharedRef};
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use sel4cp::{Channel, Handler};
+
+use banscii_storage_interface_types::*;
+
+/// Stands in for the storage protection domain when the artist is run alone,
+/// keeping the artist's entries where a test can read and rewrite them behind
+/// its back. Clones share the same entries, so they outlive the artist.
+///
+/// Only serves the requests the artist makes, and trusts it to make them
+/// properly.
+#[derive(Clone, Default)]
+pub struct MockStorage {
+    entries: Rc<RefCell<BTreeMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl MockStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries.borrow().get(key).cloned()
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) {
+        self.entries
+            .borrow_mut()
+            .insert(key.to_vec(), value.to_vec());
+    }
+
+    pub fn delete(&self, key: &[u8]) {
+        self.entries.borrow_mut().remove(key);
+    }
+
+    /// A copy of every entry, to be put back later with [`Self::restore`].
+    pub fn snapshot(&self) -> BTreeMap<Vec<u8>, Vec<u8>> {
+        self.entries.borrow().clone()
+    }
+
+    pub fn restore(&self, snapshot: BTreeMap<Vec<u8>, Vec<u8>>) {
+        *self.entries.borrow_mut() = snapshot;
+    }
+}
+
+impl Handler for MockStorage {
+    type Error = !;
+
+    fn protected(
+        &mut self,
+        _channel: Channel,
+        msg_info: MessageInfo,
+    ) -> Result<MessageInfo, Self::Error> {
+        // Running as storage, so this is its end of the artist's region
+        // SAFETY: the simulated regions live as long as the system
+        let mut region = unsafe {
+            ExternallySharedRef::<[u8]>::new(memory_region_symbol!(
+                artist_region_start: *mut [u8],
+                n = REGION_SIZE
+            ))
+        };
+        let read =
+            |start: usize, size: usize| region.as_ptr().index(start..start + size).copy_to_vec();
+
+        Ok(match msg_info.label().try_into().ok() {
+            Some(RequestTag::Get) => {
+                let req = msg_info.recv::<GetRequest>().unwrap();
+                match self.get(&read(req.key_start, req.key_size)) {
+                    Some(value) => {
+                        region
+                            .as_mut_ptr()
+                            .index(0..value.len())
+                            .copy_from_slice(&value);
+                        MessageInfo::send(
+                            StatusMessageLabel::Ok,
+                            GetResponse {
+                                value_start: 0,
+                                value_size: value.len(),
+                            },
+                        )
+                    }
+                    None => error(StorageError::NotFound),
+                }
+            }
+            Some(RequestTag::Put) => {
+                let req = msg_info.recv::<PutRequest>().unwrap();
+                self.put(
+                    &read(req.key_start, req.key_size),
+                    &read(req.value_start, req.value_size),
+                );
+                MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue)
+            }
+            Some(RequestTag::Delete) => {
+                let req = msg_info.recv::<DeleteRequest>().unwrap();
+                self.delete(&read(req.key_start, req.key_size));
+                MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue)
+            }
+            _ => error(StorageError::BadRequest),
+        })
+    }
+}
+
+fn error(err: StorageError) -> MessageInfo {
+    MessageInfo::send(
+        StatusMessageLabel::Error,
+        ErrorResponse { error: err.into() },
+    )
+}
@@ -0,0 +1,96 @@
+//! Stands in for `banscii-pl011-driver`, whose device can't be emulated with
+//! plain memory. The handler mirrors the real one, but reads from and writes
+//! to a [`Console`] instead of the UART.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use sel4cp::{Channel, Handler};
+
+use banscii_pl011_driver_interface_types::*;
+
+const DEVICE: Channel = Channel::new(0);
+const ASSISTANT: Channel = Channel::new(1);
+
+// Matches the real driver's buffer
+const BUFFER_SIZE: usize = 256;
+
+#[derive(Default)]
+pub(crate) struct Console {
+    pub(crate) input: VecDeque<u8>,
+    pub(crate) output: Vec<u8>,
+}
+
+pub(crate) fn init(console: Rc<RefCell<Console>>) -> ThisHandler {
+    ThisHandler {
+        console,
+        buffer: VecDeque::with_capacity(BUFFER_SIZE),
+        notify: true,
+    }
+}
+
+pub(crate) struct ThisHandler {
+    console: Rc<RefCell<Console>>,
+    buffer: VecDeque<u8>,
+    notify: bool,
+}
+
+impl Handler for ThisHandler {
+    type Error = !;
+
+    fn notified(&mut self, channel: Channel) -> Result<(), Self::Error> {
+        match channel {
+            DEVICE => {
+                let mut console = self.console.borrow_mut();
+                while self.buffer.len() < BUFFER_SIZE {
+                    match console.input.pop_front() {
+                        Some(c) => self.buffer.push_back(c),
+                        None => break,
+                    }
+                }
+                DEVICE.irq_ack().unwrap();
+                if self.notify {
+                    ASSISTANT.notify();
+                    self.notify = false;
+                }
+            }
+            _ => {
+                unreachable!()
+            }
+        }
+        Ok(())
+    }
+
+    fn protected(
+        &mut self,
+        channel: Channel,
+        msg_info: MessageInfo,
+    ) -> Result<MessageInfo, Self::Error> {
+        Ok(match channel {
+            ASSISTANT => match msg_info.label().try_into().ok() {
+                Some(RequestTag::PutChar) => match msg_info.recv() {
+                    Ok(PutCharRequest { val }) => {
+                        self.console.borrow_mut().output.push(val);
+                        MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue)
+                    }
+                    Err(_) => MessageInfo::send(StatusMessageLabel::Error, NoMessageValue),
+                },
+                Some(RequestTag::GetChar) => match self.buffer.pop_front() {
+                    Some(val) => {
+                        MessageInfo::send(GetCharResponseTag::Some, GetCharSomeResponse { val })
+                    }
+                    None => {
+                        self.notify = true;
+                        MessageInfo::send(GetCharResponseTag::None, NoMessageValue)
+                    }
+                },
+                None => MessageInfo::send(StatusMessageLabel::Error, NoMessageValue),
+            },
+            _ => {
+                unreachable!()
+            }
+        })
+    }
+}
@@ -0,0 +1,55 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use sel4cp::memory_region::{memory_region_symbol, ExternallySharedRef};
+use sel4cp::message::MessageInfo;
+use sel4cp::{Channel, DynHandler};
+
+use banscii_artist_interface_types::{CompleteResponse, RequestTag};
+
+use crate::REGION_SIZE;
+
+/// Wraps the artist, and once armed, damages the next masterpiece it
+/// completes on its way out through the artist's outgoing region.
+pub(crate) struct Tampering {
+    artist: Box<dyn DynHandler>,
+    armed: Rc<Cell<bool>>,
+}
+
+impl Tampering {
+    pub(crate) fn new(artist: Box<dyn DynHandler>, armed: Rc<Cell<bool>>) -> Self {
+        Self { artist, armed }
+    }
+}
+
+impl DynHandler for Tampering {
+    fn notified(&mut self, channel: Channel) {
+        self.artist.notified(channel)
+    }
+
+    fn protected(&mut self, channel: Channel, msg_info: MessageInfo) -> MessageInfo {
+        let is_complete = msg_info.label() == RequestTag::Complete.into();
+        let response = self.artist.protected(channel, msg_info);
+        if !is_complete || !self.armed.replace(false) {
+            return response;
+        }
+        let Ok(work) = response.recv::<CompleteResponse>() else {
+            return response;
+        };
+
+        // Still running as the artist, so this is its outgoing region
+        // SAFETY: the simulated regions live as long as the system
+        let mut region = unsafe {
+            ExternallySharedRef::<[u8]>::new(memory_region_symbol!(
+                region_out_start: *mut [u8],
+                n = REGION_SIZE
+            ))
+        };
+        let at = work.masterpiece_start..work.masterpiece_start + 1;
+        let mut byte = [0];
+        region.as_ptr().index(at.clone()).copy_into_slice(&mut byte);
+        byte[0] ^= 0x01;
+        region.as_mut_ptr().index(at).copy_from_slice(&byte);
+        response
+    }
+}
@@ -0,0 +1,106 @@
+//! Stands in for `banscii-timer`, whose hardware can't be emulated with plain
+//! memory. The handler mirrors the real one, but reads a [`Clock`] which only
+//! moves when a test moves it.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use sel4cp::{Channel, Handler};
+
+use banscii_timer_core::Timeouts;
+use banscii_timer_interface_types::*;
+
+const DEVICE: Channel = Channel::new(0);
+const ARTIST: Channel = Channel::new(1);
+const ASSISTANT: Channel = Channel::new(2);
+
+const CLIENTS: [Channel; 2] = [ARTIST, ASSISTANT];
+
+/// What the real-time clock reads when a [`Clock`] starts: 2023-11-14
+/// 22:13:20 UTC.
+pub const START_UNIX_SECONDS: u64 = 1_700_000_000;
+
+/// Time as the simulated timer tells it. Clones share the same time, which
+/// carries on across a restart.
+#[derive(Clone, Default)]
+pub(crate) struct Clock {
+    nanos: Rc<Cell<u64>>,
+}
+
+impl Clock {
+    pub(crate) fn advance(&self, nanos: u64) {
+        self.nanos.set(self.nanos.get() + nanos);
+    }
+
+    fn nanos(&self) -> u64 {
+        self.nanos.get()
+    }
+
+    fn unix_seconds(&self) -> u64 {
+        START_UNIX_SECONDS + self.nanos() / NANOS_PER_SEC
+    }
+}
+
+pub(crate) fn init(clock: Clock) -> ThisHandler {
+    ThisHandler {
+        clock,
+        timeouts: Timeouts::new(),
+    }
+}
+
+pub(crate) struct ThisHandler {
+    clock: Clock,
+    timeouts: Timeouts<{ CLIENTS.len() }>,
+}
+
+impl Handler for ThisHandler {
+    type Error = !;
+
+    // Raised whenever the clock moves, whether or not a timeout is due
+    fn notified(&mut self, channel: Channel) -> Result<(), Self::Error> {
+        match channel {
+            DEVICE => {
+                self.timeouts
+                    .expire(self.clock.nanos(), |client| CLIENTS[client].notify());
+                DEVICE.irq_ack().unwrap();
+            }
+            _ => {
+                unreachable!()
+            }
+        }
+        Ok(())
+    }
+
+    fn protected(
+        &mut self,
+        channel: Channel,
+        msg_info: MessageInfo,
+    ) -> Result<MessageInfo, Self::Error> {
+        let Some(client) = CLIENTS.iter().position(|c| *c == channel) else {
+            unreachable!()
+        };
+        Ok(match msg_info.label().try_into().ok() {
+            Some(RequestTag::Now) => MessageInfo::send(
+                StatusMessageLabel::Ok,
+                NowResponse {
+                    nanos: self.clock.nanos(),
+                    unix_seconds: self.clock.unix_seconds(),
+                },
+            ),
+            Some(RequestTag::SetTimeout) => match msg_info.recv::<SetTimeoutRequest>() {
+                Ok(req) if req.period == 0 || req.period >= MIN_PERIOD => {
+                    self.timeouts
+                        .set(client, self.clock.nanos(), req.after, req.period);
+                    MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue)
+                }
+                _ => MessageInfo::send(StatusMessageLabel::Error, NoMessageValue),
+            },
+            Some(RequestTag::CancelTimeout) => {
+                self.timeouts.cancel(client);
+                MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue)
+            }
+            None => MessageInfo::send(StatusMessageLabel::Error, NoMessageValue),
+        })
+    }
+}
@@ -0,0 +1,451 @@
+use std::time::Duration;
+
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use zerocopy::AsBytes;
+
+use banscii_artist_interface_types::*;
+use banscii_sim::{IsolatedArtist, MockStorage, REGION_SIZE, START_UNIX_SECONDS};
+
+fn complete(artist: &mut IsolatedArtist, req: CompleteRequest, region_in: &[u8]) -> MessageInfo {
+    artist
+        .call(MessageInfo::send(RequestTag::Complete, req), region_in)
+        .0
+}
+
+fn is_ok(response: &MessageInfo) -> bool {
+    response.label() == StatusMessageLabel::Ok.into()
+}
+
+fn rejection(response: &MessageInfo) -> RejectReason {
+    let resp = response.recv::<RejectResponse>().unwrap();
+    RejectReason::try_from(resp.reason).unwrap()
+}
+
+fn get_stats(artist: &mut IsolatedArtist) -> GetStatsResponse {
+    let (response, _) = artist.call(MessageInfo::send(RequestTag::GetStats, NoMessageValue), &[]);
+    response.recv().unwrap()
+}
+
+// Lets time pass until the rate limiter's bucket is full again
+fn refill(artist: &mut IsolatedArtist) {
+    let burst = get_stats(artist).burst;
+    while get_stats(artist).tokens < burst {
+        artist.advance_time(Duration::from_secs(1));
+    }
+}
+
+// The artist's sealed record in storage
+const STATE_KEY: &[u8] = b"state";
+
+fn audit_key(serial: u64) -> Vec<u8> {
+    [&b"audit/"[..], &serial.to_be_bytes()].concat()
+}
+
+fn get_audit_log(artist: &mut IsolatedArtist, after: usize) -> (MessageInfo, Vec<AuditEntry>) {
+    let req = GetAuditLogRequest { after };
+    let (response, region_out) = artist.call(MessageInfo::send(RequestTag::GetAuditLog, req), &[]);
+    let mut entries = vec![];
+    if let Ok(resp) = response.recv::<GetAuditLogResponse>() {
+        let bytes = &region_out[resp.entries_start..][..resp.count * AUDIT_ENTRY_SIZE];
+        for entry in bytes.chunks(AUDIT_ENTRY_SIZE) {
+            entries.push(AuditEntry::from_bytes(entry.try_into().unwrap()));
+        }
+    }
+    (response, entries)
+}
+
+const SMALL_DRAFT: CompleteRequest = CompleteRequest {
+    height: 1,
+    width: 2,
+    draft_start: 0,
+    draft_size: 2,
+};
+
+#[test]
+fn complete_within_region() {
+    let mut artist = IsolatedArtist::new();
+    let req = CompleteRequest {
+        height: 2,
+        width: 3,
+        draft_start: 10,
+        draft_size: 6,
+    };
+    let mut region_in = vec![0; 16];
+    region_in[10..].copy_from_slice(&[0, 50, 100, 150, 200, 255]);
+    let (response, region_out) =
+        artist.call(MessageInfo::send(RequestTag::Complete, req), &region_in);
+    assert!(is_ok(&response));
+    let resp = response.recv::<CompleteResponse>().unwrap();
+    let masterpiece = &region_out[resp.masterpiece_start..][..resp.masterpiece_size];
+    assert_eq!((resp.height, resp.width), (2, 3));
+    assert_eq!(masterpiece, b"@%x=- ");
+    assert_eq!((resp.serial, resp.edition), (1, 1));
+
+    let (response, _) = artist.call(MessageInfo::send(RequestTag::Complete, req), &region_in);
+    let resp = response.recv::<CompleteResponse>().unwrap();
+    assert_eq!((resp.serial, resp.edition), (2, 2));
+}
+
+#[test]
+fn editions_sell_out() {
+    let mut artist = IsolatedArtist::new();
+    let req = CompleteRequest {
+        height: 1,
+        width: 2,
+        draft_start: 0,
+        draft_size: 2,
+    };
+    let mut edition_size = None;
+    for edition in 1.. {
+        let response = complete(&mut artist, req, b"ab");
+        if !is_ok(&response) {
+            assert_eq!(rejection(&response), RejectReason::SoldOut);
+            assert_eq!(edition_size, Some(edition - 1));
+            break;
+        }
+        let resp = response.recv::<CompleteResponse>().unwrap();
+        assert_eq!((resp.serial, resp.edition), (edition, edition));
+        edition_size = Some(resp.edition_size);
+    }
+
+    // Another draft has editions of its own, and the serial number carries on
+    // from the last work completed
+    let response = complete(&mut artist, req, b"ba");
+    let resp = response.recv::<CompleteResponse>().unwrap();
+    assert_eq!(resp.edition, 1);
+    assert_eq!(Some(resp.serial), edition_size.map(|size| size + 1));
+}
+
+#[test]
+fn complete_rejects_out_of_bounds_draft() {
+    let mut artist = IsolatedArtist::new();
+    for (draft_start, draft_size) in [
+        (REGION_SIZE, 1),
+        (REGION_SIZE - 1, 2),
+        (usize::MAX, 2),
+        (1, usize::MAX),
+    ] {
+        let req = CompleteRequest {
+            height: 1,
+            width: draft_size,
+            draft_start,
+            draft_size,
+        };
+        let response = complete(&mut artist, req, &[]);
+        assert_eq!(rejection(&response), RejectReason::BadRequest);
+    }
+}
+
+#[test]
+fn complete_rejects_inconsistent_dimensions() {
+    let mut artist = IsolatedArtist::new();
+    for (height, width) in [(2, 3), (1, 5), (usize::MAX, 2), (0, 4)] {
+        let req = CompleteRequest {
+            height,
+            width,
+            draft_start: 0,
+            draft_size: 4,
+        };
+        assert!(!is_ok(&complete(&mut artist, req, &[])));
+    }
+}
+
+#[test]
+fn complete_rejects_draft_leaving_no_room_for_signature() {
+    let mut artist = IsolatedArtist::new();
+    let req = CompleteRequest {
+        height: 1,
+        width: REGION_SIZE,
+        draft_start: 0,
+        draft_size: REGION_SIZE,
+    };
+    assert!(!is_ok(&complete(&mut artist, req, &[])));
+}
+
+#[test]
+fn timestamp_is_signed() {
+    let mut artist = IsolatedArtist::new();
+    let (response, region_out) =
+        artist.call(MessageInfo::send(RequestTag::Complete, SMALL_DRAFT), b"ab");
+    let resp = response.recv::<CompleteResponse>().unwrap();
+    assert_eq!(resp.timestamp, START_UNIX_SECONDS);
+
+    // Handed back as the artist gave them, the masterpiece then the signature
+    let region_in = &region_out[..resp.signature_start + resp.signature_size];
+    let verify = |artist: &mut IsolatedArtist, timestamp| {
+        let req = VerifyRequest {
+            masterpiece_start: resp.masterpiece_start,
+            masterpiece_size: resp.masterpiece_size,
+            signature_start: resp.signature_start,
+            signature_size: resp.signature_size,
+            serial: resp.serial,
+            edition: resp.edition,
+            timestamp,
+            chain_head: resp.chain_head,
+        };
+        let (response, _) = artist.call(MessageInfo::send(RequestTag::Verify, req), region_in);
+        response.recv::<VerifyResponse>().unwrap().valid != 0
+    };
+    assert!(verify(&mut artist, resp.timestamp));
+    assert!(!verify(&mut artist, resp.timestamp + 1));
+}
+
+#[test]
+fn verify_rejects_out_of_bounds_spans() {
+    let mut artist = IsolatedArtist::new();
+    let req = VerifyRequest {
+        masterpiece_start: 0,
+        masterpiece_size: 4,
+        signature_start: REGION_SIZE - 4,
+        signature_size: 8,
+        serial: 1,
+        edition: 1,
+        timestamp: 0,
+        chain_head: Chain::START.head,
+    };
+    let (response, _) = artist.call(MessageInfo::send(RequestTag::Verify, req), &[]);
+    assert!(!is_ok(&response));
+}
+
+#[test]
+fn malformed_messages_are_rejected() {
+    let mut artist = IsolatedArtist::new();
+    let req = CompleteRequest {
+        height: 1,
+        width: 1,
+        draft_start: 0,
+        draft_size: 1,
+    };
+    let truncated = &req.as_bytes()[1..];
+    for (label, bytes) in [
+        (u64::from(RequestTag::Complete), truncated),
+        (u64::from(RequestTag::Verify), &[][..]),
+        (u64::MAX, req.as_bytes()),
+    ] {
+        let (response, _) = artist.call(MessageInfo::from_raw(label, bytes), &[]);
+        assert!(!is_ok(&response));
+    }
+}
+
+#[test]
+fn state_survives_restart() {
+    let storage = MockStorage::new();
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+
+    let mut artist = IsolatedArtist::with_storage(storage);
+    let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+    let resp = response.recv::<CompleteResponse>().unwrap();
+    assert_eq!((resp.serial, resp.edition), (3, 3));
+}
+
+#[test]
+fn tampered_state_is_refused() {
+    let storage = MockStorage::new();
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+
+    let sealed = storage.get(STATE_KEY).unwrap();
+    // Flip each bit of the record in turn, from the version to the MAC
+    for bit in 0..sealed.len() * 8 {
+        let mut tampered = sealed.clone();
+        tampered[bit / 8] ^= 1 << (bit % 8);
+        storage.put(STATE_KEY, &tampered);
+        refill(&mut artist);
+        let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+        assert_eq!(rejection(&response), RejectReason::Tampered);
+    }
+
+    // Even from an artist which has never seen it before
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+    assert_eq!(rejection(&response), RejectReason::Tampered);
+
+    storage.put(STATE_KEY, &sealed);
+    let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+    let resp = response.recv::<CompleteResponse>().unwrap();
+    assert_eq!((resp.serial, resp.edition), (2, 2));
+}
+
+#[test]
+fn rolled_back_state_is_refused_until_resolved() {
+    let storage = MockStorage::new();
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+    let earlier = storage.snapshot();
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+    let latest = storage.snapshot();
+
+    // Putting back an earlier record would let an edition be sold twice
+    storage.restore(earlier);
+    for _ in 0..2 {
+        let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+        assert_eq!(rejection(&response), RejectReason::RolledBack);
+    }
+
+    storage.restore(latest);
+    let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+    let resp = response.recv::<CompleteResponse>().unwrap();
+    assert_eq!((resp.serial, resp.edition), (3, 3));
+}
+
+#[test]
+fn deleted_state_is_refused() {
+    let storage = MockStorage::new();
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+
+    storage.delete(STATE_KEY);
+    let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+    assert_eq!(rejection(&response), RejectReason::RolledBack);
+}
+
+#[test]
+fn rolled_back_editions_are_refused() {
+    let storage = MockStorage::new();
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+    let earlier = storage.snapshot();
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+
+    // Putting back only the earlier count for the draft would let its second
+    // edition be sold twice
+    for (key, value) in earlier {
+        if key.starts_with(b"editions/") {
+            storage.put(&key, &value);
+        }
+    }
+    let response = complete(&mut artist, SMALL_DRAFT, b"ab");
+    assert_eq!(rejection(&response), RejectReason::RolledBack);
+}
+
+#[test]
+fn many_drafts_are_counted() {
+    let storage = MockStorage::new();
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    // More drafts than there's room to count in any one value in storage
+    let drafts = (0..128).map(|i| [b'a', i]);
+    for draft in drafts.clone() {
+        refill(&mut artist);
+        let response = complete(&mut artist, SMALL_DRAFT, &draft);
+        assert_eq!(response.recv::<CompleteResponse>().unwrap().edition, 1);
+    }
+
+    let mut artist = IsolatedArtist::with_storage(storage);
+    for draft in drafts {
+        refill(&mut artist);
+        let response = complete(&mut artist, SMALL_DRAFT, &draft);
+        assert_eq!(response.recv::<CompleteResponse>().unwrap().edition, 2);
+    }
+}
+
+#[test]
+fn audit_log_follows_completed_works() {
+    let mut artist = IsolatedArtist::new();
+    let (response, entries) = get_audit_log(&mut artist, 0);
+    assert!(is_ok(&response) && entries.is_empty());
+
+    let mut heads = vec![];
+    for draft in [b"ab", b"ab", b"ba"] {
+        let response = complete(&mut artist, SMALL_DRAFT, draft);
+        heads.push(response.recv::<CompleteResponse>().unwrap().chain_head);
+    }
+
+    let (response, entries) = get_audit_log(&mut artist, 0);
+    assert_eq!(response.recv::<GetAuditLogResponse>().unwrap().more, 0);
+    let mut chain = Chain::START;
+    for (entry, head) in entries.iter().zip(&heads) {
+        // Each work was handed out with the head it extends
+        assert_eq!(entry.previous, *head);
+        chain.extend(entry).unwrap();
+    }
+    assert_eq!(chain.serial, 3);
+
+    let (_, entries) = get_audit_log(&mut artist, 2);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].serial, 3);
+    let (_, entries) = get_audit_log(&mut artist, 3);
+    assert!(entries.is_empty());
+}
+
+#[test]
+fn audit_log_never_fills_storage() {
+    // Far more works than there's room in storage for entries of
+    let mut artist = IsolatedArtist::new();
+    let works = 400;
+    let mut heads = vec![];
+    for i in 0..works {
+        refill(&mut artist);
+        let draft = [b'a', (i / 10) as u8];
+        let response = complete(&mut artist, SMALL_DRAFT, &draft);
+        heads.push(response.recv::<CompleteResponse>().unwrap().chain_head);
+    }
+
+    // Only the latest entries are kept, and they still follow on from one
+    // another
+    let (response, entries) = get_audit_log(&mut artist, 0);
+    let resp = response.recv::<GetAuditLogResponse>().unwrap();
+    assert_eq!((resp.first, resp.more), (works - 63, 0));
+    assert_eq!(entries.len(), 64);
+    let mut chain = Chain::before(&entries[0]).unwrap();
+    for entry in &entries {
+        assert_eq!(entry.previous, heads[entry.serial - 1]);
+        chain.extend(entry).unwrap();
+    }
+    assert_eq!(chain.serial, works);
+
+    // Asking for what's gone starts from the oldest entry kept
+    let (_, later) = get_audit_log(&mut artist, 100);
+    assert_eq!(later, entries);
+}
+
+#[test]
+fn missing_audit_entry_is_refused() {
+    let storage = MockStorage::new();
+    let mut artist = IsolatedArtist::with_storage(storage.clone());
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+    assert!(is_ok(&complete(&mut artist, SMALL_DRAFT, b"ab")));
+
+    storage.delete(&audit_key(1));
+    let (response, _) = get_audit_log(&mut artist, 0);
+    assert_eq!(rejection(&response), RejectReason::Tampered);
+    // Entries after the missing one can still be read
+    let (response, entries) = get_audit_log(&mut artist, 1);
+    assert!(is_ok(&response));
+    assert_eq!(entries.len(), 1);
+}
+
+#[test]
+fn tight_loop_is_rate_limited() {
+    let mut artist = IsolatedArtist::new();
+    let burst = get_stats(&mut artist).burst;
+    let mut taken = 0;
+    let retry_after = loop {
+        let (response, _) = get_audit_log(&mut artist, 0);
+        if !is_ok(&response) {
+            assert_eq!(rejection(&response), RejectReason::RateLimited);
+            break response.recv::<RejectResponse>().unwrap().retry_after;
+        }
+        taken += 1;
+    };
+    // A full bucket, and no more, however fast the requests come
+    assert_eq!(taken, burst);
+    assert!(retry_after > 0);
+
+    // Requests which cost nothing don't put anything back
+    for _ in 0..burst * 4 {
+        get_stats(&mut artist);
+    }
+    let (response, _) = get_audit_log(&mut artist, 0);
+    assert_eq!(rejection(&response), RejectReason::RateLimited);
+
+    // Only time does
+    artist.advance_time(Duration::from_millis(retry_after as u64));
+    let (response, _) = get_audit_log(&mut artist, 0);
+    assert!(is_ok(&response));
+
+    let stats = get_stats(&mut artist);
+    assert_eq!((stats.rejected, stats.rate_limited), (2, 2));
+    assert!(stats.tokens < burst);
+}
@@ -0,0 +1,591 @@
+use std::time::Duration;
+
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
+use rsa::sha2::{Digest, Sha256};
+use rsa::signature::Verifier;
+use rsa::RsaPublicKey;
+
+use banscii_artist_interface_types::provenance;
+use banscii_assistant_core::base64;
+use banscii_assistant_core::output::parse_frame;
+use banscii_assistant_core::signature::dearmour;
+use banscii_sim::{Banscii, START_UNIX_SECONDS};
+use banscii_work::{check_chain, Work};
+
+fn public_key(banscii: &mut Banscii) -> RsaPublicKey {
+    let output = banscii.enter(":pubkey");
+    let end_marker = "-----END PUBLIC KEY-----\n";
+    let start = output.find("-----BEGIN PUBLIC KEY-----").unwrap();
+    let end = output.find(end_marker).unwrap() + end_marker.len();
+    RsaPublicKey::from_public_key_pem(&output[start..end]).unwrap()
+}
+
+// Whether `signature` is the artist's for a work
+fn is_signed(
+    verifying_key: &VerifyingKey<Sha256>,
+    pixel_data: &[u8],
+    serial: usize,
+    edition: usize,
+    timestamp: u64,
+    chain_head: &[u8],
+    signature: &[u8],
+) -> bool {
+    let Ok(chain_head) = chain_head.try_into() else {
+        return false;
+    };
+    let signed_data = [
+        pixel_data,
+        &provenance(serial, edition, timestamp, chain_head),
+    ]
+    .concat();
+    Signature::try_from(signature).map_or(false, |signature| {
+        verifying_key.verify(&signed_data, &signature).is_ok()
+    })
+}
+
+// Good enough for the flat objects that batches are reported in
+fn json_field<'a>(object: &'a str, key: &str) -> &'a str {
+    let start = object.find(&format!("\"{}\":", key)).unwrap() + key.len() + 3;
+    let value = &object[start..];
+    match value.strip_prefix('"') {
+        Some(string) => &string[..string.find('"').unwrap()],
+        None => &value[..value.find([',', '}']).unwrap()],
+    }
+}
+
+// From the "Chain: " line printed with a work, however its signature is
+fn chain_head(output: &str) -> Vec<u8> {
+    let line = output.lines().rfind(|line| line.starts_with("Chain: "));
+    hex::decode(&line.unwrap()["Chain: ".len()..]).unwrap()
+}
+
+#[test]
+fn prompt() {
+    let mut banscii = Banscii::new();
+    assert_eq!(banscii.take_output(), "banscii> ");
+}
+
+#[test]
+fn masterpiece_is_signed() {
+    let mut banscii = Banscii::new();
+    let output = banscii.enter("Hello, World!");
+    assert!(output.ends_with("banscii> "));
+
+    let work = Work::parse(&output).unwrap();
+    let width = work.masterpiece[0].len();
+    assert!(width > 0);
+    assert!(work.masterpiece.iter().all(|row| row.len() == width));
+    assert!(work
+        .masterpiece
+        .iter()
+        .any(|row| row.contains(|c| c != '@')));
+
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key(&mut banscii));
+    let signature = Signature::try_from(&work.signature[..]).unwrap();
+    assert!(verifying_key
+        .verify(&work.signed_data(), &signature)
+        .is_ok());
+
+    assert!(banscii.enter(":verify").contains("Signature is valid"));
+}
+
+#[test]
+fn input_split_across_interrupts() {
+    let mut whole = Banscii::new();
+    let expected = Work::parse(&whole.enter("Ünïcödé")).unwrap();
+
+    let mut split = Banscii::new();
+    for b in "Ünïcödé\r".bytes() {
+        split.type_bytes(&[b]);
+    }
+    assert_eq!(Work::parse(&split.take_output()).unwrap(), expected);
+}
+
+#[test]
+fn input_longer_than_driver_buffer() {
+    let mut banscii = Banscii::new();
+    let line = "x".repeat(1000);
+    let output = banscii.enter(&line);
+    assert!(output.contains("(subjects are limited to"));
+    let work = Work::parse(&output).unwrap();
+    assert!(!work.masterpiece.is_empty());
+}
+
+#[test]
+fn glyphs_are_cached_between_drafts() {
+    let mut banscii = Banscii::new();
+    let first = Work::parse(&banscii.enter("Hello")).unwrap();
+    let second = Work::parse(&banscii.enter("Hello")).unwrap();
+    assert_eq!(first.masterpiece, second.masterpiece);
+    let stats = banscii.enter(":stats");
+    assert!(stats.contains("5 hits, 5 misses"), "{}", stats);
+}
+
+#[test]
+fn pasted_image_is_signed() {
+    let mut banscii = Banscii::new();
+    let output = banscii.enter("-----BEGIN IMAGE-----");
+    assert!(!output.ends_with("banscii> "));
+    // An 8x4 PGM ramping from black to white, wrapped as by `base64 -w 32`
+    banscii.enter("UDUgOCA0IDI1NQoACBAYICgwOEBIUFhg");
+    banscii.enter("aHB4gIiQmKCosLjAyNDY4Ojw+A==");
+    let output = banscii.enter("-----END IMAGE-----");
+    assert!(output.ends_with("banscii> "));
+
+    let work = Work::parse(&output).unwrap();
+    assert_eq!(work.masterpiece.len(), 20);
+    assert!(work.masterpiece.iter().all(|row| row.len() == 80));
+    assert_ne!(work.masterpiece[0], work.masterpiece[19]);
+
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key(&mut banscii));
+    let signature = Signature::try_from(&work.signature[..]).unwrap();
+    assert!(verifying_key
+        .verify(&work.signed_data(), &signature)
+        .is_ok());
+}
+
+#[test]
+fn bad_paste_is_reported() {
+    let mut banscii = Banscii::new();
+    banscii.enter("-----BEGIN IMAGE-----");
+    banscii.enter("R0lGODlh");
+    let output = banscii.enter("-----END IMAGE-----");
+    assert!(output.contains("error: image is neither a PGM nor a PNG"));
+    assert!(output.ends_with("banscii> "));
+
+    banscii.enter("-----BEGIN IMAGE-----");
+    banscii.enter("UDUgOCA0");
+    banscii.type_bytes(&[0x03]);
+    assert!(banscii.take_output().contains("(paste cancelled)"));
+    assert!(Work::parse(&banscii.enter("Hello")).is_some());
+}
+
+#[test]
+fn paste_too_long_is_dropped() {
+    let mut banscii = Banscii::new();
+    banscii.enter("-----BEGIN IMAGE-----");
+    let line = "A".repeat(128);
+    for _ in 0..200 {
+        let output = banscii.enter(&line);
+        assert!(Work::parse(&output).is_none());
+        assert!(!output.contains("error"));
+    }
+    let output = banscii.enter("-----END IMAGE-----");
+    assert!(output.contains("error: image is too long to paste"));
+    assert!(output.ends_with("banscii> "));
+    assert!(banscii.enter(":stats").contains("completed = 0\n"));
+}
+
+#[test]
+fn batch_is_reported_as_json() {
+    let mut banscii = Banscii::new();
+    let expected = Work::parse(&banscii.enter("Hello")).unwrap();
+
+    let output = banscii.enter(":batch");
+    assert!(!output.ends_with("banscii> "));
+    banscii.enter("Hello");
+    banscii.enter("");
+    banscii.enter("World");
+    let output = banscii.enter(":end");
+    assert!(output.ends_with("banscii> "));
+
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key(&mut banscii));
+    let lines = output.lines().skip(1).take(3).collect::<Vec<_>>();
+    for (i, (line, subject)) in lines.iter().zip(["Hello", "World"]).enumerate() {
+        assert_eq!(json_field(line, "index"), i.to_string());
+        assert_eq!(json_field(line, "subject"), subject);
+        assert_eq!(json_field(line, "serial"), (i + 2).to_string());
+        let art = base64::decode(json_field(line, "art")).unwrap();
+        let width = json_field(line, "width").parse::<usize>().unwrap();
+        let height = json_field(line, "height").parse::<usize>().unwrap();
+        assert_eq!(art.len(), width * height);
+        let signature = base64::decode(json_field(line, "signature")).unwrap();
+        let serial = json_field(line, "serial").parse().unwrap();
+        let edition = json_field(line, "edition").parse().unwrap();
+        let timestamp = json_field(line, "timestamp").parse().unwrap();
+        let chain_head = hex::decode(json_field(line, "chain_head")).unwrap();
+        assert!(is_signed(
+            &verifying_key,
+            &art,
+            serial,
+            edition,
+            timestamp,
+            &chain_head,
+            &signature
+        ));
+    }
+    assert_eq!(
+        base64::decode(json_field(lines[0], "art")).unwrap(),
+        expected.pixel_data()
+    );
+    assert_eq!(lines[2], "{\"completed\":2,\"failed\":0}");
+
+    assert!(banscii.enter(":verify").contains("Signature is valid"));
+}
+
+#[test]
+fn batch_too_large_is_dropped() {
+    let mut banscii = Banscii::new();
+    banscii.enter(":batch");
+    let subject = "A".repeat(64);
+    for _ in 0..100 {
+        let output = banscii.enter(&subject);
+        assert!(Work::parse(&output).is_none());
+        assert!(!output.contains("error"));
+    }
+    let output = banscii.enter(":end");
+    assert_eq!(output.matches("error").count(), 1);
+    assert!(output.contains("error: too many subjects for one batch"));
+    assert!(!output.contains("unknown command"));
+    assert!(output.ends_with("banscii> "));
+    assert!(banscii.enter(":stats").contains("completed = 0\n"));
+}
+
+#[test]
+fn framed_records() {
+    let mut banscii = Banscii::new();
+    let expected = Work::parse(&banscii.enter("Hello")).unwrap();
+    let public_key = public_key(&mut banscii);
+    let key_fingerprint = hex::encode(Sha256::digest(public_key.to_public_key_der().unwrap()));
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key);
+
+    banscii.enter(":set output json");
+    banscii.type_bytes(b"Hello\r");
+    let output = banscii.take_output_bytes();
+    let (payload, rest) = parse_frame(&output).unwrap();
+    assert!(rest.ends_with(b"banscii> "));
+    let record = std::str::from_utf8(payload).unwrap();
+    assert_eq!(json_field(record, "subject"), "Hello");
+    assert_eq!(json_field(record, "algorithm"), "RSASSA-PKCS1-v1_5 SHA-256");
+    assert_eq!(json_field(record, "key_fingerprint"), key_fingerprint);
+    assert_eq!(json_field(record, "serial"), "2");
+    assert_eq!(json_field(record, "edition"), "2");
+    assert_eq!(json_field(record, "edition_size"), "10");
+    assert_eq!(
+        json_field(record, "timestamp"),
+        START_UNIX_SECONDS.to_string()
+    );
+    let art = base64::decode(json_field(record, "art")).unwrap();
+    assert_eq!(art, expected.pixel_data());
+    let signature = base64::decode(json_field(record, "signature")).unwrap();
+    let chain_head = hex::decode(json_field(record, "chain_head")).unwrap();
+    assert!(is_signed(
+        &verifying_key,
+        &art,
+        2,
+        2,
+        START_UNIX_SECONDS,
+        &chain_head,
+        &signature
+    ));
+
+    banscii.enter(":set output cbor");
+    banscii.type_bytes(b"Hello\r");
+    let output = banscii.take_output_bytes();
+    let (payload, _) = parse_frame(&output).unwrap();
+    // A map of twelve entries, the last of which is the signature
+    assert_eq!(payload[0], 0xac);
+    let signature = &payload[payload.len() - expected.signature.len()..];
+    // The text "chain_head", then 32 bytes
+    let key = b"\x6achain_head\x58\x20";
+    let at = payload.windows(key.len()).position(|w| w == key).unwrap() + key.len();
+    assert!(is_signed(
+        &verifying_key,
+        &expected.pixel_data(),
+        3,
+        3,
+        START_UNIX_SECONDS,
+        &payload[at..at + 32],
+        signature
+    ));
+
+    banscii.enter(":set output human");
+    let work = Work::parse(&banscii.enter("Hello")).unwrap();
+    assert_eq!(work.masterpiece, expected.masterpiece);
+    assert_eq!((work.serial, work.edition), (4, 4));
+}
+
+#[test]
+fn signature_encodings() {
+    let mut banscii = Banscii::new();
+    let expected = Work::parse(&banscii.enter("Hello")).unwrap();
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key(&mut banscii));
+    let pixel_data = expected.pixel_data();
+
+    banscii.enter(":set signature base64");
+    let output = banscii.enter("Hello");
+    let signature_line = output
+        .lines()
+        .position(|line| line == "Signature:")
+        .unwrap();
+    let base64 = output
+        .lines()
+        .skip(signature_line + 1)
+        .take_while(|line| !line.is_empty())
+        .collect::<String>();
+    let signature = base64::decode(&base64).unwrap();
+    assert!(is_signed(
+        &verifying_key,
+        &pixel_data,
+        2,
+        2,
+        START_UNIX_SECONDS,
+        &chain_head(&output),
+        &signature
+    ));
+
+    banscii.enter(":set signature armour");
+    let output = banscii.enter("Hello");
+    let armoured = dearmour(&output).unwrap();
+    assert_eq!((armoured.serial, armoured.edition), (Some(3), Some(3)));
+    let signature = &armoured.signature;
+    assert!(is_signed(
+        &verifying_key,
+        &pixel_data,
+        3,
+        3,
+        START_UNIX_SECONDS,
+        &chain_head(&output),
+        signature
+    ));
+
+    banscii.enter(":set signature qr");
+    let output = banscii.enter("Hello");
+    assert!(output.contains('▀'));
+    assert!(!output.contains("-----BEGIN"));
+
+    let output = banscii.enter(":set signature jpeg");
+    assert!(output.contains("error: signature must be"));
+    let settings = banscii.enter(":show settings");
+    assert!(settings.lines().any(|line| line == "signature = qr"));
+}
+
+#[test]
+fn width_too_large_is_refused() {
+    let mut banscii = Banscii::new();
+    let output = banscii.enter(":set width 18446744073709551615");
+    assert!(output.contains("error: width must be a number of pixels up to"));
+    // Narrow enough on its own, but not once it's a row of glyphs tall
+    let output = banscii.enter(":set width 15000");
+    assert!(output.contains("error: no subject would fit"));
+    let settings = banscii.enter(":show settings");
+    assert!(settings.lines().any(|line| line == "width = auto"));
+    assert!(Work::parse(&banscii.enter("Hi")).is_some());
+}
+
+#[test]
+fn tampering_is_detected() {
+    let mut banscii = Banscii::new();
+    let output = banscii.enter("Hello");
+    assert!(!output.contains("WARNING"));
+    assert!(banscii.enter(":verify").contains("Signature is valid"));
+
+    banscii.tamper_with_next_work();
+    let output = banscii.enter("Hello");
+    assert!(output.contains("* WARNING: signature is NOT valid for this work *"));
+    // Drawn as it arrived, with the first pixel altered
+    let work = Work::parse(&output).unwrap();
+    assert_ne!(work.masterpiece[0].as_bytes()[0], b' ');
+    assert!(banscii.enter(":verify").contains("NOT valid"));
+    assert!(banscii.enter(":verify artist").contains("NOT valid"));
+    // Nor is it hung in the gallery
+    assert!(banscii
+        .enter(":gallery show 2")
+        .contains("error: no work 2 in the gallery"));
+
+    // Only the next work is tampered with
+    assert!(banscii.enter(":verify").contains("NOT valid"));
+    assert!(!banscii.enter("Hello").contains("WARNING"));
+
+    banscii.enter(":set verify off");
+    banscii.tamper_with_next_work();
+    assert!(!banscii.enter("Hello").contains("WARNING"));
+    assert!(banscii.enter(":verify").contains("NOT valid"));
+}
+
+#[test]
+fn tampering_fails_a_batch_subject() {
+    let mut banscii = Banscii::new();
+    banscii.enter(":batch");
+    banscii.enter("Hi");
+    banscii.tamper_with_next_work();
+    let output = banscii.enter(":end");
+    assert!(output.contains("\"error\":\"signature is not valid for this work\""));
+    assert!(output.contains("{\"completed\":0,\"failed\":1}"));
+}
+
+#[test]
+fn storage_survives_restart() {
+    let mut banscii = Banscii::new();
+    banscii.enter("Hi");
+    banscii.enter("There");
+    let before = Work::parse(&banscii.enter("Hi")).unwrap();
+    assert_eq!((before.serial, before.edition), (3, 2));
+
+    banscii.restart();
+    assert_eq!(banscii.take_output(), "banscii> ");
+
+    // Numbering carries on, rather than selling editions again
+    let after = Work::parse(&banscii.enter("Hi")).unwrap();
+    assert_eq!((after.serial, after.edition), (4, 3));
+
+    let history = banscii.enter(":history");
+    let subjects = history
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(2))
+        .collect::<Vec<_>>();
+    assert_eq!(subjects, ["Hi", "There", "Hi", "Hi"]);
+    assert!(banscii.enter("!2").contains("There"));
+}
+
+#[test]
+fn gallery() {
+    let mut banscii = Banscii::new();
+    let hi = Work::parse(&banscii.enter("Hi")).unwrap();
+    let there = Work::parse(&banscii.enter("There")).unwrap();
+
+    let list = banscii.enter(":gallery list");
+    let lines = list.lines().skip(1).collect::<Vec<_>>();
+    assert!(lines[0].starts_with("    1  #1 (1 of 10)  ") && lines[0].ends_with("  Hi"));
+    assert!(lines[1].starts_with("    2  #2 (1 of 10)  ") && lines[1].ends_with("  There"));
+
+    assert_eq!(Work::parse(&banscii.enter(":gallery show 1")), Some(hi));
+
+    banscii.take_output();
+    banscii.type_bytes(b":gallery export 2\r");
+    let output = banscii.take_output_bytes();
+    let (payload, _) = parse_frame(&output).unwrap();
+    let record = std::str::from_utf8(payload).unwrap();
+    assert_eq!(json_field(record, "subject"), "There");
+    assert_eq!(json_field(record, "serial"), "2");
+    let art = base64::decode(json_field(record, "art")).unwrap();
+    assert_eq!(art, there.pixel_data());
+
+    assert!(!banscii.enter(":gallery drop 1").contains("error"));
+    assert!(banscii
+        .enter(":gallery show 1")
+        .contains("error: no work 1 in the gallery"));
+    let list = banscii.enter(":gallery list");
+    assert!(!list.contains("  Hi\n") && list.contains("  There\n"));
+    assert!(banscii
+        .enter(":gallery hang 2")
+        .contains("error: don't know how to 'hang' the gallery"));
+}
+
+#[test]
+fn audit_log() {
+    let mut banscii = Banscii::new();
+    let works = ["Hi", "There", "Hi"].map(|subject| Work::parse(&banscii.enter(subject)).unwrap());
+    assert_eq!(works[0].chain_head, [0; 32]);
+    assert_eq!(check_chain(&works), Ok(()));
+
+    let output = banscii.enter(":audit");
+    assert!(!output.contains("WARNING"));
+    let lines = output.lines().skip(1).collect::<Vec<_>>();
+    for (line, work) in lines.iter().zip(&works) {
+        let envelope_hash = hex::encode(Sha256::digest(work.signed_data()));
+        assert_eq!(*line, format!("{:>5}  {}", work.serial, envelope_hash));
+    }
+    let head = hex::encode(works[2].audit_entry().hash());
+    assert_eq!(lines[3], format!("3 works logged, chain head {}", head));
+
+    // Out of order, or with one missing, they don't follow on
+    assert!(check_chain(&[works[1].clone(), works[0].clone()]).is_err());
+    assert!(check_chain(&[works[0].clone(), works[2].clone()]).is_err());
+    // Nor does a work claiming to come before the first
+    let mut zeroth = works[0].clone();
+    zeroth.serial = 0;
+    assert!(check_chain(&[zeroth]).is_err());
+
+    // What was logged isn't what reached the gallery, which only happens when
+    // works aren't checked as they arrive
+    banscii.enter(":set verify off");
+    banscii.tamper_with_next_work();
+    banscii.enter("Hi");
+    assert!(banscii
+        .enter(":audit")
+        .contains("WARNING: work 4 in the gallery is not the one logged"));
+}
+
+#[test]
+fn audit_starts_from_the_oldest_entry_kept() {
+    let mut banscii = Banscii::new();
+    banscii.enter(":batch");
+    for i in 0..70 {
+        banscii.enter(&format!("{}", i));
+    }
+    let mut output = banscii.enter(":end");
+    while !output.contains("{\"completed\":70,") {
+        banscii.advance_time(Duration::from_secs(1));
+        output += &banscii.take_output();
+    }
+
+    banscii.advance_time(Duration::from_secs(1));
+    let output = banscii.enter(":audit");
+    assert!(!output.contains("WARNING"));
+    let lines = output.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(lines[0], "works before 7 are no longer logged");
+    assert!(lines[1].starts_with("    7  "));
+    assert!(lines[64].starts_with("   70  "));
+    assert!(lines[65].starts_with("70 works logged, chain head "));
+}
+
+#[test]
+fn long_batch_waits_for_the_artist() {
+    let mut banscii = Banscii::new();
+    banscii.enter(":batch");
+    for i in 0..30 {
+        banscii.enter(&format!("{}", i));
+    }
+    // The artist takes a burst, and then the batch waits until it will take
+    // more, rather than fail the rest
+    let mut output = banscii.enter(":end");
+    assert!(!output.contains("\"error\""));
+    assert!(!output.contains("{\"completed\":"));
+    assert!(!output.ends_with("banscii> "));
+
+    // Anything typed meanwhile waits for the batch, by which time the artist
+    // is busy again
+    banscii.type_bytes(b"Hi\r");
+    while !output.contains("{\"completed\":") {
+        banscii.advance_time(Duration::from_secs(1));
+        output += &banscii.take_output();
+    }
+    let (batch, after) = output
+        .split_once("{\"completed\":30,\"failed\":0}\n")
+        .unwrap();
+    assert!(!batch.contains("\"error\""));
+    assert!(after.starts_with("banscii> H"));
+    assert!(after.ends_with("\nerror: the artist is busy; try again in 250 ms\nbanscii> "));
+
+    let stats = banscii.enter(":stats");
+    assert!(!stats.contains("rate limited = 0\n"), "{}", stats);
+}
+
+#[test]
+fn works_are_timestamped() {
+    let mut banscii = Banscii::new();
+    let output = banscii.enter("Hi");
+    assert!(output.contains("\nSigned: 2023-11-14 22:13:20 UTC (1700000000)\n"));
+    // The clock stands still while the system runs
+    assert!(output.contains("\nRendered in 0.000 ms, signed in 0.000 ms\n"));
+    let hi = Work::parse(&output).unwrap();
+    assert_eq!(hi.timestamp, START_UNIX_SECONDS);
+
+    banscii.advance_time(Duration::from_secs(90));
+    let there = Work::parse(&banscii.enter("There")).unwrap();
+    assert_eq!(there.timestamp, START_UNIX_SECONDS + 90);
+    let verifying_key = VerifyingKey::<Sha256>::new_with_prefix(public_key(&mut banscii));
+    let signature = Signature::try_from(&there.signature[..]).unwrap();
+    assert!(verifying_key
+        .verify(&there.signed_data(), &signature)
+        .is_ok());
+    assert_eq!(Work::parse(&banscii.enter(":gallery show 1")), Some(hi));
+    assert!(banscii.enter(":verify").contains("Signature is valid"));
+
+    // The real-time clock keeps going across a restart
+    banscii.restart();
+    let again = Work::parse(&banscii.enter("There")).unwrap();
+    assert_eq!(again.timestamp, START_UNIX_SECONDS + 90);
+}
@@ -0,0 +1,175 @@
+use sel4cp::message::{MessageInfo, StatusMessageLabel};
+
+use banscii_sim::{Client, IsolatedStorage};
+use banscii_storage_interface_types::*;
+
+fn put(storage: &mut IsolatedStorage, client: Client, key: &[u8], value: &[u8]) -> MessageInfo {
+    let req = PutRequest {
+        key_start: 0,
+        key_size: key.len(),
+        value_start: key.len(),
+        value_size: value.len(),
+    };
+    storage
+        .call(
+            client,
+            MessageInfo::send(RequestTag::Put, req),
+            &[key, value].concat(),
+        )
+        .0
+}
+
+fn get(storage: &mut IsolatedStorage, client: Client, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+    let req = GetRequest {
+        key_start: 0,
+        key_size: key.len(),
+    };
+    let (response, region) = storage.call(client, MessageInfo::send(RequestTag::Get, req), key);
+    check(&response)?;
+    let resp = response.recv::<GetResponse>().unwrap();
+    Ok(region[resp.value_start..][..resp.value_size].to_vec())
+}
+
+fn list(storage: &mut IsolatedStorage, client: Client, after: &[u8]) -> (Vec<Vec<u8>>, bool) {
+    let req = ListRequest {
+        after_start: 0,
+        after_size: after.len(),
+    };
+    let (response, region) = storage.call(client, MessageInfo::send(RequestTag::List, req), after);
+    check(&response).unwrap();
+    let resp = response.recv::<ListResponse>().unwrap();
+    let mut packed = &region[resp.keys_start..][..resp.keys_size];
+    let mut keys = vec![];
+    for _ in 0..resp.count {
+        let len = usize::from(packed[0]);
+        keys.push(packed[1..][..len].to_vec());
+        packed = &packed[1 + len..];
+    }
+    (keys, resp.more != 0)
+}
+
+fn check(response: &MessageInfo) -> Result<(), StorageError> {
+    if response.label() == StatusMessageLabel::Ok.into() {
+        return Ok(());
+    }
+    let resp = response.recv::<ErrorResponse>().unwrap();
+    Err(StorageError::try_from(resp.error).unwrap())
+}
+
+#[test]
+fn put_then_get() {
+    let mut storage = IsolatedStorage::new();
+    assert_eq!(
+        get(&mut storage, Client::Artist, b"key"),
+        Err(StorageError::NotFound)
+    );
+    check(&put(&mut storage, Client::Artist, b"key", b"value")).unwrap();
+    assert_eq!(
+        get(&mut storage, Client::Artist, b"key").as_deref(),
+        Ok(&b"value"[..])
+    );
+
+    let req = DeleteRequest {
+        key_start: 0,
+        key_size: 3,
+    };
+    let (response, _) = storage.call(
+        Client::Artist,
+        MessageInfo::send(RequestTag::Delete, req),
+        b"key",
+    );
+    check(&response).unwrap();
+    assert_eq!(
+        get(&mut storage, Client::Artist, b"key"),
+        Err(StorageError::NotFound)
+    );
+}
+
+#[test]
+fn clients_have_separate_namespaces() {
+    let mut storage = IsolatedStorage::new();
+    check(&put(&mut storage, Client::Artist, b"serial", b"artist")).unwrap();
+    assert_eq!(
+        get(&mut storage, Client::Assistant, b"serial"),
+        Err(StorageError::NotFound)
+    );
+    check(&put(
+        &mut storage,
+        Client::Assistant,
+        b"serial",
+        b"assistant",
+    ))
+    .unwrap();
+    assert_eq!(
+        get(&mut storage, Client::Artist, b"serial").as_deref(),
+        Ok(&b"artist"[..])
+    );
+    assert_eq!(
+        list(&mut storage, Client::Artist, b""),
+        (vec![b"serial".to_vec()], false)
+    );
+}
+
+#[test]
+fn list_pages_through_keys() {
+    let mut storage = IsolatedStorage::new();
+    // Enough keys of the largest size that they can't all be listed at once
+    let keys = (0..REGION_SIZE / MAX_KEY_SIZE + 1)
+        .map(|i| {
+            let mut key = vec![b'k'; MAX_KEY_SIZE];
+            key[..2].copy_from_slice(&(i as u16).to_be_bytes());
+            key
+        })
+        .collect::<Vec<_>>();
+    for key in &keys {
+        check(&put(&mut storage, Client::Assistant, key, b"")).unwrap();
+    }
+
+    let (mut listed, more) = list(&mut storage, Client::Assistant, b"");
+    assert!(more);
+    let (rest, more) = list(&mut storage, Client::Assistant, listed.last().unwrap());
+    assert!(!more);
+    listed.extend(rest);
+    assert_eq!(listed, keys);
+}
+
+#[test]
+fn bad_requests_are_refused() {
+    let mut storage = IsolatedStorage::new();
+    let too_long_key = vec![b'k'; MAX_KEY_SIZE + 1];
+    assert_eq!(
+        check(&put(&mut storage, Client::Artist, &too_long_key, b"")),
+        Err(StorageError::BadRequest)
+    );
+    assert_eq!(
+        check(&put(&mut storage, Client::Artist, b"", b"value")),
+        Err(StorageError::BadRequest)
+    );
+    assert_eq!(
+        check(&put(
+            &mut storage,
+            Client::Artist,
+            b"key",
+            &vec![0; MAX_VALUE_SIZE + 1]
+        )),
+        Err(StorageError::BadRequest)
+    );
+    let req = GetRequest {
+        key_start: REGION_SIZE - 1,
+        key_size: 2,
+    };
+    let (response, _) = storage.call(Client::Artist, MessageInfo::send(RequestTag::Get, req), b"");
+    assert_eq!(check(&response), Err(StorageError::BadRequest));
+    let (response, _) = storage.call(Client::Artist, MessageInfo::from_raw(99, &[]), b"");
+    assert_eq!(check(&response), Err(StorageError::BadRequest));
+}
+
+#[test]
+fn full() {
+    let mut storage = IsolatedStorage::new();
+    let value = vec![0; MAX_VALUE_SIZE];
+    let err = (0u32..)
+        .map(|i| check(&put(&mut storage, Client::Artist, &i.to_be_bytes(), &value)))
+        .find_map(Result::err);
+    assert_eq!(err, Some(StorageError::Full));
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use banscii_sim::{Client, IsolatedTimer, START_UNIX_SECONDS};
+
+const MILLI: Duration = Duration::from_millis(1);
+
+#[test]
+fn now() {
+    let mut timer = IsolatedTimer::new();
+    let then = timer.call(Client::Artist, |timer| timer.now()).unwrap();
+    assert_eq!(then.since_start, Duration::ZERO);
+    assert_eq!(then.unix_seconds, START_UNIX_SECONDS);
+
+    timer.advance(Duration::from_millis(2500));
+    let now = timer.call(Client::Assistant, |timer| timer.now()).unwrap();
+    assert_eq!(now.since_start, Duration::from_millis(2500));
+    assert_eq!(now.unix_seconds, START_UNIX_SECONDS + 2);
+}
+
+#[test]
+fn one_shot_timeout() {
+    let mut timer = IsolatedTimer::new();
+    timer
+        .call(Client::Assistant, |timer| timer.set_timeout(10 * MILLI))
+        .unwrap();
+    timer.advance(9 * MILLI);
+    assert_eq!(timer.take_notified(), []);
+    timer.advance(MILLI);
+    assert_eq!(timer.take_notified(), [Client::Assistant]);
+    timer.advance(100 * MILLI);
+    assert_eq!(timer.take_notified(), []);
+}
+
+#[test]
+fn periodic_timeout() {
+    let mut timer = IsolatedTimer::new();
+    timer
+        .call(Client::Artist, |timer| {
+            timer.set_periodic(5 * MILLI, 10 * MILLI)
+        })
+        .unwrap();
+    timer
+        .call(Client::Assistant, |timer| timer.set_timeout(20 * MILLI))
+        .unwrap();
+    let mut notified = vec![];
+    for _ in 0..4 {
+        timer.advance(5 * MILLI);
+        notified.push(timer.take_notified());
+    }
+    assert_eq!(
+        notified,
+        [
+            vec![Client::Artist],
+            vec![],
+            vec![Client::Artist],
+            vec![Client::Assistant],
+        ]
+    );
+
+    // Falling behind by several periods is only made up for once
+    timer.advance(50 * MILLI);
+    assert_eq!(timer.take_notified(), [Client::Artist]);
+
+    timer
+        .call(Client::Artist, |timer| timer.cancel_timeout())
+        .unwrap();
+    timer.advance(50 * MILLI);
+    assert_eq!(timer.take_notified(), []);
+}
+
+#[test]
+fn short_period_is_refused() {
+    let mut timer = IsolatedTimer::new();
+    for period in [Duration::ZERO, Duration::from_micros(999)] {
+        assert!(timer
+            .call(Client::Artist, |timer| timer.set_periodic(MILLI, period))
+            .is_err());
+    }
+    timer.advance(10 * MILLI);
+    assert_eq!(timer.take_notified(), []);
+}
@@ -0,0 +1,147 @@
+//! For protection domains keeping values in storage, which must have a
+//! channel to it, a region shared with it alone, and a lower priority.
+//!
+//! Storage works out a client's namespace from the channel a request arrives
+//! on, so keys are only ever seen by the client that put them.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use sel4cp::memory_region::{ExternallySharedRef, ReadWrite};
+use sel4cp::message::{MessageInfo, StatusMessageLabel};
+use sel4cp::Channel;
+
+use banscii_storage_interface_types::*;
+
+/// A client's namespace in the storage protection domain, through the region
+/// shared with it.
+pub struct Storage {
+    channel: Channel,
+    region: ExternallySharedRef<'static, [u8], ReadWrite>,
+}
+
+impl Storage {
+    /// `channel` is the client's end of its channel to storage, and `region`
+    /// the one shared with storage over it.
+    pub fn new(channel: Channel, region: ExternallySharedRef<'static, [u8], ReadWrite>) -> Self {
+        Self { channel, region }
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        let (key_start, key_size) = self.write(0, key);
+        let msg_info = self.channel.pp_call(MessageInfo::send(
+            RequestTag::Get,
+            GetRequest {
+                key_start,
+                key_size,
+            },
+        ));
+        match check_status(&msg_info) {
+            Ok(()) => {}
+            Err(StorageError::NotFound) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let msg = msg_info
+            .recv::<GetResponse>()
+            .map_err(|_| StorageError::Io)?;
+        self.read(msg.value_start, msg.value_size).map(Some)
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let (key_start, key_size) = self.write(0, key);
+        let (value_start, value_size) = self.write(key_size, value);
+        let msg_info = self.channel.pp_call(MessageInfo::send(
+            RequestTag::Put,
+            PutRequest {
+                key_start,
+                key_size,
+                value_start,
+                value_size,
+            },
+        ));
+        check_status(&msg_info)
+    }
+
+    /// Removing a key that isn't there is not an error.
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), StorageError> {
+        let (key_start, key_size) = self.write(0, key);
+        let msg_info = self.channel.pp_call(MessageInfo::send(
+            RequestTag::Delete,
+            DeleteRequest {
+                key_start,
+                key_size,
+            },
+        ));
+        match check_status(&msg_info) {
+            Err(StorageError::NotFound) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Every key, in order, a page at a time.
+    pub fn keys(&mut self) -> Result<Vec<Vec<u8>>, StorageError> {
+        let mut keys = Vec::<Vec<u8>>::new();
+        loop {
+            let (after_start, after_size) = match keys.last() {
+                Some(last) => self.write(0, last),
+                None => (0, 0),
+            };
+            let msg_info = self.channel.pp_call(MessageInfo::send(
+                RequestTag::List,
+                ListRequest {
+                    after_start,
+                    after_size,
+                },
+            ));
+            check_status(&msg_info)?;
+            let msg = msg_info
+                .recv::<ListResponse>()
+                .map_err(|_| StorageError::Io)?;
+            let packed = self.read(msg.keys_start, msg.keys_size)?;
+            let mut rest = &packed[..];
+            for _ in 0..msg.count {
+                let (len, tail) = rest.split_first().ok_or(StorageError::Io)?;
+                let len = usize::from(*len);
+                if len == 0 || tail.len() < len {
+                    return Err(StorageError::Io);
+                }
+                keys.push(tail[..len].to_vec());
+                rest = &tail[len..];
+            }
+            if msg.more == 0 || msg.count == 0 {
+                return Ok(keys);
+            }
+        }
+    }
+
+    fn read(&self, start: usize, size: usize) -> Result<Vec<u8>, StorageError> {
+        let end = start
+            .checked_add(size)
+            .filter(|end| *end <= REGION_SIZE)
+            .ok_or(StorageError::Io)?;
+        Ok(self.region.as_ptr().index(start..end).copy_to_vec())
+    }
+
+    fn write(&mut self, start: usize, bytes: &[u8]) -> (usize, usize) {
+        self.region
+            .as_mut_ptr()
+            .index(start..start + bytes.len())
+            .copy_from_slice(bytes);
+        (start, bytes.len())
+    }
+}
+
+// Anything we can't make sense of counts as the storage failing
+fn check_status(msg_info: &MessageInfo) -> Result<(), StorageError> {
+    match msg_info.label().try_into() {
+        Ok(StatusMessageLabel::Ok) => Ok(()),
+        _ => Err(msg_info
+            .recv::<ErrorResponse>()
+            .ok()
+            .and_then(|msg| StorageError::try_from(msg.error).ok())
+            .unwrap_or(StorageError::Io)),
+    }
+}
@@ -0,0 +1,66 @@
+#![no_std]
+
+extern crate alloc;
+
+mod crc32;
+mod store;
+
+pub use store::{Store, StoreError};
+
+pub const BLOCK_SIZE: usize = 512;
+
+pub type Block = [u8; BLOCK_SIZE];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoError;
+
+/// Somewhere to keep blocks across reboots.
+pub trait BlockDevice {
+    fn block_count(&self) -> u64;
+
+    fn read(&mut self, index: u64, block: &mut Block) -> Result<(), IoError>;
+
+    fn write(&mut self, index: u64, block: &Block) -> Result<(), IoError>;
+}
+
+/// A block device in memory, for when there's no disk. It lasts only as long
+/// as the memory behind it.
+pub struct RamDisk<T> {
+    blocks: T,
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> RamDisk<T> {
+    pub fn new(blocks: T) -> Self {
+        Self { blocks }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.blocks
+    }
+
+    fn range(&self, index: u64) -> Result<core::ops::Range<usize>, IoError> {
+        if index >= self.block_count() {
+            return Err(IoError);
+        }
+        let start = index as usize * BLOCK_SIZE;
+        Ok(start..start + BLOCK_SIZE)
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> BlockDevice for RamDisk<T> {
+    fn block_count(&self) -> u64 {
+        (self.blocks.as_ref().len() / BLOCK_SIZE) as u64
+    }
+
+    fn read(&mut self, index: u64, block: &mut Block) -> Result<(), IoError> {
+        let range = self.range(index)?;
+        block.copy_from_slice(&self.blocks.as_ref()[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, index: u64, block: &Block) -> Result<(), IoError> {
+        let range = self.range(index)?;
+        self.blocks.as_mut()[range].copy_from_slice(block);
+        Ok(())
+    }
+}
@@ -0,0 +1,598 @@
+//! The device is split into two halves, only one of which is live at a time.
+//! Each half begins with a header block naming its generation, and the rest
+//! of it is a log of records, each of which sets or removes one key. When the
+//! live half fills up, what's still live is written afresh to the other half
+//! under the next generation. Its header is written last, so that if power is
+//! lost part way through, the old half is still the one found on mounting.
+//!
+//! A record is laid out as:
+//!
+//! ```text
+//! generation (u32) | body length (u16) | body | CRC-32 of all before (u32)
+//! body: op (u8) | namespace (u8) | key length (u8) | key | value
+//! ```
+//!
+//! with integers little-endian. Replaying the log stops at the first record
+//! which is torn, or left over from an earlier generation.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Bound;
+
+use crate::crc32::crc32;
+use crate::{Block, BlockDevice, IoError, BLOCK_SIZE};
+
+const MAGIC: [u8; 8] = *b"BNSCSTOR";
+
+const HEADER_SIZE: usize = 20;
+
+const RECORD_HEADER_SIZE: usize = 6;
+
+const RECORD_OVERHEAD: usize = RECORD_HEADER_SIZE + 4;
+
+const BODY_OVERHEAD: usize = 3;
+
+const PUT: u8 = 1;
+
+const DELETE: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreError {
+    /// The key is empty, or the key or value is too long to be recorded.
+    TooLarge,
+    /// There's no room for the value, even after compacting the log.
+    Full,
+    Io,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooLarge => write!(f, "key or value is too large"),
+            Self::Full => write!(f, "store is full"),
+            Self::Io => write!(f, "block device failed"),
+        }
+    }
+}
+
+impl From<IoError> for StoreError {
+    fn from(_: IoError) -> Self {
+        Self::Io
+    }
+}
+
+/// A log-structured map from keys to values, each key within one of a number
+/// of namespaces. Every entry is also kept in memory, so reads never touch
+/// the device.
+pub struct Store<D> {
+    device: D,
+    half_blocks: u64,
+    // Which half is live, 0 or 1
+    live: u64,
+    generation: u64,
+    // Offset into the live half's log of the next record
+    tail: usize,
+    // The block which `tail` falls in, as it is on the device
+    tail_block: Block,
+    entries: BTreeMap<(u8, Vec<u8>), Vec<u8>>,
+    // Bytes of log the entries would take up if written afresh
+    live_size: usize,
+}
+
+impl<D: BlockDevice> Store<D> {
+    /// Replays whatever the device holds, or starts an empty store on it if
+    /// it holds nothing recognisable.
+    pub fn mount(mut device: D) -> Result<Self, StoreError> {
+        let half_blocks = device.block_count() / 2;
+        if half_blocks < 2 {
+            return Err(StoreError::Full);
+        }
+
+        let mut block = [0; BLOCK_SIZE];
+        let mut found = None;
+        for half in 0..2 {
+            device.read(half * half_blocks, &mut block)?;
+            if let Some(generation) = parse_header(&block) {
+                if found.map_or(true, |(_, newest)| generation > newest) {
+                    found = Some((half, generation));
+                }
+            }
+        }
+
+        let mut store = Self {
+            device,
+            half_blocks,
+            live: 0,
+            generation: 1,
+            tail: 0,
+            tail_block: [0; BLOCK_SIZE],
+            entries: BTreeMap::new(),
+            live_size: 0,
+        };
+
+        match found {
+            Some((half, generation)) => {
+                store.live = half;
+                store.generation = generation;
+                store.replay()?;
+            }
+            None => {
+                // Nothing from the previous contents of the log will carry
+                // this generation, except by a one in 2^32 chance
+                store.device.write(1, &[0; BLOCK_SIZE])?;
+                store.device.write(0, &header(store.generation))?;
+            }
+        }
+
+        Ok(store)
+    }
+
+    pub fn into_device(self) -> D {
+        self.device
+    }
+
+    pub fn get(&self, namespace: u8, key: &[u8]) -> Option<&[u8]> {
+        self.entries
+            .get(&(namespace, key.to_vec()))
+            .map(Vec::as_slice)
+    }
+
+    pub fn put(&mut self, namespace: u8, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        let record = self.record(PUT, namespace, key, value)?;
+        let entry_key = (namespace, key.to_vec());
+        let old_size = self
+            .entries
+            .get(&entry_key)
+            .map_or(0, |old| record_size(key.len(), old.len()));
+        let live_size = self.live_size - old_size + record.len();
+        if live_size > self.capacity() {
+            return Err(StoreError::Full);
+        }
+
+        if self.tail + record.len() <= self.capacity() {
+            self.append(&record)?;
+            self.entries.insert(entry_key, value.to_vec());
+            self.live_size = live_size;
+            return Ok(());
+        }
+
+        let previous = self.entries.insert(entry_key.clone(), value.to_vec());
+        let previous_live_size = self.live_size;
+        self.live_size = live_size;
+        self.compact().map_err(|err| {
+            match previous {
+                Some(previous) => self.entries.insert(entry_key, previous),
+                None => self.entries.remove(&entry_key),
+            };
+            self.live_size = previous_live_size;
+            err
+        })
+    }
+
+    /// Returns whether there was anything to remove.
+    pub fn delete(&mut self, namespace: u8, key: &[u8]) -> Result<bool, StoreError> {
+        let entry_key = (namespace, key.to_vec());
+        let Some(old) = self.entries.get(&entry_key) else {
+            return Ok(false);
+        };
+        let old_size = record_size(key.len(), old.len());
+        let record = self.record(DELETE, namespace, key, &[])?;
+
+        if self.tail + record.len() <= self.capacity() {
+            self.append(&record)?;
+            self.entries.remove(&entry_key);
+            self.live_size -= old_size;
+            return Ok(true);
+        }
+
+        let previous = self.entries.remove(&entry_key).unwrap();
+        self.live_size -= old_size;
+        self.compact().map_err(|err| {
+            self.entries.insert(entry_key, previous);
+            self.live_size += old_size;
+            err
+        })?;
+        Ok(true)
+    }
+
+    /// The keys in a namespace, in order, starting after `after` if given.
+    pub fn keys<'a>(
+        &'a self,
+        namespace: u8,
+        after: Option<&[u8]>,
+    ) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let start = match after {
+            Some(after) => Bound::Excluded((namespace, after.to_vec())),
+            None => Bound::Included((namespace, Vec::new())),
+        };
+        self.entries
+            .range((start, Bound::Unbounded))
+            .take_while(move |((ns, _), _)| *ns == namespace)
+            .map(|((_, key), _)| key.as_slice())
+    }
+
+    // Bytes of log in each half
+    fn capacity(&self) -> usize {
+        (self.half_blocks as usize - 1) * BLOCK_SIZE
+    }
+
+    fn log_block(&self, half: u64, offset: usize) -> u64 {
+        half * self.half_blocks + 1 + (offset / BLOCK_SIZE) as u64
+    }
+
+    fn record(
+        &self,
+        op: u8,
+        namespace: u8,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Vec<u8>, StoreError> {
+        if key.is_empty() || key.len() > usize::from(u8::MAX) {
+            return Err(StoreError::TooLarge);
+        }
+        encode_record(self.generation, op, namespace, key, value).ok_or(StoreError::TooLarge)
+    }
+
+    fn replay(&mut self) -> Result<(), StoreError> {
+        let mut reader = LogReader {
+            first_block: self.log_block(self.live, 0),
+            loaded: None,
+            block: [0; BLOCK_SIZE],
+        };
+        let mut header = [0; RECORD_HEADER_SIZE];
+        let mut offset = 0;
+        loop {
+            if offset + RECORD_OVERHEAD > self.capacity() {
+                break;
+            }
+            reader.read(&mut self.device, offset, &mut header)?;
+            let generation = u32::from_le_bytes(header[..4].try_into().unwrap());
+            let len = usize::from(u16::from_le_bytes(header[4..].try_into().unwrap()));
+            let size = RECORD_OVERHEAD + len;
+            if generation != self.generation as u32 || offset + size > self.capacity() {
+                break;
+            }
+            let mut record = alloc::vec![0; size];
+            reader.read(&mut self.device, offset, &mut record)?;
+            let (covered, crc) = record.split_at(size - 4);
+            if crc32(covered) != u32::from_le_bytes(crc.try_into().unwrap()) {
+                break;
+            }
+            if !self.apply(&covered[RECORD_HEADER_SIZE..]) {
+                break;
+            }
+            offset += size;
+        }
+
+        self.tail = offset;
+        if offset < self.capacity() {
+            self.device
+                .read(self.log_block(self.live, offset), &mut self.tail_block)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, body: &[u8]) -> bool {
+        if body.len() < BODY_OVERHEAD {
+            return false;
+        }
+        let (op, namespace, key_len) = (body[0], body[1], usize::from(body[2]));
+        let Some(key) = body.get(BODY_OVERHEAD..BODY_OVERHEAD + key_len) else {
+            return false;
+        };
+        let value = &body[BODY_OVERHEAD + key_len..];
+        let entry_key = (namespace, key.to_vec());
+        if let Some(old) = self.entries.get(&entry_key) {
+            self.live_size -= record_size(key.len(), old.len());
+        }
+        match op {
+            PUT => {
+                self.live_size += record_size(key.len(), value.len());
+                self.entries.insert(entry_key, value.to_vec());
+            }
+            DELETE => {
+                self.entries.remove(&entry_key);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // The caller has checked that the record fits. If the device fails, the
+    // tail is left where it was, so that the next record overwrites whatever
+    // of this one made it.
+    fn append(&mut self, record: &[u8]) -> Result<(), StoreError> {
+        let tail = self.tail;
+        let tail_block = self.tail_block;
+        let mut written = 0;
+        while written < record.len() {
+            let in_block = self.tail % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - in_block).min(record.len() - written);
+            self.tail_block[in_block..in_block + n].copy_from_slice(&record[written..written + n]);
+            let index = self.log_block(self.live, self.tail);
+            if let Err(err) = self.device.write(index, &self.tail_block) {
+                self.tail = tail;
+                self.tail_block = tail_block;
+                return Err(err.into());
+            }
+            written += n;
+            self.tail += n;
+            if self.tail % BLOCK_SIZE == 0 {
+                self.tail_block = [0; BLOCK_SIZE];
+            }
+        }
+        Ok(())
+    }
+
+    // Rewrites the entries as they are now into the other half, and makes it
+    // the live one. The caller has checked that they fit.
+    fn compact(&mut self) -> Result<(), StoreError> {
+        let half = 1 - self.live;
+        let generation = self.generation + 1;
+        let mut block = [0; BLOCK_SIZE];
+        let mut offset = 0;
+        for ((namespace, key), value) in &self.entries {
+            let record = encode_record(generation, PUT, *namespace, key, value).unwrap();
+            let mut written = 0;
+            while written < record.len() {
+                let in_block = offset % BLOCK_SIZE;
+                let n = (BLOCK_SIZE - in_block).min(record.len() - written);
+                block[in_block..in_block + n].copy_from_slice(&record[written..written + n]);
+                written += n;
+                offset += n;
+                if offset % BLOCK_SIZE == 0 {
+                    let index = self.log_block(half, offset - 1);
+                    self.device.write(index, &block)?;
+                    block = [0; BLOCK_SIZE];
+                }
+            }
+        }
+        // Either a partial block with room after the last record, or a
+        // zeroed one, so that replaying stops there
+        if offset < self.capacity() {
+            self.device.write(self.log_block(half, offset), &block)?;
+        }
+        self.device
+            .write(half * self.half_blocks, &header(generation))?;
+
+        self.live = half;
+        self.generation = generation;
+        self.tail = offset;
+        self.tail_block = block;
+        Ok(())
+    }
+}
+
+// Reads spans of a half's log, a block at a time
+struct LogReader {
+    first_block: u64,
+    loaded: Option<u64>,
+    block: Block,
+}
+
+impl LogReader {
+    fn read<D: BlockDevice>(
+        &mut self,
+        device: &mut D,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), IoError> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = offset + done;
+            let index = self.first_block + (at / BLOCK_SIZE) as u64;
+            if self.loaded != Some(index) {
+                device.read(index, &mut self.block)?;
+                self.loaded = Some(index);
+            }
+            let in_block = at % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - in_block).min(buf.len() - done);
+            buf[done..done + n].copy_from_slice(&self.block[in_block..in_block + n]);
+            done += n;
+        }
+        Ok(())
+    }
+}
+
+fn record_size(key_len: usize, value_len: usize) -> usize {
+    RECORD_OVERHEAD + BODY_OVERHEAD + key_len + value_len
+}
+
+fn encode_record(
+    generation: u64,
+    op: u8,
+    namespace: u8,
+    key: &[u8],
+    value: &[u8],
+) -> Option<Vec<u8>> {
+    let body_len = u16::try_from(BODY_OVERHEAD + key.len() + value.len()).ok()?;
+    let mut record = Vec::with_capacity(record_size(key.len(), value.len()));
+    record.extend_from_slice(&(generation as u32).to_le_bytes());
+    record.extend_from_slice(&body_len.to_le_bytes());
+    record.extend_from_slice(&[op, namespace, key.len() as u8]);
+    record.extend_from_slice(key);
+    record.extend_from_slice(value);
+    let crc = crc32(&record);
+    record.extend_from_slice(&crc.to_le_bytes());
+    Some(record)
+}
+
+fn header(generation: u64) -> Block {
+    let mut block = [0; BLOCK_SIZE];
+    block[..8].copy_from_slice(&MAGIC);
+    block[8..16].copy_from_slice(&generation.to_le_bytes());
+    let crc = crc32(&block[..16]);
+    block[16..HEADER_SIZE].copy_from_slice(&crc.to_le_bytes());
+    block
+}
+
+fn parse_header(block: &Block) -> Option<u64> {
+    let crc = u32::from_le_bytes(block[16..HEADER_SIZE].try_into().unwrap());
+    (block[..8] == MAGIC && crc32(&block[..16]) == crc)
+        .then(|| u64::from_le_bytes(block[8..16].try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::RamDisk;
+
+    const BLOCKS: usize = 8;
+
+    fn fresh() -> Store<RamDisk<Vec<u8>>> {
+        Store::mount(RamDisk::new(vec![0; BLOCKS * BLOCK_SIZE])).unwrap()
+    }
+
+    fn remount(store: Store<RamDisk<Vec<u8>>>) -> Store<RamDisk<Vec<u8>>> {
+        Store::mount(store.into_device()).unwrap()
+    }
+
+    // Fails every write after the first `n`
+    struct Flaky {
+        disk: RamDisk<Vec<u8>>,
+        writes_left: usize,
+    }
+
+    impl BlockDevice for Flaky {
+        fn block_count(&self) -> u64 {
+            self.disk.block_count()
+        }
+
+        fn read(&mut self, index: u64, block: &mut Block) -> Result<(), IoError> {
+            self.disk.read(index, block)
+        }
+
+        fn write(&mut self, index: u64, block: &Block) -> Result<(), IoError> {
+            if self.writes_left == 0 {
+                return Err(IoError);
+            }
+            self.writes_left -= 1;
+            self.disk.write(index, block)
+        }
+    }
+
+    #[test]
+    fn put_get_delete() {
+        let mut store = fresh();
+        assert_eq!(store.get(0, b"a"), None);
+        store.put(0, b"a", b"one").unwrap();
+        store.put(0, b"b", b"two").unwrap();
+        store.put(0, b"a", b"three").unwrap();
+        assert_eq!(store.get(0, b"a"), Some(&b"three"[..]));
+        assert_eq!(store.delete(0, b"b"), Ok(true));
+        assert_eq!(store.delete(0, b"b"), Ok(false));
+        assert_eq!(store.get(0, b"b"), None);
+    }
+
+    #[test]
+    fn survives_remount() {
+        let mut store = fresh();
+        store.put(0, b"a", b"one").unwrap();
+        store.put(1, b"b", &[7; 600]).unwrap();
+        store.delete(0, b"a").unwrap();
+        store.put(0, b"c", b"three").unwrap();
+        let store = remount(store);
+        assert_eq!(store.get(0, b"a"), None);
+        assert_eq!(store.get(1, b"b"), Some(&[7; 600][..]));
+        assert_eq!(store.get(0, b"c"), Some(&b"three"[..]));
+    }
+
+    #[test]
+    fn namespaces_are_separate() {
+        let mut store = fresh();
+        store.put(0, b"key", b"zero").unwrap();
+        store.put(1, b"key", b"one").unwrap();
+        store.put(1, b"other", b"one").unwrap();
+        assert_eq!(store.get(0, b"key"), Some(&b"zero"[..]));
+        assert_eq!(store.get(1, b"key"), Some(&b"one"[..]));
+        assert_eq!(store.keys(0, None).collect::<Vec<_>>(), [&b"key"[..]]);
+        assert_eq!(
+            store.keys(1, Some(b"key")).collect::<Vec<_>>(),
+            [&b"other"[..]]
+        );
+    }
+
+    #[test]
+    fn compacts_when_the_log_fills() {
+        let mut store = fresh();
+        // Far more than a half holds, but only one value live at a time
+        for i in 0..200u32 {
+            store.put(0, b"counter", &i.to_le_bytes()).unwrap();
+            store.put(0, b"scratch", &[i as u8; 100]).unwrap();
+            store.delete(0, b"scratch").unwrap();
+        }
+        assert!(store.generation > 1);
+        let store = remount(store);
+        assert_eq!(store.get(0, b"counter"), Some(&199u32.to_le_bytes()[..]));
+        assert_eq!(store.get(0, b"scratch"), None);
+    }
+
+    #[test]
+    fn full() {
+        let mut store = fresh();
+        let value = [0; 500];
+        let mut stored = 0;
+        let err = loop {
+            match store.put(0, &[stored as u8 + 1], &value) {
+                Ok(()) => stored += 1,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err, StoreError::Full);
+        assert!(stored > 0);
+        // Replacing a value with one no larger still works
+        store.put(0, &[1], b"small").unwrap();
+        let store = remount(store);
+        assert_eq!(store.keys(0, None).count(), stored);
+    }
+
+    #[test]
+    fn too_large() {
+        let mut store = fresh();
+        assert_eq!(store.put(0, b"", b"x"), Err(StoreError::TooLarge));
+        assert_eq!(store.put(0, &[1; 256], b"x"), Err(StoreError::TooLarge));
+        assert_eq!(store.put(0, b"k", &[0; 0x10000]), Err(StoreError::TooLarge));
+    }
+
+    #[test]
+    fn torn_record_is_dropped() {
+        let mut store = fresh();
+        store.put(0, b"a", b"one").unwrap();
+        let tail = store.tail;
+        store.put(0, b"b", b"two").unwrap();
+        let mut disk = store.into_device().into_inner();
+        // Corrupt the last byte of the second record's CRC
+        let at = BLOCK_SIZE + tail + record_size(1, 3) - 1;
+        disk[at] ^= 0xff;
+        let mut store = Store::mount(RamDisk::new(disk)).unwrap();
+        assert_eq!(store.get(0, b"a"), Some(&b"one"[..]));
+        assert_eq!(store.get(0, b"b"), None);
+        // And is overwritten by the next
+        store.put(0, b"c", b"three").unwrap();
+        let store = remount(store);
+        assert_eq!(store.get(0, b"c"), Some(&b"three"[..]));
+    }
+
+    #[test]
+    fn failed_compaction_keeps_the_old_half() {
+        let mut store = fresh();
+        store.put(0, b"keep", b"old").unwrap();
+        // Records the same size as the next, until it doesn't fit
+        while store.tail + record_size(4, 3) <= store.capacity() {
+            store.put(0, b"fill", b"xyz").unwrap();
+        }
+        let disk = store.into_device();
+        let mut store = Store::mount(Flaky {
+            disk,
+            writes_left: 1,
+        })
+        .unwrap();
+        assert_eq!(store.put(0, b"keep", b"new"), Err(StoreError::Io));
+        assert_eq!(store.get(0, b"keep"), Some(&b"old"[..]));
+        let store = Store::mount(store.into_device().disk).unwrap();
+        assert_eq!(store.get(0, b"keep"), Some(&b"old"[..]));
+    }
+}
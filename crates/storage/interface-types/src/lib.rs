@@ -0,0 +1,92 @@
+#![no_std]
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use zerocopy::{AsBytes, FromBytes};
+
+pub const MAX_KEY_SIZE: usize = 64;
+
+pub const MAX_VALUE_SIZE: usize = 0x800;
+
+/// The size of the region shared with each client, which holds a key and
+/// value, or a page of keys.
+pub const REGION_SIZE: usize = 0x1_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[cfg_attr(target_pointer_width = "32", repr(u32))]
+#[cfg_attr(target_pointer_width = "64", repr(u64))]
+pub enum RequestTag {
+    Get,
+    Put,
+    Delete,
+    List,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct GetRequest {
+    pub key_start: usize,
+    pub key_size: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct GetResponse {
+    pub value_start: usize,
+    pub value_size: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct PutRequest {
+    pub key_start: usize,
+    pub key_size: usize,
+    pub value_start: usize,
+    pub value_size: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct DeleteRequest {
+    pub key_start: usize,
+    pub key_size: usize,
+}
+
+/// Asks for the keys after `after`, in order, or from the first if
+/// `after_size` is zero.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ListRequest {
+    pub after_start: usize,
+    pub after_size: usize,
+}
+
+/// The keys are packed into the region from `keys_start`, each preceded by
+/// its length as a byte. `more` is nonzero if there are keys which didn't
+/// fit, which can be had by asking again after the last one.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ListResponse {
+    pub keys_start: usize,
+    pub keys_size: usize,
+    pub count: usize,
+    pub more: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum StorageError {
+    /// The request was malformed, or pointed outside of the client's region.
+    BadRequest,
+    NotFound,
+    /// There's no room for the value, even after compacting the log.
+    Full,
+    /// The block device failed.
+    Io,
+}
+
+/// Sent along with an error status.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ErrorResponse {
+    pub error: u8,
+}
@@ -0,0 +1,264 @@
+#![no_std]
+#![no_main]
+#![feature(const_trait_impl)]
+#![feature(never_type)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use sel4cp::memory_region::{memory_region_symbol, ExternallySharedRef, ReadWrite};
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use sel4cp::{protection_domain, Channel, Handler};
+
+use banscii_storage_core::{Store, StoreError};
+use banscii_storage_interface_types::*;
+
+#[cfg(feature = "virtio-blk")]
+mod virtio_blk;
+
+const ARTIST: Channel = Channel::new(0);
+const ASSISTANT: Channel = Channel::new(1);
+
+#[cfg(not(feature = "virtio-blk"))]
+const RAM_DISK_SIZE: usize = 0x10_000;
+
+#[cfg(not(feature = "virtio-blk"))]
+type Disk = banscii_storage_core::RamDisk<&'static mut [u8]>;
+
+#[cfg(feature = "virtio-blk")]
+type Disk = virtio_blk::VirtioBlk;
+
+// Every entry is also kept on the heap
+#[protection_domain(heap_size = 0x20000)]
+fn init() -> ThisHandler {
+    let artist_region = unsafe {
+        ExternallySharedRef::<'static, [u8]>::new(
+            memory_region_symbol!(artist_region_start: *mut [u8], n = REGION_SIZE),
+        )
+    };
+
+    let assistant_region = unsafe {
+        ExternallySharedRef::<'static, [u8]>::new(
+            memory_region_symbol!(assistant_region_start: *mut [u8], n = REGION_SIZE),
+        )
+    };
+
+    // A store that can't be mounted fails every request, rather than taking
+    // the rest of the system down with it
+    ThisHandler {
+        artist_region,
+        assistant_region,
+        store: disk().and_then(|disk| Store::mount(disk).ok()),
+    }
+}
+
+// The RAM disk is mapped into this protection domain alone, so it's safe to
+// treat as an ordinary slice
+#[cfg(not(feature = "virtio-blk"))]
+fn disk() -> Option<Disk> {
+    let ram_disk = memory_region_symbol!(ram_disk_start: *mut [u8], n = RAM_DISK_SIZE);
+    Some(banscii_storage_core::RamDisk::new(unsafe {
+        &mut *ram_disk.as_ptr()
+    }))
+}
+
+#[cfg(feature = "virtio-blk")]
+fn disk() -> Option<Disk> {
+    unsafe { virtio_blk::VirtioBlk::probe() }
+}
+
+struct ThisHandler {
+    artist_region: ExternallySharedRef<'static, [u8], ReadWrite>,
+    assistant_region: ExternallySharedRef<'static, [u8], ReadWrite>,
+    store: Option<Store<Disk>>,
+}
+
+impl Handler for ThisHandler {
+    type Error = !;
+
+    fn protected(
+        &mut self,
+        channel: Channel,
+        msg_info: MessageInfo,
+    ) -> Result<MessageInfo, Self::Error> {
+        // Each client's keys live in a namespace chosen by the channel it
+        // calls on, rather than by anything it says, so neither can see or
+        // touch the other's
+        let namespace = match channel {
+            ARTIST => 0,
+            ASSISTANT => 1,
+            _ => {
+                unreachable!()
+            }
+        };
+        Ok(self
+            .serve(channel, namespace, &msg_info)
+            .unwrap_or_else(|err| {
+                MessageInfo::send(
+                    StatusMessageLabel::Error,
+                    ErrorResponse { error: err.into() },
+                )
+            }))
+    }
+}
+
+impl ThisHandler {
+    fn serve(
+        &mut self,
+        channel: Channel,
+        namespace: u8,
+        msg_info: &MessageInfo,
+    ) -> Result<MessageInfo, StorageError> {
+        match msg_info.label().try_into().ok() {
+            Some(RequestTag::Get) => {
+                let req = msg_info
+                    .recv::<GetRequest>()
+                    .map_err(|_| StorageError::BadRequest)?;
+                let key = self.read_key(channel, req.key_start, req.key_size)?;
+                let value = self
+                    .store()?
+                    .get(namespace, &key)
+                    .ok_or(StorageError::NotFound)?
+                    .to_vec();
+
+                let value_start = 0;
+                let value_size = value.len();
+                let value_end = value_start + value_size;
+
+                self.region(channel)
+                    .as_mut_ptr()
+                    .index(value_start..value_end)
+                    .copy_from_slice(&value);
+
+                Ok(MessageInfo::send(
+                    StatusMessageLabel::Ok,
+                    GetResponse {
+                        value_start,
+                        value_size,
+                    },
+                ))
+            }
+            Some(RequestTag::Put) => {
+                let req = msg_info
+                    .recv::<PutRequest>()
+                    .map_err(|_| StorageError::BadRequest)?;
+                let key = self.read_key(channel, req.key_start, req.key_size)?;
+                if req.value_size > MAX_VALUE_SIZE {
+                    return Err(StorageError::BadRequest);
+                }
+                let value_range = region_range(req.value_start, req.value_size)?;
+                let value = self
+                    .region(channel)
+                    .as_ptr()
+                    .index(value_range)
+                    .copy_to_vec();
+                self.store()?
+                    .put(namespace, &key, &value)
+                    .map_err(store_error)?;
+                Ok(MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue))
+            }
+            Some(RequestTag::Delete) => {
+                let req = msg_info
+                    .recv::<DeleteRequest>()
+                    .map_err(|_| StorageError::BadRequest)?;
+                let key = self.read_key(channel, req.key_start, req.key_size)?;
+                if !self.store()?.delete(namespace, &key).map_err(store_error)? {
+                    return Err(StorageError::NotFound);
+                }
+                Ok(MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue))
+            }
+            Some(RequestTag::List) => {
+                let req = msg_info
+                    .recv::<ListRequest>()
+                    .map_err(|_| StorageError::BadRequest)?;
+                let after = match req.after_size {
+                    0 => None,
+                    _ => Some(self.read_key(channel, req.after_start, req.after_size)?),
+                };
+                let (keys, count, more) = self.list(namespace, after.as_deref())?;
+
+                let keys_start = 0;
+                let keys_size = keys.len();
+                let keys_end = keys_start + keys_size;
+
+                self.region(channel)
+                    .as_mut_ptr()
+                    .index(keys_start..keys_end)
+                    .copy_from_slice(&keys);
+
+                Ok(MessageInfo::send(
+                    StatusMessageLabel::Ok,
+                    ListResponse {
+                        keys_start,
+                        keys_size,
+                        count,
+                        more: more.into(),
+                    },
+                ))
+            }
+            None => Err(StorageError::BadRequest),
+        }
+    }
+
+    // As many keys as fit in the region, each prefixed by its length
+    fn list(
+        &mut self,
+        namespace: u8,
+        after: Option<&[u8]>,
+    ) -> Result<(Vec<u8>, usize, bool), StorageError> {
+        let mut packed = Vec::new();
+        let mut count = 0;
+        for key in self.store()?.keys(namespace, after) {
+            if packed.len() + 1 + key.len() > REGION_SIZE {
+                return Ok((packed, count, true));
+            }
+            packed.push(key.len() as u8);
+            packed.extend_from_slice(key);
+            count += 1;
+        }
+        Ok((packed, count, false))
+    }
+
+    fn store(&mut self) -> Result<&mut Store<Disk>, StorageError> {
+        self.store.as_mut().ok_or(StorageError::Io)
+    }
+
+    fn region(&mut self, channel: Channel) -> &mut ExternallySharedRef<'static, [u8], ReadWrite> {
+        match channel {
+            ARTIST => &mut self.artist_region,
+            _ => &mut self.assistant_region,
+        }
+    }
+
+    fn read_key(
+        &mut self,
+        channel: Channel,
+        start: usize,
+        size: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        if size == 0 || size > MAX_KEY_SIZE {
+            return Err(StorageError::BadRequest);
+        }
+        let range = region_range(start, size)?;
+        Ok(self.region(channel).as_ptr().index(range).copy_to_vec())
+    }
+}
+
+/// Clients are untrusted, so every span they point us at is checked against
+/// their region before use.
+fn region_range(start: usize, size: usize) -> Result<Range<usize>, StorageError> {
+    match start.checked_add(size) {
+        Some(end) if end <= REGION_SIZE => Ok(start..end),
+        _ => Err(StorageError::BadRequest),
+    }
+}
+
+fn store_error(err: StoreError) -> StorageError {
+    match err {
+        StoreError::TooLarge => StorageError::BadRequest,
+        StoreError::Full => StorageError::Full,
+        StoreError::Io => StorageError::Io,
+    }
+}
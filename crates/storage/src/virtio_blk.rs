@@ -0,0 +1,247 @@
+//! A polling driver for a virtio-blk device behind QEMU's virtio-mmio
+//! transports, using a single request at a time on one virtqueue.
+//!
+//! Only the modern (version 2) transport is supported, which QEMU provides
+//! with `-global virtio-mmio.force-legacy=false`.
+
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{fence, Ordering};
+
+use sel4cp::memory_region_symbol;
+
+use banscii_storage_core::{Block, BlockDevice, IoError, BLOCK_SIZE};
+
+// The transports on QEMU's virt machine, 0x200 bytes apart
+const TRANSPORTS: usize = 32;
+const TRANSPORT_SIZE: usize = 0x200;
+
+// Where the DMA region is placed by `phys_addr` in the system description.
+// The device is handed physical addresses, and we have no way to look ours
+// up at run time.
+const DMA_PADDR: usize = 0x6000_0000;
+const DMA_SIZE: usize = 0x1_000;
+
+const MAGIC: u32 = 0x7472_6976;
+const BLOCK_DEVICE_ID: u32 = 2;
+
+const REG_MAGIC: usize = 0x000;
+const REG_VERSION: usize = 0x004;
+const REG_DEVICE_ID: usize = 0x008;
+const REG_DEVICE_FEATURES: usize = 0x010;
+const REG_DEVICE_FEATURES_SEL: usize = 0x014;
+const REG_DRIVER_FEATURES: usize = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+const REG_QUEUE_SEL: usize = 0x030;
+const REG_QUEUE_NUM_MAX: usize = 0x034;
+const REG_QUEUE_NUM: usize = 0x038;
+const REG_QUEUE_READY: usize = 0x044;
+const REG_QUEUE_NOTIFY: usize = 0x050;
+const REG_INTERRUPT_STATUS: usize = 0x060;
+const REG_INTERRUPT_ACK: usize = 0x064;
+const REG_STATUS: usize = 0x070;
+const REG_QUEUE_DESC: usize = 0x080;
+const REG_QUEUE_DRIVER: usize = 0x090;
+const REG_QUEUE_DEVICE: usize = 0x0a0;
+const REG_CAPACITY: usize = 0x100;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+// VIRTIO_F_VERSION_1, in the second word of features
+const FEATURE_VERSION_1: u32 = 1 << 0;
+
+const QUEUE_SIZE: u16 = 4;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+
+// Layout of the DMA region
+const DESC_OFFSET: usize = 0x000;
+const AVAIL_OFFSET: usize = 0x040;
+const USED_OFFSET: usize = 0x080;
+const HEADER_OFFSET: usize = 0x100;
+const STATUS_OFFSET: usize = 0x110;
+const DATA_OFFSET: usize = 0x200;
+
+pub(crate) struct VirtioBlk {
+    regs: NonNull<u8>,
+    dma: NonNull<u8>,
+    capacity: u64,
+    last_used: u16,
+}
+
+impl VirtioBlk {
+    /// Looks for a block device among the transports, and sets it up.
+    ///
+    /// # Safety
+    ///
+    /// Must be called at most once, from `init`.
+    pub(crate) unsafe fn probe() -> Option<Self> {
+        let mmio =
+            memory_region_symbol!(virtio_mmio_start: *mut [u8], n = TRANSPORTS * TRANSPORT_SIZE);
+        let dma = memory_region_symbol!(virtio_blk_dma_start: *mut [u8], n = DMA_SIZE);
+        (0..TRANSPORTS).find_map(|i| {
+            let regs = NonNull::new(mmio.as_ptr().cast::<u8>().add(i * TRANSPORT_SIZE))?;
+            let mut dev = Self {
+                regs,
+                dma: dma.cast(),
+                capacity: 0,
+                last_used: 0,
+            };
+            (dev.read_reg(REG_MAGIC) == MAGIC
+                && dev.read_reg(REG_VERSION) == 2
+                && dev.read_reg(REG_DEVICE_ID) == BLOCK_DEVICE_ID
+                && dev.init())
+            .then_some(dev)
+        })
+    }
+
+    unsafe fn init(&mut self) -> bool {
+        self.write_reg(REG_STATUS, 0);
+        self.write_reg(REG_STATUS, STATUS_ACKNOWLEDGE);
+        self.write_reg(REG_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        self.write_reg(REG_DEVICE_FEATURES_SEL, 1);
+        if self.read_reg(REG_DEVICE_FEATURES) & FEATURE_VERSION_1 == 0 {
+            return false;
+        }
+        self.write_reg(REG_DRIVER_FEATURES_SEL, 0);
+        self.write_reg(REG_DRIVER_FEATURES, 0);
+        self.write_reg(REG_DRIVER_FEATURES_SEL, 1);
+        self.write_reg(REG_DRIVER_FEATURES, FEATURE_VERSION_1);
+
+        let status = STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK;
+        self.write_reg(REG_STATUS, status);
+        if self.read_reg(REG_STATUS) & STATUS_FEATURES_OK == 0 {
+            return false;
+        }
+
+        self.write_reg(REG_QUEUE_SEL, 0);
+        if self.read_reg(REG_QUEUE_NUM_MAX) < u32::from(QUEUE_SIZE) {
+            return false;
+        }
+        ptr::write_bytes(self.dma.as_ptr(), 0, DMA_SIZE);
+        self.write_reg(REG_QUEUE_NUM, u32::from(QUEUE_SIZE));
+        self.write_addr(REG_QUEUE_DESC, DMA_PADDR + DESC_OFFSET);
+        self.write_addr(REG_QUEUE_DRIVER, DMA_PADDR + AVAIL_OFFSET);
+        self.write_addr(REG_QUEUE_DEVICE, DMA_PADDR + USED_OFFSET);
+        self.write_reg(REG_QUEUE_READY, 1);
+
+        self.write_reg(REG_STATUS, status | STATUS_DRIVER_OK);
+
+        self.capacity = u64::from(self.read_reg(REG_CAPACITY))
+            | (u64::from(self.read_reg(REG_CAPACITY + 4)) << 32);
+        true
+    }
+
+    // Runs one request through a chain of three descriptors: the header, the
+    // data, and the status byte the device writes back
+    unsafe fn request(&mut self, kind: u32, sector: u64) -> Result<(), IoError> {
+        if sector >= self.capacity {
+            return Err(IoError);
+        }
+
+        self.write_dma(HEADER_OFFSET, kind);
+        self.write_dma(HEADER_OFFSET + 4, 0u32);
+        self.write_dma(HEADER_OFFSET + 8, sector);
+        self.write_dma(STATUS_OFFSET, 0xffu8);
+
+        let data_flags = if kind == REQUEST_IN { DESC_F_WRITE } else { 0 };
+        self.write_desc(0, HEADER_OFFSET, 16, DESC_F_NEXT, 1);
+        self.write_desc(
+            1,
+            DATA_OFFSET,
+            BLOCK_SIZE as u32,
+            data_flags | DESC_F_NEXT,
+            2,
+        );
+        self.write_desc(2, STATUS_OFFSET, 1, DESC_F_WRITE, 0);
+
+        // Offer the chain in the next slot of the available ring
+        let avail_idx = self.read_dma::<u16>(AVAIL_OFFSET + 2);
+        let slot = usize::from(avail_idx % QUEUE_SIZE);
+        self.write_dma(AVAIL_OFFSET + 4 + 2 * slot, 0u16);
+        fence(Ordering::SeqCst);
+        self.write_dma(AVAIL_OFFSET + 2, avail_idx.wrapping_add(1));
+        fence(Ordering::SeqCst);
+        self.write_reg(REG_QUEUE_NOTIFY, 0);
+
+        while self.read_dma::<u16>(USED_OFFSET + 2) == self.last_used {
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let interrupts = self.read_reg(REG_INTERRUPT_STATUS);
+        self.write_reg(REG_INTERRUPT_ACK, interrupts);
+
+        match self.read_dma::<u8>(STATUS_OFFSET) {
+            0 => Ok(()),
+            _ => Err(IoError),
+        }
+    }
+
+    unsafe fn write_desc(&mut self, i: usize, offset: usize, len: u32, flags: u16, next: u16) {
+        let desc = DESC_OFFSET + 16 * i;
+        self.write_dma(desc, (DMA_PADDR + offset) as u64);
+        self.write_dma(desc + 8, len);
+        self.write_dma(desc + 12, flags);
+        self.write_dma(desc + 14, next);
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        ptr::read_volatile(self.regs.as_ptr().add(offset).cast())
+    }
+
+    unsafe fn write_reg(&mut self, offset: usize, value: u32) {
+        ptr::write_volatile(self.regs.as_ptr().add(offset).cast(), value)
+    }
+
+    unsafe fn write_addr(&mut self, offset: usize, paddr: usize) {
+        let paddr = paddr as u64;
+        self.write_reg(offset, paddr as u32);
+        self.write_reg(offset + 4, (paddr >> 32) as u32);
+    }
+
+    unsafe fn read_dma<T: Copy>(&self, offset: usize) -> T {
+        ptr::read_volatile(self.dma.as_ptr().add(offset).cast())
+    }
+
+    unsafe fn write_dma<T: Copy>(&mut self, offset: usize, value: T) {
+        ptr::write_volatile(self.dma.as_ptr().add(offset).cast(), value)
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn block_count(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read(&mut self, index: u64, block: &mut Block) -> Result<(), IoError> {
+        unsafe {
+            self.request(REQUEST_IN, index)?;
+            ptr::copy_nonoverlapping(
+                self.dma.as_ptr().add(DATA_OFFSET),
+                block.as_mut_ptr(),
+                BLOCK_SIZE,
+            );
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, index: u64, block: &Block) -> Result<(), IoError> {
+        unsafe {
+            ptr::copy_nonoverlapping(
+                block.as_ptr(),
+                self.dma.as_ptr().add(DATA_OFFSET),
+                BLOCK_SIZE,
+            );
+            self.request(REQUEST_OUT, index)
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! For protection domains calling the timer, which must have a channel to it
+//! and a lower priority.
+//!
+//! A client's timeout is reported by a notification on that channel, which
+//! its handler's `notified` must expect once a timeout has been set.
+
+#![no_std]
+
+use core::time::Duration;
+
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use sel4cp::Channel;
+
+use banscii_timer_interface_types::*;
+
+/// The timer misunderstood a request, or answered with something we can't
+/// make sense of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerError;
+
+/// A reading of the timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Now {
+    /// Since the timer started, and so only good for measuring intervals.
+    pub since_start: Duration,
+    /// Seconds since the Unix epoch.
+    pub unix_seconds: u64,
+}
+
+pub struct Timer {
+    channel: Channel,
+}
+
+impl Timer {
+    /// `channel` is the client's end of its channel to the timer.
+    pub const fn new(channel: Channel) -> Self {
+        Self { channel }
+    }
+
+    pub fn now(&self) -> Result<Now, TimerError> {
+        let msg_info = self
+            .channel
+            .pp_call(MessageInfo::send(RequestTag::Now, NoMessageValue));
+        check_status(&msg_info)?;
+        let msg = msg_info.recv::<NowResponse>().map_err(|_| TimerError)?;
+        Ok(Now {
+            since_start: Duration::from_nanos(msg.nanos),
+            unix_seconds: msg.unix_seconds,
+        })
+    }
+
+    /// Replaces any timeout with one expiring once, `after` from now.
+    pub fn set_timeout(&self, after: Duration) -> Result<(), TimerError> {
+        self.set(after, 0)
+    }
+
+    /// Replaces any timeout with one expiring `after` from now, and then
+    /// every `period`, which must be at least a millisecond.
+    pub fn set_periodic(&self, after: Duration, period: Duration) -> Result<(), TimerError> {
+        match nanos(period) {
+            0 => Err(TimerError),
+            period => self.set(after, period),
+        }
+    }
+
+    pub fn cancel_timeout(&self) -> Result<(), TimerError> {
+        let msg_info = self
+            .channel
+            .pp_call(MessageInfo::send(RequestTag::CancelTimeout, NoMessageValue));
+        check_status(&msg_info)
+    }
+
+    fn set(&self, after: Duration, period: u64) -> Result<(), TimerError> {
+        let msg_info = self.channel.pp_call(MessageInfo::send(
+            RequestTag::SetTimeout,
+            SetTimeoutRequest {
+                after: nanos(after),
+                period,
+            },
+        ));
+        check_status(&msg_info)
+    }
+}
+
+// Saturating, since a timeout hundreds of years off may as well be never
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+fn check_status(msg_info: &MessageInfo) -> Result<(), TimerError> {
+    match msg_info.label().try_into() {
+        Ok(StatusMessageLabel::Ok) => Ok(()),
+        _ => Err(TimerError),
+    }
+}
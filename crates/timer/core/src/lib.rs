@@ -0,0 +1,161 @@
+//! The parts of the timer protection domain which don't touch the hardware,
+//! shared with its stand-in in `banscii-sim`.
+
+#![no_std]
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Converts between ticks of a counter running at `frequency` Hz and
+/// nanoseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Counter {
+    frequency: u64,
+}
+
+impl Counter {
+    pub const fn new(frequency: u64) -> Self {
+        Self { frequency }
+    }
+
+    pub fn to_nanos(&self, ticks: u64) -> u64 {
+        (u128::from(ticks) * NANOS_PER_SEC / u128::from(self.frequency))
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Rounded up, so that a deadline converted to ticks is never early.
+    pub fn to_ticks(&self, nanos: u64) -> u64 {
+        ((u128::from(nanos) * u128::from(self.frequency) + NANOS_PER_SEC - 1) / NANOS_PER_SEC)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Timeout {
+    deadline: u64,
+    // Zero for a timeout which expires once
+    period: u64,
+}
+
+/// One timeout for each of `N` clients, in nanoseconds.
+pub struct Timeouts<const N: usize> {
+    timeouts: [Option<Timeout>; N],
+}
+
+impl<const N: usize> Timeouts<N> {
+    pub const fn new() -> Self {
+        Self {
+            timeouts: [None; N],
+        }
+    }
+
+    /// Replaces `client`'s timeout.
+    pub fn set(&mut self, client: usize, now: u64, after: u64, period: u64) {
+        self.timeouts[client] = Some(Timeout {
+            deadline: now.saturating_add(after),
+            period,
+        });
+    }
+
+    pub fn cancel(&mut self, client: usize) {
+        self.timeouts[client] = None;
+    }
+
+    /// When the hardware should next interrupt, if at all.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.timeouts.iter().flatten().map(|t| t.deadline).min()
+    }
+
+    /// Calls `f` with each client whose timeout has expired by `now`.
+    /// Periodic timeouts are set to expire again, skipping any periods missed
+    /// entirely, so that a client which falls behind isn't notified in a
+    /// burst.
+    pub fn expire(&mut self, now: u64, mut f: impl FnMut(usize)) {
+        for (client, slot) in self.timeouts.iter_mut().enumerate() {
+            let Some(timeout) = slot else {
+                continue;
+            };
+            if timeout.deadline > now {
+                continue;
+            }
+            f(client);
+            if timeout.period == 0 {
+                *slot = None;
+            } else {
+                let missed = (now - timeout.deadline) / timeout.period;
+                timeout.deadline = timeout
+                    .deadline
+                    .saturating_add((missed + 1).saturating_mul(timeout.period));
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for Timeouts<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The clients expired, as a bit for each
+    fn expire<const N: usize>(timeouts: &mut Timeouts<N>, now: u64) -> u32 {
+        let mut expired = 0;
+        timeouts.expire(now, |client| expired |= 1 << client);
+        expired
+    }
+
+    #[test]
+    fn counter() {
+        // QEMU's virt machine
+        let counter = Counter::new(62_500_000);
+        assert_eq!(counter.to_nanos(62_500_000), 1_000_000_000);
+        assert_eq!(counter.to_nanos(1), 16);
+        assert_eq!(counter.to_ticks(16), 1);
+        assert_eq!(counter.to_ticks(17), 2);
+        assert_eq!(counter.to_nanos(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn one_shot() {
+        let mut timeouts = Timeouts::<2>::new();
+        assert_eq!(timeouts.next_deadline(), None);
+        timeouts.set(1, 100, 50, 0);
+        assert_eq!(timeouts.next_deadline(), Some(150));
+        assert_eq!(expire(&mut timeouts, 149), 0);
+        assert_eq!(expire(&mut timeouts, 150), 0b10);
+        assert_eq!(timeouts.next_deadline(), None);
+        assert_eq!(expire(&mut timeouts, 1000), 0);
+    }
+
+    #[test]
+    fn periodic() {
+        let mut timeouts = Timeouts::<2>::new();
+        timeouts.set(0, 0, 10, 10);
+        timeouts.set(1, 0, 25, 0);
+        assert_eq!(expire(&mut timeouts, 10), 0b01);
+        assert_eq!(timeouts.next_deadline(), Some(20));
+        assert_eq!(expire(&mut timeouts, 26), 0b11);
+        assert_eq!(timeouts.next_deadline(), Some(30));
+
+        // Late, by more than a period
+        assert_eq!(expire(&mut timeouts, 75), 0b01);
+        assert_eq!(timeouts.next_deadline(), Some(80));
+
+        timeouts.cancel(0);
+        assert_eq!(timeouts.next_deadline(), None);
+    }
+
+    #[test]
+    fn replaced() {
+        let mut timeouts = Timeouts::<1>::new();
+        timeouts.set(0, 0, 10, 10);
+        timeouts.set(0, 5, 100, 0);
+        assert_eq!(expire(&mut timeouts, 50), 0);
+        assert_eq!(timeouts.next_deadline(), Some(105));
+    }
+}
@@ -0,0 +1,39 @@
+#![no_std]
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use zerocopy::{AsBytes, FromBytes};
+
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// The shortest period the timer repeats a timeout at, so that no client can
+/// keep it handling interrupts and nothing else.
+pub const MIN_PERIOD: u64 = 1_000_000;
+
+/// Each client has one timeout at a time, and is notified on its channel to
+/// the timer when it expires.
+#[derive(Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[cfg_attr(target_pointer_width = "32", repr(u32))]
+#[cfg_attr(target_pointer_width = "64", repr(u64))]
+pub enum RequestTag {
+    Now,
+    SetTimeout,
+    CancelTimeout,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct NowResponse {
+    /// Nanoseconds since the timer started, which never go backwards.
+    pub nanos: u64,
+    /// Seconds since the Unix epoch, by the real-time clock.
+    pub unix_seconds: u64,
+}
+
+/// Replaces the client's timeout with one expiring `after` nanoseconds from
+/// now, and then every `period` nanoseconds, or just once for zero.
+#[derive(Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct SetTimeoutRequest {
+    pub after: u64,
+    pub period: u64,
+}
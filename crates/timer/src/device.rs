@@ -0,0 +1,82 @@
+use core::arch::asm;
+use core::ops::Deref;
+
+use tock_registers::interfaces::Readable;
+use tock_registers::register_structs;
+use tock_registers::registers::ReadOnly;
+
+/// The EL1 physical timer of the ARM generic timer, through the system
+/// registers which seL4 lets user level use when built with
+/// `KernelArmExportPCNTUser` and `KernelArmExportPTMRUser`. The kernel keeps
+/// the virtual timer for itself. Nothing checks the SDK's kernel config at
+/// build time, but `make test-e2e` fails on one built without them.
+pub struct GenericTimer;
+
+impl GenericTimer {
+    pub fn frequency(&self) -> u64 {
+        let frequency: u64;
+        unsafe { asm!("mrs {}, cntfrq_el0", out(reg) frequency) };
+        frequency
+    }
+
+    pub fn count(&self) -> u64 {
+        let count: u64;
+        // Without the barrier, the read may happen before earlier
+        // instructions have completed
+        unsafe { asm!("isb", "mrs {}, cntpct_el0", out(reg) count) };
+        count
+    }
+
+    /// Interrupts once the count reaches `count`, or at once if it already
+    /// has, until disabled or set again.
+    pub fn set_compare(&self, count: u64) {
+        unsafe {
+            asm!("msr cntp_cval_el0, {}", in(reg) count);
+            asm!("msr cntp_ctl_el0, {}", in(reg) CTL_ENABLE);
+        }
+    }
+
+    pub fn disable(&self) {
+        unsafe { asm!("msr cntp_ctl_el0, {}", in(reg) 0u64) };
+    }
+}
+
+// Enabled, and with its interrupt not masked
+const CTL_ENABLE: u64 = 1;
+
+register_structs! {
+    #[allow(non_snake_case)]
+    pub Pl031RegisterBlock {
+        (0x000 => DR: ReadOnly<u32>),
+        (0x004 => @END),
+    }
+}
+
+/// QEMU's real-time clock, which counts seconds from the host's time at
+/// startup.
+pub struct Pl031Device {
+    ptr: *const Pl031RegisterBlock,
+}
+
+impl Pl031Device {
+    pub unsafe fn new(ptr: *const Pl031RegisterBlock) -> Self {
+        Self { ptr }
+    }
+
+    fn ptr(&self) -> *const Pl031RegisterBlock {
+        self.ptr
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn seconds(&self) -> u64 {
+        self.DR.get().into()
+    }
+}
+
+impl Deref for Pl031Device {
+    type Target = Pl031RegisterBlock;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.ptr() }
+    }
+}
@@ -0,0 +1,117 @@
+#![no_std]
+#![no_main]
+#![feature(never_type)]
+
+use sel4cp::message::{MessageInfo, NoMessageValue, StatusMessageLabel};
+use sel4cp::{memory_region_symbol, protection_domain, Channel, Handler};
+
+use banscii_timer_core::{Counter, Timeouts};
+use banscii_timer_interface_types::*;
+
+mod device;
+
+use device::{GenericTimer, Pl031Device, Pl031RegisterBlock};
+
+const DEVICE: Channel = Channel::new(0);
+const ARTIST: Channel = Channel::new(1);
+const ASSISTANT: Channel = Channel::new(2);
+
+// Indexed by client, as in `Timeouts`
+const CLIENTS: [Channel; 2] = [ARTIST, ASSISTANT];
+
+#[protection_domain]
+fn init() -> ThisHandler {
+    let rtc = unsafe {
+        Pl031Device::new(
+            memory_region_symbol!(pl031_register_block: *mut Pl031RegisterBlock).as_ptr(),
+        )
+    };
+    let timer = GenericTimer;
+    timer.disable();
+    ThisHandler {
+        counter: Counter::new(timer.frequency()),
+        start: timer.count(),
+        timer,
+        rtc,
+        timeouts: Timeouts::new(),
+    }
+}
+
+struct ThisHandler {
+    timer: GenericTimer,
+    counter: Counter,
+    // The count when we started, from which nanoseconds are measured
+    start: u64,
+    rtc: Pl031Device,
+    timeouts: Timeouts<{ CLIENTS.len() }>,
+}
+
+impl Handler for ThisHandler {
+    type Error = !;
+
+    fn notified(&mut self, channel: Channel) -> Result<(), Self::Error> {
+        match channel {
+            DEVICE => {
+                self.timeouts
+                    .expire(self.now(), |client| CLIENTS[client].notify());
+                // Disabling or setting the compare value again clears the
+                // interrupt, which is level-triggered
+                self.arm();
+                DEVICE.irq_ack().unwrap();
+            }
+            _ => {
+                unreachable!()
+            }
+        }
+        Ok(())
+    }
+
+    fn protected(
+        &mut self,
+        channel: Channel,
+        msg_info: MessageInfo,
+    ) -> Result<MessageInfo, Self::Error> {
+        let Some(client) = CLIENTS.iter().position(|c| *c == channel) else {
+            unreachable!()
+        };
+        Ok(match msg_info.label().try_into().ok() {
+            Some(RequestTag::Now) => MessageInfo::send(
+                StatusMessageLabel::Ok,
+                NowResponse {
+                    nanos: self.now(),
+                    unix_seconds: self.rtc.seconds(),
+                },
+            ),
+            Some(RequestTag::SetTimeout) => match msg_info.recv::<SetTimeoutRequest>() {
+                Ok(req) if req.period == 0 || req.period >= MIN_PERIOD => {
+                    self.timeouts.set(client, self.now(), req.after, req.period);
+                    self.arm();
+                    MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue)
+                }
+                _ => MessageInfo::send(StatusMessageLabel::Error, NoMessageValue),
+            },
+            Some(RequestTag::CancelTimeout) => {
+                self.timeouts.cancel(client);
+                self.arm();
+                MessageInfo::send(StatusMessageLabel::Ok, NoMessageValue)
+            }
+            None => MessageInfo::send(StatusMessageLabel::Error, NoMessageValue),
+        })
+    }
+}
+
+impl ThisHandler {
+    fn now(&self) -> u64 {
+        self.counter.to_nanos(self.timer.count() - self.start)
+    }
+
+    // Sets the timer for the next timeout to expire
+    fn arm(&self) {
+        match self.timeouts.next_deadline() {
+            Some(deadline) => self
+                .timer
+                .set_compare(self.start.saturating_add(self.counter.to_ticks(deadline))),
+            None => self.timer.disable(),
+        }
+    }
+}
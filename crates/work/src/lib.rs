@@ -0,0 +1,111 @@
+//! Works as the assistant prints them at its console, for checking what it
+//! prints against the artist's public key and audit log.
+
+use banscii_artist_interface_types::{provenance, AuditEntry, Chain, ChainError, ChainHash};
+
+/// A masterpiece and its signature, as printed by the assistant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Work {
+    pub masterpiece: Vec<String>,
+    pub serial: usize,
+    pub edition: usize,
+    /// Seconds since the Unix epoch when the artist signed the work.
+    pub timestamp: u64,
+    /// The head of the artist's audit log before this work.
+    pub chain_head: ChainHash,
+    pub signature: Vec<u8>,
+}
+
+impl Work {
+    /// Finds the last work in some console output.
+    pub fn parse(output: &str) -> Option<Self> {
+        let lines = output.lines().collect::<Vec<_>>();
+        let signature_line = lines.iter().rposition(|line| *line == "Signature:")?;
+
+        // Headers such as "Serial: 3" sit between the masterpiece and its
+        // signature
+        let masterpiece_end = lines[..signature_line]
+            .iter()
+            .rposition(|line| line.is_empty())?;
+        let mut serial = None;
+        let mut edition = None;
+        let mut timestamp = None;
+        let mut chain_head = None;
+        for line in &lines[masterpiece_end + 1..signature_line] {
+            let (key, value) = line.split_once(": ")?;
+            // "Edition: 2 of 10"
+            let number = || value.split(' ').next()?.parse().ok();
+            match key {
+                "Serial" => serial = Some(number()?),
+                "Edition" => edition = Some(number()?),
+                // "Signed: 2023-11-14 22:13:20 UTC (1700000000)"
+                "Signed" => {
+                    let (_, seconds) = value.strip_suffix(')')?.rsplit_once('(')?;
+                    timestamp = Some(seconds.parse().ok()?);
+                }
+                "Chain" => chain_head = Some(hex::decode(value).ok()?.try_into().ok()?),
+                _ => {}
+            }
+        }
+
+        let masterpiece_start = lines[..masterpiece_end]
+            .iter()
+            .rposition(|line| line.is_empty())?
+            + 1;
+        let masterpiece = lines[masterpiece_start..masterpiece_end]
+            .iter()
+            .map(|line| (*line).to_owned())
+            .collect();
+
+        let signature = lines[signature_line + 1..]
+            .iter()
+            .take_while(|line| !line.is_empty())
+            .map(hex::decode)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?
+            .concat();
+
+        Some(Self {
+            masterpiece,
+            serial: serial?,
+            edition: edition?,
+            timestamp: timestamp?,
+            chain_head: chain_head?,
+            signature,
+        })
+    }
+
+    /// The masterpiece's rows run together.
+    pub fn pixel_data(&self) -> Vec<u8> {
+        self.masterpiece.concat().into_bytes()
+    }
+
+    /// The bytes that the artist signed.
+    pub fn signed_data(&self) -> Vec<u8> {
+        let provenance = provenance(self.serial, self.edition, self.timestamp, &self.chain_head);
+        [&self.pixel_data()[..], &provenance].concat()
+    }
+
+    /// The artist's audit log entry for this work.
+    pub fn audit_entry(&self) -> AuditEntry {
+        AuditEntry::new(self.serial, &self.signed_data(), self.chain_head)
+    }
+}
+
+/// Checks that works, in the order they were completed, follow on from one
+/// another in the artist's audit log, with none missing in between.
+pub fn check_chain(works: &[Work]) -> Result<(), ChainError> {
+    let Some(first) = works.first() else {
+        return Ok(());
+    };
+    let mut chain = Chain {
+        serial: first.serial.checked_sub(1).ok_or(ChainError::Gap {
+            after: 0,
+            next: first.serial,
+        })?,
+        head: first.chain_head,
+    };
+    works
+        .iter()
+        .try_for_each(|work| chain.extend(&work.audit_entry()))
+}